@@ -39,8 +39,19 @@ fn add_os_deps() {
 #[cfg(all(unix, not(target_vendor = "apple")))]
 fn add_cmake_overrides(config: &mut cmake::Config) {}
 
+// Declares everything exported with #[crt_export] in $OUT_DIR/include/crtrs.h, and a header per
+// module in $OUT_DIR/include/crtrs/
+fn generate_headers(out_dir: &str) {
+    let include_dir = std::path::Path::new(out_dir).join("include");
+    let config = crt_bindgen::Config::new("crtrs", std::path::Path::new("src/lib.rs"), &include_dir)
+        .generators("c");
+    crt_bindgen::generate_with(&config, &crt_bindgen::plugin::Registry::default())
+        .expect("Failed to generate headers");
+}
+
 fn main() {
     let out_dir = std::env::var("OUT_DIR").unwrap();
+    generate_headers(&out_dir);
     let mut config = cmake::Config::new("crt/aws-crt-ffi");
     config.profile("RelWithDebInfo")
         //.define("CMAKE_PREFIX_PATH", "build/install")
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde_json::Value;
+
+use crate::manifest::{describe, MANIFEST_VERSION};
+use crate::scan::Crate;
+
+/// A difference between the ABI of a baseline manifest and the current one. Breaking changes
+/// are those which break C callers built against the baseline, e.g. a function's params
+/// changing, and additive ones are those which don't, e.g. a new function.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub breaking: bool,
+    /// What changed, e.g. "AwsCredentials_new was removed"
+    pub message: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.breaking {
+            true => write!(f, "breaking: {}", self.message),
+            false => write!(f, "additive: {}", self.message),
+        }
+    }
+}
+
+/// Compares the crate rooted at lib_rs with the manifest at baseline, as written by the json
+/// generator when the baseline was released
+pub fn check(baseline: &Path, crate_name: &str, lib_rs: &Path) -> io::Result<Vec<Change>> {
+    let baseline: Value = serde_json::from_str(&fs::read_to_string(baseline)?)?;
+    let current = describe(&Crate::scan(crate_name, lib_rs)?);
+    compare(&baseline, &current)
+}
+
+/// The changes from the baseline manifest to the current one. Items are matched by their
+/// exported symbol, so moving them between modules, renaming args and changing docs don't count.
+pub fn compare(baseline: &Value, current: &Value) -> io::Result<Vec<Change>> {
+    if baseline["manifest_version"] != current["manifest_version"] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+            "The baseline is manifest version {}, but the current manifest is version {}, so the baseline must be regenerated",
+            baseline["manifest_version"], MANIFEST_VERSION)));
+    }
+    let mut changes = Changes { changes: vec![] };
+    changes.compare_items(baseline, current, "functions", "symbol", Changes::compare_function);
+    changes.compare_items(baseline, current, "structs", "name", Changes::compare_struct);
+    changes.compare_items(baseline, current, "enums", "name", Changes::compare_enum);
+    changes.compare_items(baseline, current, "constants", "symbol", Changes::compare_constant);
+    Ok(changes.changes)
+}
+
+struct Changes {
+    changes: Vec<Change>,
+}
+
+impl Changes {
+    fn breaking(&mut self, message: String) {
+        self.changes.push(Change { breaking: true, message });
+    }
+
+    fn additive(&mut self, message: String) {
+        self.changes.push(Change { breaking: false, message });
+    }
+
+    // Items which were removed, then changed, then added, each in the order of their keys
+    fn compare_items(&mut self, baseline: &Value, current: &Value, section: &str, key: &str, compare: fn(&mut Changes, &str, &Value, &Value)) {
+        let old = items(baseline, section, key);
+        let new = items(current, section, key);
+        for name in old.keys().filter(|name| !new.contains_key(*name)) {
+            self.breaking(format!("{} was removed", name));
+        }
+        for (name, old_item) in old.iter() {
+            if let Some(new_item) = new.get(name) {
+                compare(self, name, old_item, new_item);
+            }
+        }
+        for name in new.keys().filter(|name| !old.contains_key(*name)) {
+            self.additive(format!("{} was added", name));
+        }
+    }
+
+    fn compare_function(&mut self, symbol: &str, old: &Value, new: &Value) {
+        let old_params = c_params(old);
+        let new_params = c_params(new);
+        if old_params != new_params {
+            self.breaking(format!("{} params changed from ({}) to ({})", symbol, old_params.join(", "), new_params.join(", ")));
+        } else {
+            let args = old["args"].as_array().into_iter().flatten().zip(new["args"].as_array().into_iter().flatten());
+            for (old_arg, new_arg) in args {
+                let name = str_of(&new_arg["name"]);
+                if old_arg["ownership"] != new_arg["ownership"] {
+                    self.breaking(format!("{} {} changed from {} to {}", symbol, name, str_of(&old_arg["ownership"]), str_of(&new_arg["ownership"])));
+                }
+                // Callers which never pass NULL are unaffected by an arg becoming nullable
+                match (old_arg["nullable"].as_bool(), new_arg["nullable"].as_bool()) {
+                    (Some(true), Some(false)) => self.breaking(format!("{} {} no longer accepts NULL", symbol, name)),
+                    (Some(false), Some(true)) => self.additive(format!("{} {} now accepts NULL", symbol, name)),
+                    _ => (),
+                }
+                if old_arg["release"] != new_arg["release"] {
+                    self.breaking(format!("{} {} must now be released with {}", symbol, name, str_of(&new_arg["release"])));
+                }
+            }
+        }
+        let (old_returns, new_returns) = (&old["returns"], &new["returns"]);
+        if old_returns["c_type"] != new_returns["c_type"] {
+            self.breaking(format!("{} return type changed from {} to {}", symbol, str_of(&old_returns["c_type"]), str_of(&new_returns["c_type"])));
+        } else if old_returns["ownership"] != new_returns["ownership"] {
+            self.breaking(format!("{} return value changed from {} to {}", symbol, str_of(&old_returns["ownership"]), str_of(&new_returns["ownership"])));
+        } else if old_returns["release"] != new_returns["release"] {
+            self.breaking(format!("{} return value must now be released with {}", symbol, str_of(&new_returns["release"])));
+        }
+        if old_returns["nullable"] == Value::Bool(false) && new_returns["nullable"] == Value::Bool(true) {
+            self.breaking(format!("{} may now return NULL", symbol));
+        }
+    }
+
+    // Only structs which C uses by value have a layout, the rest are only ever handles
+    fn compare_struct(&mut self, name: &str, old: &Value, new: &Value) {
+        match (old["kind"] == "value", new["kind"] == "value") {
+            (true, true) => {
+                let old_fields = c_fields(old);
+                let new_fields = c_fields(new);
+                if old_fields != new_fields {
+                    self.breaking(format!("{} layout changed from {{{}}} to {{{}}}", name, old_fields.join("; "), new_fields.join("; ")));
+                }
+            },
+            (true, false) => self.breaking(format!("{} can no longer be used by value", name)),
+            (false, true) => self.additive(format!("{} can now be used by value", name)),
+            (false, false) => (),
+        }
+    }
+
+    fn compare_enum(&mut self, name: &str, old: &Value, new: &Value) {
+        if old["c_type"] != new["c_type"] {
+            self.breaking(format!("{} changed from {} to {}", name, str_of(&old["c_type"]), str_of(&new["c_type"])));
+        }
+        let old_variants = variants(old);
+        let new_variants = variants(new);
+        for (symbol, old_value) in old_variants.iter() {
+            match new_variants.get(symbol) {
+                None => self.breaking(format!("{} was removed", symbol)),
+                Some(new_value) if new_value != old_value => {
+                    self.breaking(format!("{} changed from {} to {}", symbol, old_value, new_value));
+                },
+                _ => (),
+            }
+        }
+        for symbol in new_variants.keys().filter(|symbol| !old_variants.contains_key(*symbol)) {
+            self.additive(format!("{} was added", symbol));
+        }
+    }
+
+    // Const values are compiled into callers, so changing them is breaking too
+    fn compare_constant(&mut self, symbol: &str, old: &Value, new: &Value) {
+        if old["kind"] != new["kind"] {
+            self.breaking(format!("{} changed from a {} to a {}", symbol, str_of(&old["kind"]), str_of(&new["kind"])));
+        } else if old["c_type"] != new["c_type"] {
+            self.breaking(format!("{} changed from {} to {}", symbol, str_of(&old["c_type"]), str_of(&new["c_type"])));
+        } else if old["value"] != new["value"] {
+            self.breaking(format!("{} changed from {} to {}", symbol, str_of(&old["value"]), str_of(&new["value"])));
+        }
+    }
+}
+
+// The items in the section of every module, by the key which identifies them
+fn items<'a>(manifest: &'a Value, section: &str, key: &str) -> BTreeMap<String, &'a Value> {
+    manifest["modules"].as_array().into_iter().flatten()
+        .flat_map(|module| module[section].as_array().into_iter().flatten())
+        .map(|item| (str_of(&item[key]), item))
+        .collect()
+}
+
+fn c_params(function: &Value) -> Vec<String> {
+    function["args"].as_array().into_iter().flatten()
+        .flat_map(|arg| arg["c_params"].as_array().into_iter().flatten())
+        .map(|param| str_of(&param["c_type"]))
+        .collect()
+}
+
+fn c_fields(struct_item: &Value) -> Vec<String> {
+    struct_item["fields"].as_array().into_iter().flatten()
+        .map(|field| format!("{} {}", str_of(&field["c_type"]), str_of(&field["name"])))
+        .collect()
+}
+
+fn variants(enum_item: &Value) -> BTreeMap<String, String> {
+    enum_item["variants"].as_array().into_iter().flatten()
+        .map(|variant| (str_of(&variant["symbol"]), str_of(&variant["value"])))
+        .collect()
+}
+
+fn str_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::tests::write_crate;
+
+    fn manifest(name: &str, lib_rs: &str) -> Value {
+        let root = write_crate(name, &[("src/lib.rs", lib_rs)]);
+        let manifest = describe(&Crate::scan("crtrs", &root.join("src/lib.rs")).unwrap());
+        fs::remove_dir_all(root).unwrap();
+        manifest
+    }
+
+    fn messages(changes: &[Change]) -> Vec<String> {
+        changes.iter().map(Change::to_string).collect()
+    }
+
+    const BASELINE: &str = "
+        #[crt_export(refcounted)] pub struct AwsCredentials {}
+        #[crt_export] impl AwsCredentials {
+            pub fn new(access_key_id: &str, session_token: Option<&str>, expiration: u64) -> Result<AwsCredentials, Error> {}
+            pub fn get_access_key_id(&self) -> CByteCursor {}
+        }
+        #[crt_export] pub struct Point { x: i32, y: i32 }
+        #[crt_export] #[repr(u8)] pub enum Shape { Circle = 1, Square = 2 }
+        #[crt_export(prefix = \"CRTRS_\")] pub const VERSION_MAJOR: u32 = 0;";
+
+    #[test]
+    fn compatible_changes() {
+        let baseline = manifest("compatible_baseline", BASELINE);
+        assert_eq!(Vec::<Change>::new(), compare(&baseline, &baseline).unwrap());
+
+        let current = manifest("compatible_current", "
+            /// Docs and arg names aren't part of the ABI
+            #[crt_export(refcounted)] pub struct AwsCredentials {}
+            #[crt_export] impl AwsCredentials {
+                pub fn new(key: &str, token: Option<&str>, expiration: u64) -> Result<AwsCredentials, Error> {}
+                pub fn get_access_key_id(&self) -> CByteCursor {}
+                pub fn get_session_token(&self) -> CByteCursor {}
+            }
+            #[crt_export] pub struct Point { x: i32, y: i32 }
+            #[crt_export] #[repr(u8)] pub enum Shape { Circle = 1, Square = 2, Triangle = 3 }
+            #[crt_export(prefix = \"CRTRS_\")] pub const VERSION_MAJOR: u32 = 0;");
+        assert_eq!(vec![
+            "additive: AwsCredentials_get_session_token was added",
+            "additive: Shape_Triangle was added",
+        ], messages(&compare(&baseline, &current).unwrap()));
+    }
+
+    #[test]
+    fn breaking_changes() {
+        let baseline = manifest("breaking_baseline", BASELINE);
+        let current = manifest("breaking_current", "
+            #[crt_export] pub struct AwsCredentials {}
+            #[crt_export] impl AwsCredentials {
+                pub fn new(access_key_id: &str, session_token: &str, expiration: u32) -> Result<AwsCredentials, Error> {}
+            }
+            #[crt_export] pub struct Point { x: i64, y: i32 }
+            #[crt_export] #[repr(u8)] pub enum Shape { Square = 1 }
+            #[crt_export(prefix = \"CRTRS_\")] pub const VERSION_MAJOR: u32 = 1;");
+        assert_eq!(vec![
+            "breaking: AwsCredentials_acquire was removed",
+            "breaking: AwsCredentials_get_access_key_id was removed",
+            "breaking: AwsCredentials_release was removed",
+            "breaking: AwsCredentials_new params changed from (const char*, const char*, uint64_t, AwsCredentials**) to (const char*, const char*, uint32_t, AwsCredentials**)",
+            "additive: AwsCredentials_destroy was added",
+            "breaking: Point layout changed from {int32_t x; int32_t y} to {int64_t x; int32_t y}",
+            "breaking: Shape_Circle was removed",
+            "breaking: Shape_Square changed from 2 to 1",
+            "breaking: CRTRS_VERSION_MAJOR changed from 0 to 1",
+        ], messages(&compare(&baseline, &current).unwrap()));
+    }
+
+    #[test]
+    fn ownership_changes() {
+        let baseline = manifest("ownership_baseline", "
+            #[crt_export] pub struct Signer {}
+            #[crt_export] impl Signer {
+                pub fn new(name: Option<&str>) -> Signer {}
+                pub fn sign(&self, request: &mut Request) -> Box<Request> {}
+            }");
+        let current = manifest("ownership_current", "
+            #[crt_export] pub struct Signer {}
+            #[crt_export] impl Signer {
+                pub fn new(name: &str) -> Signer {}
+                pub fn sign(&self, request: Box<Request>) -> &mut Request {}
+            }");
+        assert_eq!(vec![
+            "breaking: Signer_new name no longer accepts NULL",
+            "breaking: Signer_sign request changed from borrowed to transferred",
+            "breaking: Signer_sign return value changed from owned to borrowed",
+        ], messages(&compare(&baseline, &current).unwrap()));
+    }
+
+    #[test]
+    fn checks_manifest_version() {
+        let baseline = serde_json::json!({ "crate": "crtrs", "manifest_version": 0, "modules": [] });
+        let current = manifest("checks_manifest_version", "");
+        assert_eq!(io::ErrorKind::InvalidData, compare(&baseline, &current).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,172 @@
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+use syn::{Attribute, AttributeArgs, Ident, Lit, Meta, NestedMeta};
+
+/// The arguments to #[crt_export(...)], on a struct, enum, impl or a method within an impl
+pub struct ExportArgs {
+    /// Generate {Struct}_new and {Struct}_set/get_<field> so C can treat the struct as opaque
+    pub builder: bool,
+    /// Back handles to the struct with an Arc, and generate {Struct}_acquire/_release rather
+    /// than {Struct}_destroy
+    pub refcounted: bool,
+    /// Don't export this method, e.g. for helpers which are only used from Rust
+    pub skip: bool,
+    /// Prepended to all exported symbols for the struct/enum/impl, e.g. aws_crtrs_
+    pub prefix: Option<String>,
+    /// On a struct/enum/impl, replaces the type's name in exported symbols. On a method,
+    /// replaces the whole exported symbol.
+    pub name: Option<String>,
+    /// How the type's name is written in exported symbols
+    pub style: Option<NamingStyle>,
+    // Where each arg was given, for reporting args which don't apply to the item
+    spans: Vec<(&'static str, Span)>,
+}
+
+impl ExportArgs {
+    pub fn new(args: AttributeArgs) -> Result<ExportArgs, syn::Error> {
+        let mut export_args = ExportArgs {
+            builder: false,
+            refcounted: false,
+            skip: false,
+            prefix: None,
+            name: None,
+            style: None,
+            spans: vec![],
+        };
+        for arg in args.iter() {
+            let given = match arg {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("builder") => {
+                    export_args.builder = true;
+                    "builder"
+                },
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("refcounted") => {
+                    export_args.refcounted = true;
+                    "refcounted"
+                },
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => {
+                    export_args.skip = true;
+                    "skip"
+                },
+                NestedMeta::Meta(Meta::NameValue(name_value)) => {
+                    let value = match &name_value.lit {
+                        Lit::Str(value) => value.value(),
+                        lit => return Err(syn::Error::new_spanned(lit, "crt_export argument values must be strings, e.g. prefix = \"aws_crtrs_\"")),
+                    };
+                    if name_value.path.is_ident("prefix") {
+                        export_args.prefix = Some(value);
+                        "prefix"
+                    } else if name_value.path.is_ident("name") {
+                        export_args.name = Some(value);
+                        "name"
+                    } else if name_value.path.is_ident("style") {
+                        let style = NamingStyle::new(&value).map_err(|msg| syn::Error::new_spanned(&name_value.lit, msg))?;
+                        export_args.style = Some(style);
+                        "style"
+                    } else {
+                        return Err(syn::Error::new_spanned(&name_value.path, "Unknown crt_export argument, expected prefix, name or style"));
+                    }
+                },
+                _ => return Err(syn::Error::new_spanned(arg, "Unknown crt_export argument, expected builder, refcounted, skip, prefix, name or style")),
+            };
+            export_args.spans.push((given, arg.span()));
+        }
+        Ok(export_args)
+    }
+
+    // Removes any #[crt_export(...)] attributes from a method, returning their args
+    pub fn take(attrs: &mut Vec<Attribute>) -> Result<ExportArgs, syn::Error> {
+        let mut args: AttributeArgs = vec![];
+        let mut result = Ok(());
+        attrs.retain(|attr| {
+            if !attr.path.is_ident("crt_export") {
+                return true;
+            }
+            match attr.parse_meta() {
+                Ok(Meta::Path(_)) => (),
+                Ok(Meta::List(list)) => args.extend(list.nested),
+                Ok(meta) => result = Err(syn::Error::new_spanned(meta, "Invalid crt_export attribute, expected #[crt_export(...)]")),
+                Err(err) => result = Err(err),
+            }
+            false
+        });
+        result?;
+        ExportArgs::new(args)
+    }
+
+    pub fn naming(&self) -> Naming {
+        Naming {
+            prefix: self.prefix.clone().unwrap_or_default(),
+            name: self.name.clone(),
+            style: self.style.unwrap_or(NamingStyle::AsIs),
+        }
+    }
+
+    // Fails if any args other than those allowed on this kind of item were given
+    pub fn allow_only(&self, allowed: &[&str], item: &str) -> Result<(), syn::Error> {
+        match self.spans.iter().find(|(arg, _)| !allowed.contains(arg)) {
+            Some((arg, span)) => Err(syn::Error::new(*span, format!("crt_export({}) is not applicable to {}", arg, item))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How type names are written in exported symbols
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NamingStyle {
+    /// As written in Rust, e.g. AwsCredentials_new
+    AsIs,
+    /// snake_case, e.g. aws_credentials_new
+    SnakeCase,
+}
+
+impl NamingStyle {
+    fn new(style: &str) -> Result<NamingStyle, &'static str> {
+        match style {
+            "as_is" => Ok(NamingStyle::AsIs),
+            "snake_case" => Ok(NamingStyle::SnakeCase),
+            _ => Err("Unknown crt_export style, expected as_is or snake_case"),
+        }
+    }
+}
+
+/// How a struct or enum is named in exported symbols
+#[derive(Clone)]
+pub struct Naming {
+    pub prefix: String,
+    pub name: Option<String>,
+    pub style: NamingStyle,
+}
+
+impl Default for Naming {
+    fn default() -> Naming {
+        ExportArgs::new(vec![]).unwrap().naming()
+    }
+}
+
+impl Naming {
+    pub fn apply(&self, id: &Ident) -> String {
+        let name = match (&self.name, self.style) {
+            (Some(name), _) => name.clone(),
+            (None, NamingStyle::AsIs) => id.to_string(),
+            (None, NamingStyle::SnakeCase) => to_snake_case(&id.to_string()),
+        };
+        format!("{}{}", self.prefix, name)
+    }
+}
+
+// EventLoopGroup -> event_loop_group, CRTError -> crt_error
+fn to_snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            let prev = chars[idx - 1];
+            let next_is_lower = chars.get(idx + 1).is_some_and(|next| next.is_lowercase());
+            if prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use crt_bindgen::manifest::{describe, to_json};
+use crt_bindgen::scan::Crate;
+
+// crtrs-abi-check [--update] <baseline.json> <crate name> <src/lib.rs>, which reports how the
+// crate's exports have changed since the baseline, and fails if any change breaks C callers.
+// --update rewrites the baseline from the crate, e.g. when releasing.
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let update = args.len() > 1 && args[1] == "--update";
+    if update {
+        args.remove(1);
+    }
+    if args.len() != 4 {
+        eprintln!("usage: {} [--update] <baseline.json> <crate name> <src/lib.rs>", args[0]);
+        std::process::exit(2);
+    }
+    let (baseline, crate_name, lib_rs) = (Path::new(&args[1]), &args[2], Path::new(&args[3]));
+    if update {
+        let result = Crate::scan(crate_name, lib_rs)
+            .and_then(|krate| Ok(to_json(&describe(&krate))?))
+            .and_then(|manifest| std::fs::write(baseline, manifest));
+        if let Err(err) = result {
+            eprintln!("Failed to update {}: {}", baseline.display(), err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    match crt_bindgen::abi::check(baseline, crate_name, lib_rs) {
+        Ok(changes) => {
+            changes.iter().for_each(|change| println!("{}", change));
+            if changes.iter().any(|change| change.breaking) {
+                std::process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("Failed to check against {}: {}", baseline.display(), err);
+            std::process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,761 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::docs::Docs;
+use crate::header::{c_name, doxygen, write_if_changed};
+use crate::manifest::{self, free_function_names, is_exported, items, list, str_of, ErrorFunctions};
+use crate::plugin::Plugin;
+use crate::scan::Crate;
+use crate::types::c_declaration;
+
+/// The cpp generator, which writes <output_dir>/<crate_name>.hpp, a header-only C++17 layer over
+/// the c generator's header. Each exported struct is a class owning its handle, which is released
+/// by its destructor. Classes are move-only, unless they are refcounted or Clone, in which case
+/// copies acquire or clone the handle. Strings are taken as std::string_view, byte slices as
+/// ByteView, which converts from std::span and other containers, and failures are thrown as Error.
+#[derive(Default)]
+pub struct CppExporter {
+    errors: ErrorFunctions,
+}
+
+impl CppExporter {
+    pub fn new() -> CppExporter {
+        CppExporter::default()
+    }
+
+    pub fn errors(mut self, errors: ErrorFunctions) -> CppExporter {
+        self.errors = errors;
+        self
+    }
+
+    fn header(&self, crate_name: &str, manifest: &Value) -> String {
+        let types = Types::new(manifest);
+        let functions: Vec<&Value> = items(manifest, "functions").collect();
+        let error_name = Some(&self.errors.error_name).filter(|symbol| is_exported(manifest, symbol));
+        let error_str = Some(&self.errors.error_str).filter(|symbol| is_exported(manifest, symbol));
+        let name_method = match error_name {
+            Some(symbol) => format!("
+    /** The name of the error, e.g. AWS_ERROR_INVALID_ARGUMENT */
+    const char* name() const noexcept {{
+        return ::{}(code_);
+    }}
+", symbol),
+            None => String::new(),
+        };
+        let message = match (error_name, error_str) {
+            (Some(name), Some(description)) => format!("std::string(::{}(code)) + \": \" + ::{}(code)", name, description),
+            (Some(symbol), None) | (None, Some(symbol)) => format!("::{}(code)", symbol),
+            (None, None) => String::from("\"error \" + std::to_string(code)"),
+        };
+        let last_error = match is_exported(manifest, &self.errors.last_error) {
+            true => format!("::{}()", self.errors.last_error),
+            false => String::from("-1"),
+        };
+
+        let mut sections = vec![];
+        let mut hashes = vec![];
+        for module in list(manifest, "modules") {
+            let mut section = vec![];
+            for item in list(module, "enums") {
+                section.push(types.enum_class(item));
+            }
+            for item in list(module, "structs") {
+                let name = str_of(&item["name"]);
+                let methods: Vec<&Value> = functions.iter().copied().filter(|function| function["struct"] == *name).collect();
+                if methods.iter().any(|function| function["kind"] == "hash") {
+                    hashes.push(format!(
+"template <>
+struct hash<{namespace}::{name}> {{
+    size_t operator()(const {namespace}::{name}& value) const {{
+        return static_cast<size_t>(value.hash());
+    }}
+}};
+", namespace = crate_name, name = name));
+                }
+                section.push(types.handle_class(item, &methods));
+            }
+            for function in list(module, "functions").filter(|function| function["struct"].is_null()) {
+                section.push(types.function(function, &types.free_names[&str_of(&function["symbol"])], Receiver::None));
+            }
+            if !section.is_empty() {
+                sections.push(format!("/* {} */\n\n{}", module_path(crate_name, module), section.join("\n")));
+            }
+        }
+        let hashes = match hashes.is_empty() {
+            true => String::new(),
+            false => format!("namespace std {{\n\n{}\n}} // namespace std\n\n", hashes.join("\n")),
+        };
+        format!(
+"/* Generated by crt_export from the {crate_name} crate, do not edit */
+#ifndef {guard}
+#define {guard}
+
+/* The wrappers use std::optional and std::string_view. MSVC only reports the standard in _MSVC_LANG. */
+#if (defined(_MSVC_LANG) ? _MSVC_LANG : __cplusplus) < 201703L
+#error \"{crate_name}.hpp requires C++17\"
+#endif
+
+#include \"{crate_name}.h\"
+
+#include <cstddef>
+#include <cstdint>
+#include <functional>
+#include <iterator>
+#include <memory>
+#include <optional>
+#include <stdexcept>
+#include <string>
+#include <string_view>
+#include <utility>
+#include <vector>
+
+/* The wrappers of deprecated functions are deprecated themselves, so only their callers warn */
+#if defined(__GNUC__) || defined(__clang__)
+#pragma GCC diagnostic push
+#pragma GCC diagnostic ignored \"-Wdeprecated-declarations\"
+#elif defined(_MSC_VER)
+#pragma warning(push)
+#pragma warning(disable: 4996)
+#endif
+
+namespace {crate_name} {{
+
+/** A failure reported by the library, with its error code */
+class Error : public std::runtime_error {{
+public:
+    explicit Error(int32_t code) : std::runtime_error(message(code)), code_(code) {{}}
+
+    int32_t code() const noexcept {{
+        return code_;
+    }}
+{name_method}
+private:
+    static std::string message(int32_t code) {{
+        return {message};
+    }}
+
+    int32_t code_;
+}};
+
+/**
+ * Bytes passed to the library, from a pointer and length, or from any contiguous container of
+ * bytes, e.g. std::string_view, std::vector<uint8_t> or std::span<const uint8_t>
+ */
+class ByteView {{
+public:
+    ByteView(const uint8_t* data, size_t size) noexcept : data_(data), size_(size) {{}}
+
+    template <typename Bytes, typename = decltype(std::data(std::declval<const Bytes&>()))>
+    ByteView(const Bytes& bytes) noexcept
+        : data_(reinterpret_cast<const uint8_t*>(std::data(bytes))), size_(std::size(bytes)) {{
+        static_assert(sizeof(*std::data(bytes)) == 1, \"ByteView is only for containers of bytes\");
+    }}
+
+    const uint8_t* data() const noexcept {{
+        return data_;
+    }}
+
+    size_t size() const noexcept {{
+        return size_;
+    }}
+
+private:
+    const uint8_t* data_;
+    size_t size_;
+}};
+
+/**
+ * Bytes for the library to write into, from a pointer and length, or from any contiguous
+ * container of bytes, e.g. std::vector<uint8_t> or std::span<uint8_t>
+ */
+class MutableByteView {{
+public:
+    MutableByteView(uint8_t* data, size_t size) noexcept : data_(data), size_(size) {{}}
+
+    template <typename Bytes, typename = decltype(std::data(std::declval<Bytes&>()))>
+    MutableByteView(Bytes&& bytes) noexcept
+        : data_(reinterpret_cast<uint8_t*>(std::data(bytes))), size_(std::size(bytes)) {{
+        static_assert(sizeof(*std::data(bytes)) == 1, \"MutableByteView is only for containers of bytes\");
+    }}
+
+    uint8_t* data() const noexcept {{
+        return data_;
+    }}
+
+    size_t size() const noexcept {{
+        return size_;
+    }}
+
+private:
+    uint8_t* data_;
+    size_t size_;
+}};
+
+namespace detail {{
+
+[[noreturn]] inline void raise_last_error() {{
+    throw Error({last_error});
+}}
+
+inline void check(int result) {{
+    if (result != 0) {{
+        raise_last_error();
+    }}
+}}
+
+/* A handle returned by the library, where NULL is a failure */
+template <typename Handle>
+Handle* checked(Handle* handle) {{
+    if (handle == nullptr) {{
+        raise_last_error();
+    }}
+    return handle;
+}}
+
+template <typename T, typename Handle>
+T wrap(Handle* handle) {{
+    return T(checked(handle));
+}}
+
+template <typename T, typename Handle>
+std::optional<T> wrap_optional(Handle* handle) {{
+    if (handle == nullptr) {{
+        return std::nullopt;
+    }}
+    return T(handle);
+}}
+
+/* Copies a string returned by the library, and frees it */
+inline std::optional<std::string> take_optional_string(char* s) {{
+    if (s == nullptr) {{
+        return std::nullopt;
+    }}
+    std::string copy(s);
+    ::crtrs_string_free(s);
+    return copy;
+}}
+
+inline std::string take_string(char* s) {{
+    return *take_optional_string(checked(s));
+}}
+
+inline std::vector<uint8_t> take_bytes(CByteBuf buf) {{
+    std::vector<uint8_t> bytes(buf.ptr, buf.ptr + buf.len);
+    ::crtrs_byte_buf_free(buf);
+    return bytes;
+}}
+
+/*
+ * Calls the std::function passed as user_data, which the library deletes with destroy once it
+ * will no longer call it.
+ */
+template <typename R, typename... Args>
+struct Callback {{
+    using Function = std::function<R(Args...)>;
+
+    static R call(Args... args, void* user_data) noexcept {{
+        return (*static_cast<Function*>(user_data))(args...);
+    }}
+
+    static void destroy(void* user_data) noexcept {{
+        delete static_cast<Function*>(user_data);
+    }}
+
+    static void* user_data(Function&& function) {{
+        return function ? new Function(std::move(function)) : nullptr;
+    }}
+}};
+
+}} // namespace detail
+
+{sections}
+}} // namespace {crate_name}
+
+{hashes}#if defined(__GNUC__) || defined(__clang__)
+#pragma GCC diagnostic pop
+#elif defined(_MSC_VER)
+#pragma warning(pop)
+#endif
+
+#endif /* {guard} */
+", crate_name = crate_name, guard = format!("{}_HPP", crate_name.to_uppercase()), name_method = name_method,
+            message = message, last_error = last_error, sections = sections.join("\n"), hashes = hashes)
+    }
+}
+
+// How a wrapper is given the handle its function is called on
+#[derive(Clone, Copy, PartialEq)]
+enum Receiver {
+    // A free function
+    None,
+    // A static member function, e.g. a constructor
+    Static,
+    // A member function, whose first arg is the object's handle
+    Instance,
+}
+
+// A C++ param of a wrapper, and how it is passed to the C function
+struct Param {
+    decl: String,
+    // The param's default, which trailing optional params have
+    default: Option<&'static str>,
+    // How a delegating constructor passes it on
+    forward: String,
+}
+
+// The exported structs and enums which C types refer to, e.g. EventLoopGroup* is a handle
+struct Types {
+    // The C name of each struct to its kind, e.g. refcounted
+    structs: HashMap<String, String>,
+    // The C name of each enum to the C type it is passed as
+    enums: HashMap<String, String>,
+    // The C name of each struct and enum to its name in the bindings
+    names: HashMap<String, String>,
+    // Free function symbol to name
+    free_names: HashMap<String, String>,
+}
+
+impl Types {
+    fn new(manifest: &Value) -> Types {
+        Types {
+            structs: items(manifest, "structs").map(|item| (str_of(&item["c_name"]), str_of(&item["kind"]))).collect(),
+            enums: items(manifest, "enums").map(|item| (str_of(&item["c_name"]), str_of(&item["c_type"]))).collect(),
+            names: items(manifest, "structs").chain(items(manifest, "enums")).map(|item| (str_of(&item["c_name"]), str_of(&item["name"]))).collect(),
+            free_names: free_function_names(manifest, c_name),
+        }
+    }
+
+    // The struct a handle type points to, e.g. EventLoopGroup for const EventLoopGroup*
+    fn handle_struct(&self, c_type: &str) -> Option<&str> {
+        let pointee = c_type.strip_suffix('*')?.trim_start_matches("const ").trim();
+        match self.structs.contains_key(pointee) {
+            true => self.names.get(pointee).map(String::as_str),
+            false => None,
+        }
+    }
+
+    // The C type as written in the namespace, where exported names are the C++ classes, e.g.
+    // ::EventLoopGroup* for the C handle
+    fn qualify(&self, c_type: &str) -> String {
+        let mut qualified = String::new();
+        let mut ident = String::new();
+        for c in c_type.chars().chain(std::iter::once(' ')) {
+            if c.is_alphanumeric() || c == '_' {
+                ident.push(c);
+                continue;
+            }
+            if self.structs.contains_key(&ident) || self.enums.contains_key(&ident) {
+                qualified.push_str("::");
+            }
+            qualified.push_str(&ident);
+            ident.clear();
+            qualified.push(c);
+        }
+        qualified.pop();
+        qualified
+    }
+
+    fn enum_class(&self, item: &Value) -> String {
+        let variants: Vec<String> = list(item, "variants").map(|variant| {
+            let deprecated = match variant["deprecated"].is_null() {
+                true => String::new(),
+                false => format!(" {}", attribute(&variant["deprecated"], "")),
+            };
+            format!("{}    {}{} = {},\n", doxygen(&docs_of(variant), &[], "    "), c_name(&str_of(&variant["name"])), deprecated,
+                str_of(&variant["value"]))
+        }).collect();
+        format!("{}enum class {}{} : {} {{\n{}}};\n", doxygen(&docs_of(item), &[], ""), attribute(&item["deprecated"], ""),
+            str_of(&item["name"]), str_of(&item["c_type"]), variants.concat())
+    }
+
+    fn handle_class(&self, item: &Value, functions: &[&Value]) -> String {
+        let name = str_of(&item["name"]);
+        let find = |kind: &str| functions.iter().find(|function| function["kind"] == *kind).map(|function| str_of(&function["symbol"]));
+        let copy = match (find("acquire"), find("clone")) {
+            (Some(symbol), _) | (None, Some(symbol)) => format!("
+    /** Copies share the handle if it is refcounted, otherwise the struct is cloned */
+    {name}(const {name}& other) : handle_(other.handle_ ? detail::checked(::{symbol}(other.handle_)) : nullptr) {{}}
+
+    {name}& operator=(const {name}& other) {{
+        {name} copy(other);
+        std::swap(handle_, copy.handle_);
+        return *this;
+    }}
+", name = name, symbol = symbol),
+            (None, None) => format!("
+    {name}(const {name}&) = delete;
+    {name}& operator=(const {name}&) = delete;
+", name = name),
+        };
+        let destructor = match find("release").or_else(|| find("destroy")) {
+            Some(symbol) => format!("
+    ~{}() {{
+        ::{}(handle_);
+    }}
+", name, symbol),
+            None => String::new(),
+        };
+
+        let mut members = vec![];
+        for function in functions.iter() {
+            let method_name = match c_name(&str_of(&function["name"])) {
+                // get and release are the handle's, as for std::unique_ptr
+                method_name if method_name == "get" || method_name == "release" => format!("{}_", method_name),
+                method_name => method_name,
+            };
+            match str_of(&function["kind"]).as_str() {
+                "release" | "destroy" | "acquire" | "clone" => (),
+                "equals" => {
+                    members.push(self.function(function, "operator==", Receiver::Instance));
+                    members.push(format!("    bool operator!=(const {}& other) const {{\n        return !(*this == other);\n    }}\n", name));
+                },
+                "constructor" | "new" => {
+                    // new is also the constructor, by delegating to the move constructor
+                    let factory = self.function(function, &method_name, Receiver::Static);
+                    if method_name == "new_" {
+                        let params = self.params(function, Receiver::Static);
+                        let explicit = match params.is_empty() {
+                            true => "",
+                            false => "explicit ",
+                        };
+                        let forward: Vec<&str> = params.iter().map(|param| param.forward.as_str()).collect();
+                        members.push(format!("{}    {}{}{}({}) : {}(new_({})) {{}}\n", self.doxygen(function, "    "), attribute(&function["deprecated"], " "),
+                            explicit, name, declarations(&params).join(", "), name, forward.join(", ")));
+                    }
+                    members.push(factory);
+                },
+                "static" => members.push(self.function(function, &method_name, Receiver::Static)),
+                _ => members.push(self.function(function, &method_name, Receiver::Instance)),
+            }
+        }
+        if item["kind"] == "value" {
+            for field in list(item, "fields") {
+                let field_name = c_name(&str_of(&field["name"]));
+                members.push(format!("{}    {} {}() const {{\n        return handle_->{};\n    }}\n", doxygen(&docs_of(field), &[], "    "),
+                    self.qualify(&str_of(&field["c_type"])), field_name, field_name));
+            }
+        }
+        let members: Vec<String> = members.into_iter().map(|member| format!("\n{}", member)).collect();
+
+        format!(
+"{docs}class {deprecated}{name} {{
+public:
+    /** Takes ownership of a handle from the C API */
+    explicit {name}(::{handle}* handle) noexcept : handle_(handle) {{}}
+
+    {name}({name}&& other) noexcept : handle_(std::exchange(other.handle_, nullptr)) {{}}
+
+    {name}& operator=({name}&& other) noexcept {{
+        std::swap(handle_, other.handle_);
+        return *this;
+    }}
+{copy}{destructor}
+    /** The handle, which is still owned by this object */
+    ::{handle}* get() const noexcept {{
+        return handle_;
+    }}
+
+    /** Gives up ownership of the handle, e.g. to pass it to the C API */
+    ::{handle}* release() noexcept {{
+        return std::exchange(handle_, nullptr);
+    }}
+
+    explicit operator bool() const noexcept {{
+        return handle_ != nullptr;
+    }}
+{members}
+private:
+    ::{handle}* handle_;
+}};
+", docs = doxygen(&docs_of(item), &[], ""), deprecated = attribute(&item["deprecated"], " "), name = name, handle = str_of(&item["c_name"]), copy = copy,
+            destructor = destructor, members = members.concat())
+    }
+
+    fn doxygen(&self, function: &Value, indent: &str) -> String {
+        let params: Vec<(String, String)> = list(function, "args")
+            .filter_map(|arg| Some((c_name(&str_of(&arg["name"])), String::from(arg["docs"].as_str()?))))
+            .collect();
+        doxygen(&docs_of(function), &params, indent)
+    }
+
+    // The C++ params of a function, besides its receiver and out arg
+    fn params(&self, function: &Value, receiver: Receiver) -> Vec<Param> {
+        let fallible = function["fallible"].as_bool().unwrap_or(false);
+        let mut params: Vec<Param> = list(function, "args").enumerate()
+            .filter(|(idx, arg)| !((receiver == Receiver::Instance && *idx == 0) || (fallible && arg["ownership"] == "owned")))
+            .map(|(_, arg)| self.param(arg))
+            .collect();
+        // Only trailing params may have defaults
+        let required = params.iter().rposition(|param| param.default.is_none()).map_or(0, |idx| idx + 1);
+        params[..required].iter_mut().for_each(|param| param.default = None);
+        params
+    }
+
+    fn param(&self, arg: &Value) -> Param {
+        let name = c_name(&str_of(&arg["name"]));
+        let c_type = str_of(&arg["c_type"]);
+        let nullable = arg["nullable"].as_bool().unwrap_or(false);
+        let param = |decl: String, default: Option<&'static str>| Param { decl, default, forward: name.clone() };
+        let moved = |decl: String, default: Option<&'static str>| Param { decl, default, forward: format!("std::move({})", name) };
+        match (arg["marshal"].as_str().unwrap_or_default(), self.handle_struct(&c_type)) {
+            ("Str", _) | ("String", _) => param(format!("std::string_view {}", name), None),
+            ("OptionStr", _) => param(format!("std::optional<std::string_view> {}", name), Some("std::nullopt")),
+            ("Slice", _) | ("Bytes", _) => param(format!("ByteView {}", name), None),
+            ("SliceMut", _) => param(format!("MutableByteView {}", name), None),
+            ("OutBuffer", _) => param(format!("std::vector<uint8_t>& {}", name), None),
+            ("Callback", _) | ("OptionCallback", _) => {
+                let (_, return_type, params) = self.callback(&c_type);
+                moved(format!("std::function<{}({})> {}", return_type, params.join(", "), name), Some("nullptr").filter(|_| nullable))
+            },
+            ("Value", _) | ("Array", _) => param(c_declaration(&self.qualify(&c_type), &name), None),
+            (_, Some(struct_name)) if arg["ownership"] == "transferred" => moved(format!("{}&& {}", struct_name, name), None),
+            (_, Some(struct_name)) if nullable => param(format!("const {}* {}", struct_name, name), Some("nullptr")),
+            (_, Some(struct_name)) => param(format!("const {}& {}", struct_name, name), None),
+            _ if self.enums.contains_key(&c_type) => param(format!("{} {}", self.names[&c_type], name), None),
+            _ => param(c_declaration(&self.qualify(&c_type), &name), None),
+        }
+    }
+
+    // The detail::Callback for a callback arg, and the return type and params of its std::function
+    fn callback(&self, c_type: &str) -> (String, String, Vec<String>) {
+        let idx = c_type.find("(*)").expect("Callbacks are function pointers");
+        let return_type = self.qualify(c_type[..idx].trim());
+        let mut params: Vec<String> = c_type[idx + 3..].trim().trim_start_matches('(').trim_end_matches(')')
+            .split(',').map(str::trim).filter(|param| !param.is_empty()).map(|param| self.qualify(param)).collect();
+        // The last param is the user_data
+        params.pop();
+        let callback = std::iter::once(return_type.clone()).chain(params.iter().cloned()).collect::<Vec<String>>();
+        (format!("detail::Callback<{}>", callback.join(", ")), return_type, params)
+    }
+
+    // A wrapper which converts its params to C, calls the function and converts the result back
+    fn function(&self, function: &Value, name: &str, receiver: Receiver) -> String {
+        let symbol = str_of(&function["symbol"]);
+        let fallible = function["fallible"].as_bool().unwrap_or(false);
+        let mut prelude: Vec<String> = vec![];
+        let mut call_args: Vec<String> = vec![];
+        let mut post: Vec<String> = vec![];
+        let mut out: Option<&Value> = None;
+        let mut qualifiers = "";
+        for (idx, arg) in list(function, "args").enumerate() {
+            let arg_name = c_name(&str_of(&arg["name"]));
+            let c_type = str_of(&arg["c_type"]);
+            if receiver == Receiver::Instance && idx == 0 {
+                // A consuming method can only be called on an rvalue, e.g. std::move(object).close()
+                qualifiers = match (arg["ownership"] == "transferred", c_type.starts_with("const ")) {
+                    (true, _) => " &&",
+                    (false, true) => " const",
+                    (false, false) => "",
+                };
+                call_args.push(String::from(match qualifiers {
+                    " &&" => "release()",
+                    _ => "handle_",
+                }));
+                continue;
+            }
+            if fallible && arg["ownership"] == "owned" {
+                prelude.push(format!("{} out{{}};", self.qualify(c_type.strip_suffix('*').unwrap_or(&c_type))));
+                call_args.push(String::from("&out"));
+                out = Some(arg);
+                continue;
+            }
+            let nullable = arg["nullable"].as_bool().unwrap_or(false);
+            let call_arg = match (arg["marshal"].as_str().unwrap_or_default(), self.handle_struct(&c_type)) {
+                ("Str", _) | ("String", _) => format!("std::string({}).c_str()", arg_name),
+                ("OptionStr", _) => format!("{0} ? std::string(*{0}).c_str() : nullptr", arg_name),
+                ("Slice", _) | ("Bytes", _) | ("SliceMut", _) => format!("{0}.data(), {0}.size()", arg_name),
+                // The vector is truncated to what was written
+                ("OutBuffer", _) => {
+                    prelude.push(format!("size_t {}_written = 0;", arg_name));
+                    post.push(format!("{0}.resize({0}_written);", arg_name));
+                    format!("{0}.data(), {0}.size(), &{0}_written", arg_name)
+                },
+                ("Callback", _) | ("OptionCallback", _) => {
+                    let (callback, _, _) = self.callback(&c_type);
+                    prelude.push(format!("void* {0}_user_data = {1}::user_data(std::move({0}));", arg_name, callback));
+                    format!("{0}_user_data ? &{1}::call : nullptr, {0}_user_data, &{1}::destroy", arg_name, callback)
+                },
+                ("Value", _) | ("Array", _) => arg_name,
+                (_, Some(_)) if arg["ownership"] == "transferred" => format!("{}.release()", arg_name),
+                (_, Some(_)) if nullable => format!("{0} ? {0}->get() : nullptr", arg_name),
+                (_, Some(_)) => format!("{}.get()", arg_name),
+                _ if self.enums.contains_key(&c_type) => format!("static_cast<::{}>({})", c_type, arg_name),
+                _ => arg_name,
+            };
+            call_args.push(call_arg);
+        }
+
+        let returns = &function["returns"];
+        let void = !fallible && returns["c_type"] == "void";
+        let mut call = format!("::{}({})", symbol, call_args.join(", "));
+        let mut body = prelude;
+        // Args are cleaned up after the call whether or not it fails
+        if !post.is_empty() {
+            match void {
+                true => body.push(format!("{};", call)),
+                false => body.push(format!("auto result = {};", call)),
+            }
+            call = String::from("result");
+            body.extend(post);
+        }
+        let return_type = match (fallible, out) {
+            (true, Some(out)) => {
+                body.push(format!("detail::check({});", call));
+                let c_type = str_of(&out["c_type"]);
+                let nullable = str_of(&out["rust_type"]).starts_with("Option<");
+                let (return_type, value) = self.convert(c_type.strip_suffix('*').unwrap_or(&c_type), "owned", nullable, "out");
+                body.push(format!("return {};", value));
+                return_type
+            },
+            (true, None) => {
+                body.push(format!("detail::check({});", call));
+                String::from("void")
+            },
+            (false, _) if void => {
+                // Unless it was called before the cleanup
+                if call != "result" {
+                    body.push(format!("{};", call));
+                }
+                String::from("void")
+            },
+            (false, _) => {
+                let nullable = returns["marshal"].as_str().is_some_and(|marshal| marshal.starts_with("Option"));
+                let (return_type, value) = self.convert(&str_of(&returns["c_type"]), &str_of(&returns["ownership"]), nullable, &call);
+                body.push(format!("return {};", value));
+                return_type
+            },
+        };
+
+        let params: Vec<String> = declarations(&self.params(function, receiver));
+        let (indent, prefix) = match receiver {
+            Receiver::None => ("", "inline "),
+            Receiver::Static => ("    ", "static "),
+            Receiver::Instance => ("    ", ""),
+        };
+        let body: Vec<String> = body.iter().map(|line| format!("{}    {}\n", indent, line)).collect();
+        format!("{}{}{}{}{} {}({}){} {{\n{}{}}}\n", self.doxygen(function, indent), indent, attribute(&function["deprecated"], " "),
+            prefix, return_type, name, params.join(", "), qualifiers, body.concat(), indent)
+    }
+
+    // The C++ type of a value returned from C, and the expression converting it
+    fn convert(&self, c_type: &str, ownership: &str, nullable: bool, value: &str) -> (String, String) {
+        match (c_type, ownership, self.handle_struct(c_type)) {
+            ("char*", "owned", _) if nullable => (String::from("std::optional<std::string>"), format!("detail::take_optional_string({})", value)),
+            ("char*", "owned", _) => (String::from("std::string"), format!("detail::take_string({})", value)),
+            ("CByteBuf", "owned", _) => (String::from("std::vector<uint8_t>"), format!("detail::take_bytes({})", value)),
+            (_, "owned", Some(struct_name)) if nullable => (format!("std::optional<{}>", struct_name), format!("detail::wrap_optional<{}>({})", struct_name, value)),
+            (_, "owned", Some(struct_name)) => (String::from(struct_name), format!("detail::wrap<{}>({})", struct_name, value)),
+            _ if self.enums.contains_key(c_type) => (self.names[c_type].clone(), format!("static_cast<{}>({})", self.names[c_type], value)),
+            _ => (self.qualify(c_type), String::from(value)),
+        }
+    }
+}
+
+fn declarations(params: &[Param]) -> Vec<String> {
+    params.iter().map(|param| match param.default {
+        Some(default) => format!("{} = {}", param.decl, default),
+        None => param.decl.clone(),
+    }).collect()
+}
+
+// The docs of an item in the manifest, whose arg docs are on its args
+fn docs_of(item: &Value) -> Docs {
+    Docs {
+        lines: item["docs"].as_str().into_iter().flat_map(str::lines).map(String::from).collect(),
+        args: vec![],
+        deprecated: item["deprecated"].as_str().map(String::from),
+    }
+}
+
+// [[deprecated]] followed by the separator, if the item is deprecated
+fn attribute(deprecated: &Value, separator: &str) -> String {
+    match deprecated.as_str() {
+        Some("") => format!("[[deprecated]]{}", separator),
+        Some(note) => format!("[[deprecated(\"{}\")]]{}", note.replace('\\', "\\\\").replace('"', "\\\""), separator),
+        None => String::new(),
+    }
+}
+
+fn module_path(crate_name: &str, module: &Value) -> String {
+    std::iter::once(crate_name).chain(list(module, "path").filter_map(Value::as_str)).collect::<Vec<&str>>().join("::")
+}
+
+impl Plugin for CppExporter {
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let path = output_dir.join(format!("{}.hpp", krate.name));
+        write_if_changed(&path, &self.header(&krate.name, &manifest::describe(krate)))?;
+        Ok(vec![path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plugin::Registry;
+    use crate::scan::tests::write_crate;
+    use crate::{generate_with, Config};
+    use std::fs;
+
+    #[test]
+    fn wraps_exports() {
+        let root = write_crate("cpp_wraps_exports", &[
+            ("src/lib.rs", "
+                #[crt_export(prefix = \"geo_\")]
+                #[repr(u8)]
+                pub enum Shape { Circle = 1, #[deprecated(note = \"Use Circle\")] Oval }
+
+                #[crt_export(prefix = \"geo_\")]
+                #[derive(Clone, PartialEq, Hash)]
+                pub struct Point { x: i32, y: i32 }
+
+                #[crt_export(prefix = \"geo_\")]
+                impl Point {
+                    pub fn new(x: i32, y: i32) -> Point {}
+                    pub fn shape(&self) -> Shape {}
+                    pub fn on_moved(&self, callback: Box<dyn FnOnce(i32) + Send>) {}
+                    pub fn kind(&self, from: Option<&str>) -> Option<String> {}
+                    pub fn close(self) {}
+                    pub fn get(&mut self) -> i32 {}
+                }"),
+        ]);
+        let output_dir = root.join("out");
+        let config = Config::new("geometry", &root.join("src/lib.rs"), &output_dir).generators("c,cpp");
+        let written = generate_with(&config, &Registry::default()).unwrap();
+        let path = output_dir.join("geometry.hpp");
+        assert!(written.contains(&path));
+        let header = fs::read_to_string(&path).unwrap();
+        assert!(header.contains("#include \"geometry.h\"\n"));
+        assert!(header.contains("#error \"geometry.hpp requires C++17\"\n"));
+
+        assert!(header.contains("    Oval [[deprecated(\"Use Circle\")]] = 2,\n"));
+        // Classes are named after the Rust types, and wrap the C types
+        assert!(header.contains("    explicit Point(::geo_Point* handle) noexcept : handle_(handle) {}\n"));
+        assert!(header.contains("    Point(const Point& other) : handle_(other.handle_ ? detail::checked(::geo_Point_clone(other.handle_)) : nullptr) {}\n"));
+        assert!(header.contains("    ~Point() {\n        ::geo_Point_destroy(handle_);\n    }\n"));
+        assert!(header.contains("    explicit Point(int32_t x, int32_t y) : Point(new_(x, y)) {}\n"));
+        assert!(header.contains("    Shape shape() const {\n        return static_cast<Shape>(::geo_Point_shape(handle_));\n    }\n"));
+        assert!(header.contains("::geo_Point_on_moved(handle_, callback_user_data ? &detail::Callback<void, int32_t>::call : nullptr, callback_user_data, &detail::Callback<void, int32_t>::destroy);\n"));
+        assert!(header.contains("    std::optional<std::string> kind(std::optional<std::string_view> from = std::nullopt) const {\n"));
+        // Consuming self gives up the handle, and get stays the handle's
+        assert!(header.contains("    void close() && {\n        ::geo_Point_close(release());\n    }\n"));
+        assert!(header.contains("    int32_t get_() {\n"));
+        assert!(header.contains("    int32_t x() const {\n        return handle_->x;\n    }\n"));
+        assert!(header.contains("struct hash<geometry::Point> {\n"));
+
+        let cxx = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+        let output = std::process::Command::new(&cxx)
+            .args(["-x", "c++", "-std=c++17", "-Wall", "-Wextra", "-Werror", "-fsyntax-only"])
+            .arg(&path)
+            .output()
+            .unwrap_or_else(|err| panic!("Failed to run {}: {}", cxx, err));
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        // Older standards fail up front, rather than on whichever C++17 feature comes first
+        let output = std::process::Command::new(&cxx)
+            .args(["-x", "c++", "-std=c++14", "-fsyntax-only"])
+            .arg(&path)
+            .output()
+            .unwrap_or_else(|err| panic!("Failed to run {}: {}", cxx, err));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("geometry.hpp requires C++17"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,890 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::header::write_if_changed;
+use crate::manifest::{self, free_function_names, is_exported, items, list, str_of, ErrorFunctions};
+use crate::plugin::Plugin;
+use crate::scan::Crate;
+
+// C# keywords which are valid Rust identifiers, and so may be used as names
+const RESERVED_NAMES: &[&str] = &[
+    "abstract", "base", "bool", "byte", "case", "catch", "char", "checked", "class", "decimal",
+    "default", "delegate", "do", "double", "event", "explicit", "finally", "fixed", "float", "foreach",
+    "goto", "implicit", "int", "interface", "internal", "is", "lock", "long", "namespace", "new",
+    "null", "object", "operator", "out", "params", "private", "protected", "public", "readonly",
+    "sbyte", "sealed", "short", "sizeof", "stackalloc", "string", "switch", "this", "throw", "try",
+    "typeof", "uint", "ulong", "unchecked", "ushort", "using", "virtual", "void", "volatile", "while",
+];
+
+// Members which every handle class inherits from SafeHandle and object
+const HANDLE_MEMBERS: &[&str] = &[
+    "Close", "DangerousAddRef", "DangerousGetHandle", "DangerousRelease", "Dispose", "Equals",
+    "Finalize", "GetHashCode", "GetType", "IsClosed", "IsInvalid", "MemberwiseClone", "ReleaseHandle",
+    "SetHandle", "SetHandleAsInvalid", "ToString",
+];
+
+/// The csharp generator, which writes <output_dir>/<crate_name>.cs, P/Invoke bindings for the
+/// crate's cdylib. Each exported struct is a SafeHandle subclass, which releases its handle when
+/// disposed or finalized, and free functions and constants are on the static Library class.
+/// Strings are passed as UTF-8, byte slices as spans, and failures are thrown as CrtException.
+#[derive(Default)]
+pub struct CSharpExporter {
+    errors: ErrorFunctions,
+}
+
+impl CSharpExporter {
+    pub fn new() -> CSharpExporter {
+        CSharpExporter::default()
+    }
+
+    pub fn errors(mut self, errors: ErrorFunctions) -> CSharpExporter {
+        self.errors = errors;
+        self
+    }
+
+    fn source(&self, crate_name: &str, manifest: &Value) -> String {
+        let types = Types::new(manifest);
+        let functions: Vec<&Value> = items(manifest, "functions").collect();
+        let error_name = is_exported(manifest, &self.errors.error_name);
+        let error_str = Some(&self.errors.error_str).filter(|symbol| is_exported(manifest, symbol));
+        let message = match (error_name, error_str) {
+            (true, Some(symbol)) => format!("$\"{{name}}: {{Marshal.PtrToStringUTF8(Native.{}(code))}}\"", symbol),
+            (false, Some(symbol)) => format!("Marshal.PtrToStringUTF8(Native.{}(code)) ?? $\"error {{code}}\"", symbol),
+            (true, None) => String::from("name ?? $\"error {code}\""),
+            (false, None) => String::from("$\"error {code}\""),
+        };
+        let error_name = match error_name {
+            true => format!("Marshal.PtrToStringUTF8(Native.{}(code))", self.errors.error_name),
+            false => String::from("null"),
+        };
+        let last_error = match is_exported(manifest, &self.errors.last_error) {
+            true => format!("Native.{}()", self.errors.last_error),
+            false => String::from("-1"),
+        };
+
+        let mut sections = vec![];
+        let mut declarations = vec![];
+        let mut statics = false;
+        for module in list(manifest, "modules") {
+            let mut section = vec![];
+            for item in list(module, "enums") {
+                section.push(types.enum_type(item));
+            }
+            for item in list(module, "structs").filter(|item| item["kind"] == "value") {
+                section.push(types.fields_struct(item));
+            }
+            for item in list(module, "structs") {
+                let name = str_of(&item["name"]);
+                let methods: Vec<&Value> = functions.iter().copied().filter(|function| function["struct"] == *name).collect();
+                section.push(types.handle_class(item, &methods));
+            }
+            let mut members: Vec<String> = list(module, "constants").map(|item| types.constant(item)).collect();
+            statics |= list(module, "constants").any(|item| item["value"].is_null());
+            for function in list(module, "functions").filter(|function| function["struct"].is_null()) {
+                members.push(types.wrapper(function, &types.free_names[&str_of(&function["symbol"])], Receiver::None));
+            }
+            if !members.is_empty() {
+                section.push(format!("    public static partial class Library\n    {{\n{}    }}\n", members.join("\n")));
+            }
+            if !section.is_empty() {
+                sections.push(format!("    // {}\n\n{}", module_path(crate_name, module), section.join("\n")));
+            }
+
+            let module_declarations: Vec<String> = list(module, "functions").map(|function| types.declaration(function)).collect();
+            if !module_declarations.is_empty() {
+                declarations.push(format!("        // {}\n\n{}", module_path(crate_name, module), module_declarations.join("\n")));
+            }
+        }
+        let library_handle = match statics {
+            true => "
+        // For statics, which are read from the library's exports
+        static readonly Lazy<IntPtr> handle = new Lazy<IntPtr>(() => NativeLibrary.Load(Library, typeof(Native).Assembly, null));
+
+        internal static IntPtr Export(string symbol) => NativeLibrary.GetExport(handle.Value, symbol);
+",
+            false => "",
+        };
+
+        format!(
+"// Generated by crt_export from the {crate_name} crate, do not edit
+#nullable enable
+// The wrappers of deprecated functions are deprecated themselves, so only their callers warn
+#pragma warning disable CS0612, CS0618
+
+using System;
+using System.Runtime.InteropServices;
+
+namespace {namespace}
+{{
+    /// <summary>
+    /// A failure reported by the library, with its error code
+    /// </summary>
+    public class CrtException : Exception
+    {{
+        public CrtException(int code) : this(code, {error_name})
+        {{
+        }}
+
+        CrtException(int code, string? name) : base({message})
+        {{
+            Code = code;
+            Name = name;
+        }}
+
+        public int Code {{ get; }}
+
+        /// <summary>
+        /// The name of the error, e.g. AWS_ERROR_INVALID_ARGUMENT
+        /// </summary>
+        public string? Name {{ get; }}
+    }}
+
+    /// <summary>
+    /// Bytes borrowed from the library
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct CByteCursor
+    {{
+        public nuint Len;
+        public IntPtr Ptr;
+    }}
+
+    /// <summary>
+    /// Bytes owned by the caller, which must be released with crtrs_byte_buf_free
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct CByteBuf
+    {{
+        public nuint Len;
+        public IntPtr Ptr;
+        public nuint Capacity;
+    }}
+
+    /// <summary>
+    /// A handle owned by the library, which is released when disposed or finalized
+    /// </summary>
+    public abstract class CrtHandle : SafeHandle
+    {{
+        protected CrtHandle() : base(IntPtr.Zero, true)
+        {{
+        }}
+
+        public override bool IsInvalid => handle == IntPtr.Zero;
+
+        // The handle, for a call which may be passed NULL, and so isn't marshalled as a SafeHandle
+        internal IntPtr Borrow()
+        {{
+            if (IsClosed || IsInvalid)
+            {{
+                throw new ObjectDisposedException(GetType().Name);
+            }}
+            return handle;
+        }}
+
+        // Gives up the handle to a function which takes ownership of it
+        internal IntPtr Detach()
+        {{
+            IntPtr detached = Borrow();
+            SetHandleAsInvalid();
+            return detached;
+        }}
+    }}
+
+{sections}
+    // The exports of the library, which the wrappers marshal to and from
+    internal static class Native
+    {{
+        internal const string Library = \"{crate_name}\";
+{library_handle}
+        internal static void RaiseLastError() => throw new CrtException({last_error});
+
+        internal static void Check(int result)
+        {{
+            if (result != 0)
+            {{
+                RaiseLastError();
+            }}
+        }}
+
+        // A handle returned by the library, where an invalid handle is a failure
+        internal static T Checked<T>(T handle) where T : CrtHandle
+        {{
+            if (handle.IsInvalid)
+            {{
+                RaiseLastError();
+            }}
+            return handle;
+        }}
+
+        internal static T? Optional<T>(T handle) where T : CrtHandle => handle.IsInvalid ? null : handle;
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        internal delegate void DestroyUserData(IntPtr userData);
+
+        // Frees the GCHandle passed as the user_data of a callback, once the library is done with it
+        internal static readonly DestroyUserData FreeUserData = userData => GCHandle.FromIntPtr(userData).Free();
+
+        // Copies a string returned by the library, and frees it
+        internal static string? TakeOptionalString(IntPtr s)
+        {{
+            if (s == IntPtr.Zero)
+            {{
+                return null;
+            }}
+            try
+            {{
+                return Marshal.PtrToStringUTF8(s);
+            }}
+            finally
+            {{
+                crtrs_string_free(s);
+            }}
+        }}
+
+        internal static string TakeString(IntPtr s)
+        {{
+            if (s == IntPtr.Zero)
+            {{
+                RaiseLastError();
+            }}
+            return TakeOptionalString(s)!;
+        }}
+
+        internal static byte[] CopyBytes(CByteCursor cursor)
+        {{
+            byte[] bytes = new byte[checked((int)cursor.Len)];
+            if (bytes.Length > 0)
+            {{
+                Marshal.Copy(cursor.Ptr, bytes, 0, bytes.Length);
+            }}
+            return bytes;
+        }}
+
+        internal static byte[] TakeBytes(CByteBuf buf)
+        {{
+            try
+            {{
+                return CopyBytes(new CByteCursor {{ Len = buf.Len, Ptr = buf.Ptr }});
+            }}
+            finally
+            {{
+                crtrs_byte_buf_free(buf);
+            }}
+        }}
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void crtrs_string_free(IntPtr s);
+
+        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]
+        internal static extern void crtrs_byte_buf_free(CByteBuf buf);
+
+{declarations}    }}
+}}
+", crate_name = crate_name, namespace = pascal(crate_name), error_name = error_name, message = message,
+            sections = sections.join("\n"), library_handle = library_handle, last_error = last_error,
+            declarations = declarations.join("\n"))
+    }
+}
+
+// How a wrapper is given the handle its function is called on
+#[derive(Clone, Copy, PartialEq)]
+enum Receiver {
+    // A static method, of a handle class or the Library class
+    None,
+    // An instance method, whose first arg is the object's handle
+    Instance,
+}
+
+// How an arg is passed from a wrapper to the library
+#[derive(Default)]
+struct Arg {
+    // The params of the [DllImport] declaration
+    native: Vec<String>,
+    // The params of the wrapper, and their defaults
+    params: Vec<(String, Option<&'static str>)>,
+    // Statements before and after the call, and the args of the call
+    prelude: Vec<String>,
+    post: Vec<String>,
+    call: Vec<String>,
+}
+
+// The exported structs which C types refer to, e.g. EventLoopGroup* is a handle
+struct Types {
+    // The C name of each struct to its kind, e.g. refcounted
+    structs: HashMap<String, String>,
+    // The C name of each struct and enum to its name in the bindings
+    names: HashMap<String, String>,
+    // Free function symbol to name
+    free_names: HashMap<String, String>,
+}
+
+impl Types {
+    fn new(manifest: &Value) -> Types {
+        Types {
+            structs: items(manifest, "structs").map(|item| (str_of(&item["c_name"]), str_of(&item["kind"]))).collect(),
+            names: items(manifest, "structs").chain(items(manifest, "enums")).map(|item| (str_of(&item["c_name"]), str_of(&item["name"]))).collect(),
+            free_names: free_function_names(manifest, pascal),
+        }
+    }
+
+    // The struct a handle type points to, e.g. EventLoopGroup for const EventLoopGroup*
+    fn handle_struct(&self, c_type: &str) -> Option<&str> {
+        let pointee = c_type.strip_suffix('*')?.trim_start_matches("const ").trim();
+        match self.structs.contains_key(pointee) {
+            true => self.names.get(pointee).map(String::as_str),
+            false => None,
+        }
+    }
+
+    // The C# type which a C type is marshalled as, where pointers are IntPtr unless the caller
+    // knows better
+    fn cs_type(&self, c_type: &str) -> String {
+        if c_type.ends_with('*') {
+            return String::from("IntPtr");
+        }
+        let cs_type = match c_type {
+            "void" => "void",
+            "bool" => "bool",
+            "char" | "unsigned char" | "uint8_t" => "byte",
+            "signed char" | "int8_t" => "sbyte",
+            "short" | "int16_t" => "short",
+            "unsigned short" | "uint16_t" => "ushort",
+            "int" | "int32_t" => "int",
+            "unsigned int" | "uint32_t" => "uint",
+            "long long" | "int64_t" => "long",
+            "unsigned long long" | "uint64_t" => "ulong",
+            "long" | "intptr_t" | "ssize_t" => "nint",
+            "unsigned long" | "size_t" | "uintptr_t" => "nuint",
+            "float" => "float",
+            "double" => "double",
+            _ if self.structs.get(c_type).is_some_and(|kind| kind == "value") => return format!("{}Fields", self.names[c_type]),
+            _ => self.names.get(c_type).map_or(c_type, String::as_str),
+        };
+        String::from(cs_type)
+    }
+
+    // A C# bool is marshalled as a 4 byte BOOL unless told otherwise
+    fn declare(&self, cs_type: &str, name: &str) -> String {
+        match cs_type {
+            "bool" => format!("[MarshalAs(UnmanagedType.U1)] bool {}", name),
+            _ => format!("{} {}", cs_type, name),
+        }
+    }
+
+    fn enum_type(&self, item: &Value) -> String {
+        let variants: Vec<String> = list(item, "variants").map(|variant| {
+            format!("{}{}        {} = {},\n", doc_comment(&variant["docs"], &[], "        "), obsolete(&variant["deprecated"], "        "),
+                str_of(&variant["name"]), str_of(&variant["value"]))
+        }).collect();
+        format!("{}{}    public enum {} : {}\n    {{\n{}    }}\n", doc_comment(&item["docs"], &[], "    "), obsolete(&item["deprecated"], "    "),
+            str_of(&item["name"]), self.cs_type(&str_of(&item["c_type"])), variants.concat())
+    }
+
+    // Structs whose fields are visible to C are read through this
+    fn fields_struct(&self, item: &Value) -> String {
+        let fields: Vec<String> = list(item, "fields").map(|field| match self.cs_type(&str_of(&field["c_type"])).as_str() {
+            "bool" => format!("        [MarshalAs(UnmanagedType.U1)]\n        public bool {};\n", pascal(&str_of(&field["name"]))),
+            cs_type => format!("        public {} {};\n", cs_type, pascal(&str_of(&field["name"]))),
+        }).collect();
+        format!("    [StructLayout(LayoutKind.Sequential)]\n    public struct {}Fields\n    {{\n{}    }}\n", str_of(&item["name"]), fields.concat())
+    }
+
+    fn constant(&self, item: &Value) -> String {
+        let name = pascal(&str_of(&item["name"]));
+        let c_type = str_of(&item["c_type"]);
+        let docs = format!("{}{}", doc_comment(&item["docs"], &[], "        "), obsolete(&item["deprecated"], "        "));
+        match item["value"].as_str() {
+            Some(value) if value.starts_with('"') => format!("{}        public const string {} = {};\n", docs, name, value),
+            Some(value) if c_type == "float" => format!("{}        public const float {} = {}f;\n", docs, name, value.trim_end_matches("f32")),
+            Some(value) => format!("{}        public const {} {} = {};\n", docs, self.cs_type(&c_type), name, value),
+            // Statics are read when they are used, as mutable ones may change
+            None => {
+                let cs_type = self.cs_type(&c_type);
+                format!("{}        public static {} {} => Marshal.PtrToStructure<{}>(Native.Export(\"{}\"));\n", docs, cs_type, name, cs_type,
+                    str_of(&item["symbol"]))
+            },
+        }
+    }
+
+    fn handle_class(&self, item: &Value, functions: &[&Value]) -> String {
+        let name = str_of(&item["name"]);
+        let find = |kind: &str| functions.iter().find(|function| function["kind"] == *kind).map(|function| str_of(&function["symbol"]));
+        let release = match find("release").or_else(|| find("destroy")) {
+            Some(symbol) => format!("            Native.{}(handle);\n", symbol),
+            None => String::new(),
+        };
+        let mut members = vec![format!("
+        // Handles are created by the marshaller when they are returned from the library
+        {0}()
+        {{
+        }}
+
+        protected override bool ReleaseHandle()
+        {{
+{1}            return true;
+        }}
+", name, release)];
+
+        let equals = find("equals");
+        let hash = find("hash");
+        if let Some(symbol) = &equals {
+            members.push(format!("
+        public bool Equals({0}? other) => other is not null && Native.{1}(this, other);
+", name, symbol));
+        }
+        if let Some(symbol) = &hash {
+            if equals.is_some() {
+                members.push(format!("\n        public override bool Equals(object? obj) => obj is {} other && Equals(other);\n", name));
+            }
+            members.push(format!("\n        public override int GetHashCode() => Native.{}(this).GetHashCode();\n", symbol));
+        }
+        for function in functions.iter() {
+            let method_name = match pascal(&str_of(&function["name"])) {
+                method_name if HANDLE_MEMBERS.contains(&method_name.as_str()) || method_name == name => format!("{}_", method_name),
+                method_name => method_name,
+            };
+            match str_of(&function["kind"]).as_str() {
+                "release" | "destroy" | "equals" | "hash" => (),
+                // Display is preferred for ToString, with Debug as DebugString if there are both
+                "debug_string" if find("to_string").is_some() => members.push(format!("\n        public string DebugString() => Native.TakeString(Native.{}(this));\n",
+                    str_of(&function["symbol"]))),
+                "to_string" | "debug_string" => members.push(format!("\n        public override string ToString() => Native.TakeString(Native.{}(this));\n",
+                    str_of(&function["symbol"]))),
+                "constructor" | "new" | "static" => members.push(format!("\n{}", self.wrapper(function, &method_name, Receiver::None))),
+                _ => members.push(format!("\n{}", self.wrapper(function, &method_name, Receiver::Instance))),
+            }
+        }
+        if item["kind"] == "value" {
+            members.push(format!("
+        {0}Fields Fields => Marshal.PtrToStructure<{0}Fields>(Borrow());
+", name));
+            for field in list(item, "fields") {
+                let field_name = pascal(&str_of(&field["name"]));
+                let property = match HANDLE_MEMBERS.contains(&field_name.as_str()) || field_name == name || field_name == "Fields" {
+                    true => format!("{}_", field_name),
+                    false => field_name.clone(),
+                };
+                members.push(format!("\n{}        public {} {} => Fields.{};\n", doc_comment(&field["docs"], &[], "        "),
+                    self.cs_type(&str_of(&field["c_type"])), property, field_name));
+            }
+        }
+
+        let equatable = match equals {
+            Some(_) => format!(", IEquatable<{}>", name),
+            None => String::new(),
+        };
+        format!("{}{}    public sealed class {} : CrtHandle{}\n    {{{}    }}\n", doc_comment(&item["docs"], &[], "    "), obsolete(&item["deprecated"], "    "),
+            name, equatable, members.concat())
+    }
+
+    // How an arg is passed, where the receiver's handle is this
+    fn arg(&self, function: &Value, arg: &Value, receiver: bool) -> Arg {
+        let name = match receiver {
+            true => String::from("this"),
+            false => cs_name(&camel(&str_of(&arg["name"]))),
+        };
+        // Suffixed for the extra params of an arg, e.g. dataLen
+        let base_name = camel(&str_of(&arg["name"]));
+        let native_name = cs_name(&base_name);
+        let c_type = str_of(&arg["c_type"]);
+        let nullable = arg["nullable"].as_bool().unwrap_or(false);
+        let fallible = function["fallible"].as_bool().unwrap_or(false);
+        let mut passed = Arg::default();
+        if fallible && arg["ownership"] == "owned" {
+            let pointee = c_type.strip_suffix('*').unwrap_or(&c_type);
+            let cs_type = match self.handle_struct(pointee) {
+                Some(struct_name) => String::from(struct_name),
+                None => self.cs_type(pointee),
+            };
+            passed.native.push(format!("out {} {}", cs_type, native_name));
+            passed.call.push(format!("out {} @out", cs_type));
+            return passed;
+        }
+        let mut simple = |native: String, param: String, default: Option<&'static str>, call: String| {
+            passed.native.push(native);
+            passed.params.push((param, default));
+            passed.call.push(call);
+        };
+        match (arg["marshal"].as_str().unwrap_or_default(), self.handle_struct(&c_type)) {
+            ("Str", _) | ("String", _) | ("CStr", _) => simple(format!("[MarshalAs(UnmanagedType.LPUTF8Str)] string {}", native_name),
+                format!("string {}", name), None, name.clone()),
+            ("OptionStr", _) => simple(format!("[MarshalAs(UnmanagedType.LPUTF8Str)] string? {}", native_name),
+                format!("string? {}", name), Some("null"), name.clone()),
+            // Spans are pinned for the call by passing a reference to their first byte
+            ("Slice", _) | ("Bytes", _) => {
+                passed.native.extend([format!("in byte {}", native_name), format!("nuint {}Len", base_name)]);
+                passed.params.push((format!("ReadOnlySpan<byte> {}", name), None));
+                passed.call.extend([format!("in MemoryMarshal.GetReference({})", name), format!("(nuint){}.Length", name)]);
+            },
+            ("SliceMut", _) => {
+                passed.native.extend([format!("ref byte {}", native_name), format!("nuint {}Len", base_name)]);
+                passed.params.push((format!("Span<byte> {}", name), None));
+                passed.call.extend([format!("ref MemoryMarshal.GetReference({})", name), format!("(nuint){}.Length", name)]);
+            },
+            // How much of the span was written is passed back through an out param
+            ("OutBuffer", _) => {
+                passed.native.extend([format!("ref byte {}", native_name), format!("nuint {}Len", base_name), format!("out nuint {}Written", base_name)]);
+                passed.params.extend([(format!("Span<byte> {}", name), None), (format!("out int {}Written", base_name), None)]);
+                passed.call.extend([format!("ref MemoryMarshal.GetReference({})", name), format!("(nuint){}.Length", name),
+                    format!("out nuint {}WrittenLength", base_name)]);
+                passed.post.push(format!("{0}Written = (int){0}WrittenLength;", base_name));
+            },
+            ("Array", _) => {
+                let element = self.cs_type(&c_type[..c_type.find('[').unwrap_or(c_type.len())]);
+                let direction = match c_type.starts_with("const ") {
+                    true => "[In]",
+                    false => "[In, Out]",
+                };
+                simple(format!("{} {}[] {}", direction, element, native_name), format!("{}[] {}", element, name), None, name.clone());
+            },
+            // The delegate is kept alive by a GCHandle, which is its user_data, until the library
+            // destroys the user_data
+            ("Callback", _) | ("OptionCallback", _) => {
+                let (delegate, function_type, _) = self.callback(function, arg);
+                let user_data = format!("{}UserData", base_name);
+                passed.native.extend([format!("{}? {}", delegate, native_name), format!("IntPtr {}UserData", base_name),
+                    format!("DestroyUserData? {}Destroy", base_name)]);
+                match nullable {
+                    true => {
+                        passed.params.push((format!("{}? {}", function_type, name), Some("null")));
+                        passed.prelude.push(format!("IntPtr {} = {} == null ? IntPtr.Zero : GCHandle.ToIntPtr(GCHandle.Alloc({}));", user_data, name, name));
+                        passed.call.push(format!("{} == null ? null : Native.{}Trampoline", name, delegate));
+                    },
+                    false => {
+                        passed.params.push((format!("{} {}", function_type, name), None));
+                        passed.prelude.push(format!("IntPtr {} = GCHandle.ToIntPtr(GCHandle.Alloc({}));", user_data, name));
+                        passed.call.push(format!("Native.{}Trampoline", delegate));
+                    },
+                }
+                passed.call.extend([user_data, String::from("Native.FreeUserData")]);
+            },
+            // A consumed handle is invalid afterwards
+            (_, Some(struct_name)) if arg["ownership"] == "transferred" => match (receiver, nullable) {
+                (true, _) => simple(format!("IntPtr {}", native_name), String::new(), None, String::from("Detach()")),
+                (false, true) => simple(format!("IntPtr {}", native_name), format!("{}? {}", struct_name, name), Some("null"),
+                    format!("{}?.Detach() ?? IntPtr.Zero", name)),
+                (false, false) => simple(format!("IntPtr {}", native_name), format!("{} {}", struct_name, name), None,
+                    format!("{}.Detach()", name)),
+            },
+            // NULL can't be marshalled as a SafeHandle, so is passed as an IntPtr, keeping the
+            // handle alive until the call returns
+            (_, Some(struct_name)) if nullable => {
+                simple(format!("IntPtr {}", native_name), format!("{}? {}", struct_name, name), Some("null"), format!("{}?.Borrow() ?? IntPtr.Zero", name));
+                passed.post.push(format!("GC.KeepAlive({});", name));
+            },
+            (_, Some(struct_name)) => simple(format!("{} {}", struct_name, native_name), format!("{} {}", struct_name, name), None, name.clone()),
+            _ => {
+                let cs_type = self.cs_type(&c_type);
+                simple(self.declare(&cs_type, &native_name), format!("{} {}", cs_type, name), None, name.clone());
+            },
+        }
+        if receiver {
+            passed.params.clear();
+        }
+        passed
+    }
+
+    // The delegate for a callback arg, named after its function and arg, the Action or Func which
+    // the wrapper takes, and the delegate's params
+    fn callback(&self, function: &Value, arg: &Value) -> (String, String, Vec<(String, String)>) {
+        let c_type = str_of(&arg["c_type"]);
+        let idx = c_type.find("(*)").expect("Callbacks are function pointers");
+        let return_type = self.cs_type(c_type[..idx].trim());
+        let mut params: Vec<(String, String)> = c_type[idx + 3..].trim().trim_start_matches('(').trim_end_matches(')')
+            .split(',').map(str::trim).filter(|param| !param.is_empty())
+            .enumerate().map(|(idx, param)| (self.cs_type(param), format!("arg{}", idx)))
+            .collect();
+        // The last param is the user_data
+        params.pop();
+        let mut type_args: Vec<String> = params.iter().map(|(cs_type, _)| cs_type.clone()).collect();
+        let function_type = match return_type.as_str() {
+            "void" => String::from("Action"),
+            _ => {
+                type_args.push(return_type);
+                String::from("Func")
+            },
+        };
+        let function_type = match type_args.is_empty() {
+            true => function_type,
+            false => format!("{}<{}>", function_type, type_args.join(", ")),
+        };
+        (format!("{}_{}", str_of(&function["symbol"]), str_of(&arg["name"])), function_type, params)
+    }
+
+    // The [DllImport] of a function, and the delegates and trampolines of its callbacks
+    fn declaration(&self, function: &Value) -> String {
+        let symbol = str_of(&function["symbol"]);
+        let mut declarations = vec![];
+        let mut native = vec![];
+        for arg in list(function, "args") {
+            native.extend(self.arg(function, arg, false).native);
+            if !matches!(arg["marshal"].as_str(), Some("Callback") | Some("OptionCallback")) {
+                continue;
+            }
+            let (delegate, function_type, params) = self.callback(function, arg);
+            let return_type = c_return(&function_type);
+            let args: Vec<&str> = params.iter().map(|(_, name)| name.as_str()).collect();
+            let delegate_params: Vec<String> = params.iter().map(|(cs_type, name)| self.declare(cs_type, name))
+                .chain(std::iter::once(String::from("IntPtr userData")))
+                .collect();
+            let call = match return_type {
+                "void" => format!("function({});", args.join(", ")),
+                _ => format!("return function({});", args.join(", ")),
+            };
+            let marshal_return = match return_type {
+                "bool" => "        [return: MarshalAs(UnmanagedType.U1)]\n",
+                _ => "",
+            };
+            declarations.push(format!("
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+{marshal_return}        internal delegate {return_type} {delegate}({delegate_params});
+
+        // Calls the {function_type} passed as user_data
+        internal static readonly {delegate} {delegate}Trampoline = ({lambda_params}) =>
+        {{
+            var function = ({function_type})GCHandle.FromIntPtr(userData).Target!;
+            {call}
+        }};
+", marshal_return = marshal_return, return_type = return_type, delegate = delegate, delegate_params = delegate_params.join(", "), function_type = function_type,
+                lambda_params = args.iter().copied().chain(std::iter::once("userData")).collect::<Vec<&str>>().join(", "), call = call));
+        }
+        let returns = &function["returns"];
+        let c_type = str_of(&returns["c_type"]);
+        let return_type = match (self.handle_struct(&c_type), returns["ownership"].as_str()) {
+            (Some(struct_name), Some("owned")) => String::from(struct_name),
+            _ => self.cs_type(&c_type),
+        };
+        let marshal_return = match return_type.as_str() {
+            "bool" => "        [return: MarshalAs(UnmanagedType.U1)]\n",
+            _ => "",
+        };
+        format!("{}        [DllImport(Library, CallingConvention = CallingConvention.Cdecl)]\n{}        internal static extern {} {}({});\n",
+            declarations.iter().map(|declaration| format!("{}\n", &declaration[1..])).collect::<String>(), marshal_return, return_type, symbol,
+            native.join(", "))
+    }
+
+    // A method which converts its params to C, calls the function and converts the result back
+    fn wrapper(&self, function: &Value, name: &str, receiver: Receiver) -> String {
+        let symbol = str_of(&function["symbol"]);
+        let fallible = function["fallible"].as_bool().unwrap_or(false);
+        let mut params: Vec<(String, Option<&'static str>)> = vec![];
+        let mut prelude: Vec<String> = vec![];
+        let mut call_args: Vec<String> = vec![];
+        let mut post: Vec<String> = vec![];
+        let mut out: Option<&Value> = None;
+        for (idx, arg) in list(function, "args").enumerate() {
+            if fallible && arg["ownership"] == "owned" {
+                out = Some(arg);
+            }
+            let passed = self.arg(function, arg, receiver == Receiver::Instance && idx == 0);
+            params.extend(passed.params);
+            prelude.extend(passed.prelude);
+            call_args.extend(passed.call);
+            post.extend(passed.post);
+        }
+        // Only trailing params may have defaults
+        let required = params.iter().rposition(|(_, default)| default.is_none()).map_or(0, |idx| idx + 1);
+        let params: Vec<String> = params.iter().enumerate().map(|(idx, (param, default))| match default {
+            Some(default) if idx >= required => format!("{} = {}", param, default),
+            _ => param.clone(),
+        }).collect();
+
+        let returns = &function["returns"];
+        let void = !fallible && returns["c_type"] == "void";
+        let mut call = format!("Native.{}({})", symbol, call_args.join(", "));
+        let mut body = prelude;
+        // Args are cleaned up after the call whether or not it fails
+        if !post.is_empty() {
+            match void {
+                true => body.push(format!("{};", call)),
+                false => body.push(format!("var result = {};", call)),
+            }
+            call = String::from("result");
+            body.extend(post);
+        }
+        let return_type = match (fallible, out) {
+            (true, Some(out)) => {
+                body.push(format!("Native.Check({});", call));
+                let c_type = str_of(&out["c_type"]);
+                let nullable = str_of(&out["rust_type"]).starts_with("Option<");
+                let (return_type, value) = self.convert(c_type.strip_suffix('*').unwrap_or(&c_type), "owned", nullable, "@out");
+                body.push(format!("return {};", value));
+                return_type
+            },
+            (true, None) => {
+                body.push(format!("Native.Check({});", call));
+                String::from("void")
+            },
+            (false, _) if void => {
+                // Unless it was called before the cleanup
+                if call != "result" {
+                    body.push(format!("{};", call));
+                }
+                String::from("void")
+            },
+            (false, _) => {
+                let nullable = returns["marshal"].as_str().is_some_and(|marshal| marshal.starts_with("Option"));
+                let (return_type, value) = self.convert(&str_of(&returns["c_type"]), &str_of(&returns["ownership"]), nullable, &call);
+                body.push(format!("return {};", value));
+                return_type
+            },
+        };
+
+        let arg_docs: Vec<(String, String)> = list(function, "args")
+            .filter_map(|arg| Some((cs_name(&camel(&str_of(&arg["name"]))), String::from(arg["docs"].as_str()?))))
+            .collect();
+        let modifier = match receiver {
+            Receiver::None => "public static",
+            Receiver::Instance => "public",
+        };
+        let body: Vec<String> = body.iter().map(|line| format!("            {}\n", line)).collect();
+        format!("{}{}        {} {} {}({})\n        {{\n{}        }}\n", doc_comment(&function["docs"], &arg_docs, "        "),
+            obsolete(&function["deprecated"], "        "), modifier, return_type, name, params.join(", "), body.concat())
+    }
+
+    // The C# type of a value returned from C, and the expression converting it
+    fn convert(&self, c_type: &str, ownership: &str, nullable: bool, value: &str) -> (String, String) {
+        match (c_type, ownership, self.handle_struct(c_type)) {
+            ("char*", "owned", _) if nullable => (String::from("string?"), format!("Native.TakeOptionalString({})", value)),
+            ("char*", "owned", _) => (String::from("string"), format!("Native.TakeString({})", value)),
+            ("const char*", _, _) => (String::from("string?"), format!("Marshal.PtrToStringUTF8({})", value)),
+            ("CByteCursor", _, _) => (String::from("byte[]"), format!("Native.CopyBytes({})", value)),
+            ("CByteBuf", "owned", _) => (String::from("byte[]"), format!("Native.TakeBytes({})", value)),
+            (_, "owned", Some(struct_name)) if nullable => (format!("{}?", struct_name), format!("Native.Optional({})", value)),
+            (_, "owned", Some(struct_name)) => (String::from(struct_name), format!("Native.Checked({})", value)),
+            _ => (self.cs_type(c_type), String::from(value)),
+        }
+    }
+}
+
+// The return type of an Action or Func
+fn c_return(function_type: &str) -> &str {
+    match function_type.strip_prefix("Func<") {
+        Some(type_args) => type_args.trim_end_matches('>').rsplit(", ").next().unwrap_or_default(),
+        None => "void",
+    }
+}
+
+// e.g. VersionMajor for VERSION_MAJOR or set_num_threads
+fn pascal(name: &str) -> String {
+    let words = name.split('_').filter(|word| !word.is_empty());
+    let words: Vec<String> = match name.chars().any(char::is_lowercase) {
+        // Already PascalCase words, e.g. Circle, keep their case
+        true => words.map(|word| word[..1].to_uppercase() + &word[1..]).collect(),
+        false => words.map(|word| word[..1].to_uppercase() + &word[1..].to_lowercase()).collect(),
+    };
+    words.concat()
+}
+
+fn camel(name: &str) -> String {
+    let name = pascal(name);
+    match name.chars().next() {
+        Some(first) => first.to_lowercase().chain(name.chars().skip(1)).collect(),
+        None => name,
+    }
+}
+
+fn cs_name(name: &str) -> String {
+    match RESERVED_NAMES.contains(&name) {
+        true => format!("@{}", name),
+        false => String::from(name),
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// The docs as an XML doc comment, with the docs of each arg as a <param>
+fn doc_comment(docs: &Value, args: &[(String, String)], indent: &str) -> String {
+    let mut lines = vec![];
+    if let Some(docs) = docs.as_str() {
+        lines.push(String::from("<summary>"));
+        lines.extend(docs.lines().map(xml_escape));
+        lines.push(String::from("</summary>"));
+    }
+    lines.extend(args.iter().map(|(name, docs)| format!("<param name=\"{}\">{}</param>", name.trim_start_matches('@'), xml_escape(docs))));
+    lines.iter().map(|line| format!("{}/// {}\n", indent, line).replace("/// \n", "///\n")).collect()
+}
+
+fn obsolete(deprecated: &Value, indent: &str) -> String {
+    match deprecated.as_str() {
+        Some("") => format!("{}[Obsolete]\n", indent),
+        Some(note) => format!("{}[Obsolete(\"{}\")]\n", indent, note.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => String::new(),
+    }
+}
+
+fn module_path(crate_name: &str, module: &Value) -> String {
+    std::iter::once(crate_name).chain(list(module, "path").filter_map(Value::as_str)).collect::<Vec<&str>>().join("::")
+}
+
+impl Plugin for CSharpExporter {
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let path = output_dir.join(format!("{}.cs", krate.name));
+        write_if_changed(&path, &self.source(&krate.name, &manifest::describe(krate)))?;
+        Ok(vec![path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plugin::Registry;
+    use crate::scan::tests::write_crate;
+    use crate::{generate_with, Config};
+    use std::fs;
+
+    #[test]
+    fn wraps_exports() {
+        let root = write_crate("csharp_wraps_exports", &[
+            ("src/lib.rs", "
+                #[crt_export]
+                #[derive(Clone, PartialEq, Hash)]
+                pub struct Point { x: i32, y: i32, visible: bool }
+
+                #[crt_export]
+                impl Point {
+                    pub fn digest(&self, data: &[u8], out: &mut OutBuffer) -> Result<(), Error> {}
+                    pub fn on_moved(&self, callback: Box<dyn FnOnce(i32) + Send>) {}
+                    pub fn on_checked(&self, callback: Option<Box<dyn FnMut(i32, i32) -> bool + Send>>) {}
+                    pub fn close(self) {}
+                    pub fn bytes(&self) -> Vec<u8> {}
+                }
+
+                #[crt_export]
+                pub static LIMIT: u32 = 1;"),
+        ]);
+        let output_dir = root.join("out");
+        let config = Config::new("geometry", &root.join("src/lib.rs"), &output_dir).generators("csharp");
+        let written = generate_with(&config, &Registry::default()).unwrap();
+        assert_eq!(vec![output_dir.join("geometry.cs")], written);
+        let source = fs::read_to_string(&written[0]).unwrap();
+        assert!(source.contains("namespace Geometry\n"));
+        assert!(source.contains("        internal const string Library = \"geometry\";\n"));
+
+        assert!(source.contains("        [MarshalAs(UnmanagedType.U1)]\n        public bool Visible;\n"));
+        assert!(source.contains("    public sealed class Point : CrtHandle, IEquatable<Point>\n"));
+        assert!(source.contains("        public override int GetHashCode() => Native.Point_hash(this).GetHashCode();\n"));
+        // Spans are passed by reference to their first element
+        assert!(source.contains("
+        public void Digest(ReadOnlySpan<byte> data, Span<byte> @out, out int outWritten)
+        {
+            var result = Native.Point_digest(this, in MemoryMarshal.GetReference(data), (nuint)data.Length, ref MemoryMarshal.GetReference(@out), (nuint)@out.Length, out nuint outWrittenLength);
+            outWritten = (int)outWrittenLength;
+            Native.Check(result);
+        }
+"));
+        assert!(source.contains("internal static extern int Point_digest(Point @this, in byte data, nuint dataLen, ref byte @out, nuint outLen, out nuint outWritten);\n"));
+        // Callbacks are only freed after the call if the library calls them once
+        assert!(source.contains("internal delegate void Point_on_moved_callback(int arg0, IntPtr userData);\n"));
+        assert!(source.contains("            var function = (Action<int>)GCHandle.FromIntPtr(userData).Target!;\n            function(arg0);\n"));
+        assert!(source.contains("        [return: MarshalAs(UnmanagedType.U1)]\n        internal delegate bool Point_on_checked_callback(int arg0, int arg1, IntPtr userData);\n"));
+        assert!(source.contains("            Native.Point_on_checked(this, callback == null ? null : Native.Point_on_checked_callbackTrampoline, callbackUserData, Native.FreeUserData);\n"));
+        // Consuming self gives up the handle, and Close stays SafeHandle's
+        assert!(source.contains("        public void Close_()\n        {\n            Native.Point_close(Detach());\n        }\n"));
+        assert!(source.contains("            return Native.TakeBytes(Native.Point_bytes(this));\n"));
+        assert!(source.contains("        public static uint Limit => Marshal.PtrToStructure<uint>(Native.Export(\"LIMIT\"));\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use syn::{Attribute, Lit, Meta, NestedMeta};
+
+/// The doc comment and #[deprecated] attribute of an exported item, which are carried into the
+/// bindings, as that is what their users read rather than the Rust source
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct Docs {
+    /// The doc comment, a line per entry, without its # Arguments section
+    pub lines: Vec<String>,
+    /// (arg, description) from the # Arguments section of the doc comment, as Rust doesn't allow
+    /// doc comments on args, e.g. "* `options` - How to create it" is ("options", "How to create it")
+    pub args: Vec<(String, String)>,
+    /// Set if the item is #[deprecated], to the note and/or since version if they were given
+    pub deprecated: Option<String>,
+}
+
+impl Docs {
+    pub fn from_attrs(attrs: &[Attribute]) -> Docs {
+        let mut lines: Vec<String> = vec![];
+        let mut deprecated: Option<String> = None;
+        for attr in attrs.iter() {
+            if attr.path.is_ident("doc") {
+                if let Ok(Meta::NameValue(name_value)) = attr.parse_meta() {
+                    if let Lit::Str(doc) = name_value.lit {
+                        // /// doc is #[doc = " doc"], and /** */ comments span several lines. An
+                        // empty /// has no lines, but is still a blank line.
+                        let doc = doc.value();
+                        match doc.is_empty() {
+                            true => lines.push(String::new()),
+                            false => lines.extend(doc.lines().map(|line| {
+                                String::from(line.strip_prefix(' ').unwrap_or(line).trim_end())
+                            })),
+                        }
+                    }
+                }
+            } else if attr.path.is_ident("deprecated") {
+                deprecated = Some(deprecation(attr));
+            }
+        }
+        let (lines, args) = split_args(lines);
+        Docs { lines, args, deprecated }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.args.is_empty() && self.deprecated.is_none()
+    }
+
+    /// The description of the arg, from the # Arguments section
+    pub fn arg(&self, name: &str) -> Option<&str> {
+        self.args.iter().find(|(arg, _)| arg == name).map(|(_, doc)| doc.as_str())
+    }
+}
+
+// #[deprecated], #[deprecated = "note"] or #[deprecated(since = "0.2", note = "note")]
+fn deprecation(attr: &Attribute) -> String {
+    let mut since: Option<String> = None;
+    let mut note: Option<String> = None;
+    match attr.parse_meta() {
+        Ok(Meta::NameValue(name_value)) => if let Lit::Str(lit) = name_value.lit {
+            note = Some(lit.value());
+        },
+        Ok(Meta::List(list)) => for nested in list.nested.iter() {
+            if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
+                if let Lit::Str(lit) = &name_value.lit {
+                    if name_value.path.is_ident("since") {
+                        since = Some(lit.value());
+                    } else if name_value.path.is_ident("note") {
+                        note = Some(lit.value());
+                    }
+                }
+            }
+        },
+        _ => (),
+    }
+    match (since, note) {
+        (Some(since), Some(note)) => format!("Since {}: {}", since, note),
+        (Some(since), None) => format!("Since {}", since),
+        (None, Some(note)) => note,
+        (None, None) => String::new(),
+    }
+}
+
+// Separates the # Arguments section, which lists each arg as "* `arg` - description", with any
+// further lines of the description indented
+fn split_args(lines: Vec<String>) -> (Vec<String>, Vec<(String, String)>) {
+    let mut doc: Vec<String> = vec![];
+    let mut args: Vec<(String, String)> = vec![];
+    let mut in_args = false;
+    for line in lines.into_iter() {
+        if line.starts_with('#') {
+            in_args = line.trim_start_matches('#').trim() == "Arguments";
+            if in_args {
+                continue;
+            }
+        }
+        if !in_args {
+            doc.push(line);
+            continue;
+        }
+        let item = line.strip_prefix("* ").or_else(|| line.strip_prefix("- "));
+        match (item.and_then(parse_arg), args.last_mut()) {
+            (Some(arg), _) => args.push(arg),
+            (None, Some((_, description))) if !line.trim().is_empty() => {
+                description.push(' ');
+                description.push_str(line.trim());
+            },
+            _ => (),
+        }
+    }
+    // Drop the blank lines which separated the sections
+    while doc.last().is_some_and(|line| line.is_empty()) {
+        doc.pop();
+    }
+    (doc, args)
+}
+
+// `arg` - description, or `arg`: description
+fn parse_arg(item: &str) -> Option<(String, String)> {
+    let item = item.trim().strip_prefix('`')?;
+    let end = item.find('`')?;
+    let description = item[end + 1..].trim_start().trim_start_matches(['-', ':']);
+    Some((String::from(&item[..end]), String::from(description.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::ItemFn;
+
+    fn docs(item: &str) -> Docs {
+        let item: ItemFn = syn::parse_str(item).unwrap();
+        Docs::from_attrs(&item.attrs)
+    }
+
+    #[test]
+    fn doc_comments() {
+        let docs = docs("
+            /// Creates a thing.
+            ///
+            /// # Arguments
+            ///
+            /// * `options` - How to create it, which may
+            ///   be NULL
+            /// * `out`: The created thing
+            ///
+            /// # Errors
+            /// If the options are invalid
+            fn new(options: Option<&Options>) -> Result<Thing, Error> {}");
+        assert_eq!(vec!["Creates a thing.", "", "# Errors", "If the options are invalid"], docs.lines);
+        assert_eq!(Some("How to create it, which may be NULL"), docs.arg("options"));
+        assert_eq!(Some("The created thing"), docs.arg("out"));
+        assert_eq!(None, docs.deprecated);
+    }
+
+    #[test]
+    fn deprecations() {
+        assert_eq!(Some(String::new()), docs("#[deprecated] fn f() {}").deprecated);
+        assert_eq!(Some(String::from("Use g")), docs("#[deprecated = \"Use g\"] fn f() {}").deprecated);
+        assert_eq!(Some(String::from("Since 0.2: Use g")), docs("#[deprecated(since = \"0.2\", note = \"Use g\")] fn f() {}").deprecated);
+        assert!(docs("#[inline] fn f() {}").is_empty());
+    }
+}
@@ -0,0 +1,574 @@
+use crate::plugin::Plugin;
+
+use crate::{Constant, Enum, Struct, Method};
+use crate::scan::{Crate, Module};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::Ident;
+use crate::docs::Docs;
+use crate::types::{c_declaration, rename_c_type, Marshal};
+
+// C and C++ keywords which are valid Rust identifiers, and so may be used as parameter names
+const RESERVED_NAMES: &[&str] = &[
+    "auto", "char", "class", "default", "delete", "double", "float", "friend", "goto", "int",
+    "long", "namespace", "new", "operator", "private", "protected", "public", "register", "short",
+    "signed", "sizeof", "template", "this", "throw", "typedef", "typename", "union", "unsigned",
+    "virtual", "void", "volatile",
+];
+
+/// The c generator, which declares everything exported from each module in a header which can be
+/// included on its own, see write_headers
+#[derive(Default)]
+pub struct HeaderExporter {
+    crate_name: String,
+    modules: Vec<ModuleHeader>,
+    // The exported C name of each struct and enum, which C types are renamed to
+    c_names: HashMap<String, String>,
+}
+
+impl HeaderExporter {
+    pub fn new() -> HeaderExporter {
+        HeaderExporter::default()
+    }
+
+    // The header for the module whose items are being visited
+    fn current(&mut self) -> &mut ModuleHeader {
+        self.modules.last_mut().expect("Items are visited after their module")
+    }
+}
+
+// Structs and enums are declared first, so that the constants and functions can use them
+struct ModuleHeader {
+    name: String,
+    // The module's Rust path, e.g. crtrs::io
+    path: String,
+    types: Vec<String>,
+    constants: Vec<String>,
+    functions: Vec<String>,
+    // The C types declared by this header, which other headers must include it to use
+    declared: Vec<String>,
+}
+
+impl ModuleHeader {
+    fn new(crate_name: &str, module: &Module) -> ModuleHeader {
+        ModuleHeader {
+            name: module.name(),
+            path: std::iter::once(crate_name).chain(module.path.iter().map(String::as_str)).collect::<Vec<&str>>().join("::"),
+            types: vec![],
+            constants: vec![],
+            functions: vec![],
+            declared: vec![],
+        }
+    }
+
+    // Identifiers used by the constants and functions, some of which are types from other modules.
+    // Doc comments are skipped, as mentioning a type doesn't need its declaration.
+    fn used_names(&self) -> BTreeSet<String> {
+        self.constants.iter().chain(self.functions.iter())
+            .flat_map(|decl| decl.lines())
+            .filter(|line| !line.trim_start().starts_with(['/', '*']))
+            .flat_map(|line| line.split(|c: char| !c.is_alphanumeric() && c != '_').map(String::from))
+            .collect()
+    }
+
+    fn header(&self, crate_name: &str, includes: &BTreeSet<String>) -> String {
+        let guard = format!("{}_{}_H", crate_name, self.name).to_uppercase();
+        let includes: Vec<String> = includes.iter().map(|include| format!("#include \"{}.h\"\n", include)).collect();
+        let sections: Vec<String> = [&self.types, &self.constants, &self.functions].iter()
+            .filter(|section| !section.is_empty())
+            .map(|section| section.join("\n"))
+            .collect();
+        format!(
+"/* Generated by crt_export from the {module} module of the {crate_name} crate, do not edit */
+#ifndef {guard}
+#define {guard}
+
+#include \"common.h\"
+{includes}
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+{sections}
+#ifdef __cplusplus
+}}
+#endif
+
+#endif /* {guard} */
+", module = self.path, crate_name = crate_name, guard = guard,
+            includes = includes.concat(), sections = sections.join("\n"))
+    }
+}
+
+// Writes <output_dir>/<crate_name>/<module>.h for each module, which includes the headers of any
+// other modules whose types it uses, and <output_dir>/<crate_name>.h which includes them all.
+// Headers are only rewritten if they change, and headers for modules which no longer have any
+// exports are removed, so the output only depends on the modules given.
+fn write_headers(crate_name: &str, modules: &[ModuleHeader], output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let module_dir = output_dir.join(crate_name);
+    fs::create_dir_all(&module_dir)?;
+    let mut declared_by: HashMap<&str, &str> = HashMap::new();
+    for module in modules.iter() {
+        module.declared.iter().for_each(|c_type| {
+            declared_by.insert(c_type, &module.name);
+        });
+    }
+
+    let mut written = vec![module_dir.join("common.h")];
+    write_if_changed(&written[0], &common_header(crate_name))?;
+    for module in modules.iter() {
+        let includes: BTreeSet<String> = module.used_names().iter()
+            .filter_map(|name| declared_by.get(name.as_str()))
+            .filter(|include| **include != module.name)
+            .map(|include| String::from(*include))
+            .collect();
+        let header = module_dir.join(format!("{}.h", module.name));
+        write_if_changed(&header, &module.header(crate_name, &includes))?;
+        written.push(header);
+    }
+    for entry in fs::read_dir(&module_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "h") && !written.contains(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let guard = format!("{}_H", crate_name.to_uppercase());
+    let includes: Vec<String> = written.iter().map(|header| {
+        format!("#include \"{}/{}\"\n", crate_name, header.file_name().unwrap().to_string_lossy())
+    }).collect();
+    let umbrella = output_dir.join(format!("{}.h", crate_name));
+    write_if_changed(&umbrella, &format!(
+"/* Generated by crt_export from the {crate_name} crate, do not edit */
+#ifndef {guard}
+#define {guard}
+
+{includes}
+#endif /* {guard} */
+", crate_name = crate_name, guard = guard, includes = includes.concat()))?;
+    written.push(umbrella);
+    Ok(written)
+}
+
+// The types and functions from the crate's runtime, which exported functions use
+fn common_header(crate_name: &str) -> String {
+    let guard = format!("{}_COMMON_H", crate_name.to_uppercase());
+    format!(
+"/* Generated by crt_export for the {crate_name} crate, do not edit */
+#ifndef {guard}
+#define {guard}
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#if defined(__GNUC__) || defined(__clang__)
+#define {prefix}_DEPRECATED(note) __attribute__((deprecated(note)))
+#else
+#define {prefix}_DEPRECATED(note)
+#endif
+
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+/* Bytes borrowed from Rust, valid until the owner is modified or released */
+typedef struct CByteCursor {{
+    size_t len;
+    const uint8_t* ptr;
+}} CByteCursor;
+
+/* Bytes owned by C, which must be released with crtrs_byte_buf_free */
+typedef struct CByteBuf {{
+    size_t len;
+    uint8_t* ptr;
+    size_t capacity;
+}} CByteBuf;
+
+/* Releases strings returned by exported functions */
+void crtrs_string_free(char* s);
+void crtrs_byte_buf_free(CByteBuf buf);
+
+#ifdef __cplusplus
+}}
+#endif
+
+#endif /* {guard} */
+", crate_name = crate_name, guard = guard, prefix = crate_name.to_uppercase())
+}
+
+// Writes to a temporary file which is renamed into place, so that nothing ever sees a partially
+// written header, and unchanged headers are left alone so that C builds don't rebuild for them
+pub(crate) fn write_if_changed(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    if fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+        return Ok(());
+    }
+    let tmp = path.with_extension(format!("tmp.{}", std::process::id()));
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+// Parameter names which are C or C++ keywords get a trailing _
+pub(crate) fn c_name(name: &str) -> String {
+    match RESERVED_NAMES.contains(&name) {
+        true => format!("{}_", name),
+        false => String::from(name),
+    }
+}
+
+fn c_param(c_type: &str, name: &str) -> String {
+    c_declaration(c_type, &c_name(name))
+}
+
+// An empty parameter list in C means the parameters are unspecified, rather than none
+fn c_function(return_type: &str, name: &str, params: Vec<String>, attrs: &str) -> String {
+    let params = match params.is_empty() {
+        true => String::from("void"),
+        false => params.join(", "),
+    };
+    format!("{} {}({}){};\n", return_type, name, params, attrs)
+}
+
+// The docs as a Doxygen comment, with a @param for each documented (param, description)
+pub(crate) fn doxygen(docs: &Docs, params: &[(String, String)], indent: &str) -> String {
+    let mut lines: Vec<String> = docs.lines.clone();
+    if !params.is_empty() || docs.deprecated.is_some() {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(params.iter().map(|(name, doc)| format!("@param {} {}", name, doc)));
+        if let Some(deprecated) = &docs.deprecated {
+            lines.push(format!("@deprecated {}", deprecated).trim_end().to_owned());
+        }
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<String> = lines.iter().map(|line| {
+        // A */ in the docs would end the comment
+        match line.is_empty() {
+            true => format!("{} *\n", indent),
+            false => format!("{} * {}\n", indent, line.replace("*/", "* /")),
+        }
+    }).collect();
+    format!("{}/**\n{}{} */\n", indent, lines.concat(), indent)
+}
+
+impl HeaderExporter {
+    fn c_type(&self, c_type: &str) -> String {
+        rename_c_type(c_type, &self.c_names)
+    }
+
+    // Marks a declaration as deprecated, if it is. Compilers which don't support the attribute
+    // still get the @deprecated in the docs.
+    fn deprecation(&self, deprecated: Option<&String>) -> String {
+        match deprecated {
+            Some(note) => format!(" {}_DEPRECATED(\"{}\")", self.crate_name.to_uppercase(), note.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => String::new(),
+        }
+    }
+
+    // Functions generated for a struct are deprecated along with it
+    fn function_declaration(&self, method: &Method, struct_docs: &Docs) -> String {
+        let mut param_docs: Vec<(String, String)> = vec![];
+        let params : Vec<String> = method.args.iter().chain(method.out.iter()).flat_map(|a| {
+            if let Some(doc) = method.docs.arg(&a.name) {
+                param_docs.push((c_name(&a.name), String::from(doc)));
+            }
+            if matches!(a.marshal, Marshal::Callback | Marshal::OptionCallback) {
+                param_docs.push((format!("{}_user_data", a.name), format!("Passed to each call of {}", a.name)));
+                param_docs.push((format!("{}_destroy", a.name), format!(
+                    "Called with {0}_user_data once {0} will not be called again, after which it may be freed. \
+                    May be NULL, in which case {0}_user_data is never freed by the library.", a.name)));
+            }
+            let nullable = a.marshal.is_nullable();
+            a.c_params().into_iter().enumerate().map(move |(idx, (name, c_type))| {
+                let c_type = self.c_type(&c_type);
+                match nullable && idx == 0 {
+                    true => format!("/* nullable */ {}", c_param(&c_type, &name)),
+                    false => c_param(&c_type, &name),
+                }
+            })
+        }).collect();
+        let mut docs = method.docs.clone();
+        docs.deprecated = docs.deprecated.or_else(|| struct_docs.deprecated.clone());
+        let deprecated = self.deprecation(docs.deprecated.as_ref());
+        doxygen(&docs, &param_docs, "") + &c_function(&self.c_type(&method.exported_return_type()), &method.exported_name(), params, &deprecated)
+    }
+}
+
+impl Plugin for HeaderExporter {
+    fn on_module(&mut self, krate: &Crate, module: &Module) {
+        self.crate_name = krate.name.clone();
+        self.c_names = krate.c_type_names();
+        self.modules.push(ModuleHeader::new(&krate.name, module));
+    }
+
+    // Structs are opaque to C, which only holds handles to them, unless they can be used by value
+    fn on_struct(&mut self, struct_target: &Struct) {
+        let name = struct_target.exported_name();
+        let deprecated = self.deprecation(struct_target.docs.deprecated.as_ref());
+        let decl = match struct_target.c_fields() {
+            Some(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(field, c_type)| {
+                    let field_deprecated = self.deprecation(field.docs.deprecated.as_ref());
+                    format!("{}    {}{};\n", doxygen(&field.docs, &[], "    "), c_param(&self.c_type(c_type), &field.id.to_string()), field_deprecated)
+                }).collect();
+                format!("typedef struct {} {{\n{}}} {}{};\n", name, fields.concat(), name, deprecated)
+            },
+            None => format!("typedef struct {} {}{};\n", name, name, deprecated),
+        };
+        let docs = doxygen(&struct_target.docs, &[], "");
+        self.current().types.push(docs + &decl);
+        self.current().declared.push(name.clone());
+
+        let mut functions = match struct_target.refcounted {
+            true => vec![
+                c_function(&format!("{}*", name), &format!("{}_acquire", name), vec![format!("const {}* self", name)], &deprecated),
+                c_function("void", &format!("{}_release", name), vec![format!("const {}* self", name)], &deprecated),
+            ],
+            false => vec![c_function("void", &format!("{}_destroy", name), vec![format!("{}* self", name)], &deprecated)],
+        };
+        if struct_target.builder {
+            functions.push(c_function(&format!("{}*", name), &format!("{}_new", name), vec![], &deprecated));
+            for accessor in struct_target.accessors().unwrap_or_default().iter() {
+                if let Ok(method) = Method::new(struct_target, accessor) {
+                    functions.push(self.function_declaration(&method, &struct_target.docs));
+                }
+            }
+        }
+        self.current().functions.push(functions.concat());
+    }
+
+    // C enums are int sized, so the enum is declared as its repr, with the variants as constants
+    fn on_enum(&mut self, enum_target: &Enum) {
+        let name = enum_target.exported_name();
+        let variants: Vec<String> = enum_target.variants.iter().map(|variant| {
+            let deprecated = self.deprecation(variant.docs.deprecated.as_ref());
+            format!("{}    {}_{}{} = {},\n", doxygen(&variant.docs, &[], "    "), name, variant.id, deprecated, variant.value)
+        }).collect();
+        let deprecated = self.deprecation(enum_target.docs.deprecated.as_ref());
+        let decl = format!("{}typedef {} {}{};\nenum {{\n{}}};\n",
+            doxygen(&enum_target.docs, &[], ""), enum_target.c_repr_type(), name, deprecated, variants.concat());
+        self.current().types.push(decl);
+        self.current().declared.push(name);
+    }
+
+    fn on_impl(&mut self, struct_target: &Struct, methods: &[Method]) {
+        for method in methods.iter() {
+            let decl = self.function_declaration(method, &struct_target.docs);
+            self.current().functions.push(decl);
+        }
+    }
+
+    fn on_trait(&mut self, struct_target: &Struct, trait_name: &Ident) {
+        let id = self.c_type(&struct_target.id.to_string());
+        let this = format!("const {}* self", id);
+        let (suffix, return_type, params) = match trait_name.to_string().as_str() {
+            "Clone" => ("clone", format!("{}*", id), vec![this]),
+            "PartialEq" => ("equals", String::from("bool"), vec![this, format!("const {}* other", id)]),
+            "Debug" => ("debug_string", String::from("char*"), vec![this]),
+            "Display" => ("to_string", String::from("char*"), vec![this]),
+            "Hash" => ("hash", String::from("uint64_t"), vec![this]),
+            _ => unreachable!("{} is not in EXPORTED_TRAITS", trait_name),
+        };
+        let name = format!("{}_{}", struct_target.exported_name(), suffix);
+        let deprecated = self.deprecation(struct_target.docs.deprecated.as_ref());
+        self.current().functions.push(c_function(&return_type, &name, params, &deprecated));
+    }
+
+    fn on_function(&mut self, method: &Method) {
+        let decl = self.function_declaration(method, &Docs::default());
+        self.current().functions.push(decl);
+    }
+
+    // #defines can't be deprecated, so they only have the @deprecated in their docs
+    fn on_const(&mut self, constant: &Constant) {
+        let name = constant.exported_name();
+        let deprecated = self.deprecation(constant.docs.deprecated.as_ref());
+        let decl = match (&constant.value, constant.is_mut) {
+            (Some(value), _) => format!("#define {} {}\n", name, value),
+            (None, false) => format!("extern const {}{};\n", c_declaration(&self.c_type(&constant.c_type), &name), deprecated),
+            (None, true) => format!("extern {}{};\n", c_declaration(&self.c_type(&constant.c_type), &name), deprecated),
+        };
+        let docs = doxygen(&constant.docs, &[], "");
+        self.current().constants.push(docs + &decl);
+    }
+
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        write_headers(&krate.name, &self.modules, output_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate;
+    use crate::scan::tests::write_crate;
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn headers_are_deterministic() {
+        let root = write_crate("headers_are_deterministic", &[
+            ("src/lib.rs", "mod io; mod auth;"),
+            ("src/io.rs", "#[crt_export] pub struct EventLoopGroup {}"),
+            ("src/auth.rs", "#[crt_export] pub struct Signer {}
+                #[crt_export] impl Signer {
+                    pub fn new(elg: &EventLoopGroup) -> Signer { Signer {} }
+                    pub fn elg(&self) -> &EventLoopGroup { unimplemented!() }
+                }"),
+        ]);
+        let include_dir = root.join("include");
+        let headers = generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        let names: Vec<String> = headers.iter().map(|h| h.strip_prefix(&include_dir).unwrap().display().to_string()).collect();
+        assert_eq!(vec!["crtrs/common.h", "crtrs/io.h", "crtrs/auth.h", "crtrs.h"], names);
+        let contents: Vec<String> = headers.iter().map(|h| read(h.clone())).collect();
+        let auth_h = &contents[2];
+        assert!(auth_h.contains("#include \"common.h\"\n#include \"io.h\"\n"));
+        assert!(auth_h.contains("Signer* Signer_new(const EventLoopGroup* elg);\n"));
+        assert!(auth_h.contains("const EventLoopGroup* Signer_elg(const Signer* this_);\n"));
+
+        let modified = fs::metadata(&headers[2]).unwrap().modified().unwrap();
+        assert_eq!(headers, generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap());
+        let regenerated: Vec<String> = headers.iter().map(|h| read(h.clone())).collect();
+        assert_eq!(contents, regenerated);
+        assert_eq!(modified, fs::metadata(&headers[2]).unwrap().modified().unwrap());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn removes_stale_headers() {
+        let root = write_crate("removes_stale_headers", &[
+            ("src/lib.rs", "mod io; #[crt_export] pub fn init() {}"),
+            ("src/io.rs", "#[crt_export] pub struct EventLoopGroup {}"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        assert!(include_dir.join("crtrs/io.h").is_file());
+
+        fs::write(root.join("src/io.rs"), "pub struct EventLoopGroup {}").unwrap();
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        assert!(!include_dir.join("crtrs/io.h").exists());
+        assert!(!read(include_dir.join("crtrs.h")).contains("io.h"));
+        assert!(read(include_dir.join("crtrs/lib.h")).contains("void init(void);\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn exported_type_names() {
+        let root = write_crate("exported_type_names", &[
+            ("src/lib.rs", "mod io; mod auth;"),
+            ("src/io.rs", "#[crt_export(prefix = \"aws_\", style = \"snake_case\")] pub struct EventLoopGroup {}
+                #[crt_export(prefix = \"aws_\")] #[repr(u8)] pub enum SocketDomain { Ipv4 }"),
+            ("src/auth.rs", "#[crt_export(prefix = \"aws_\", style = \"snake_case\")] #[derive(Clone)] pub struct Signer {}
+                #[crt_export(prefix = \"aws_\", style = \"snake_case\")] impl Signer {
+                    pub fn new(elg: &EventLoopGroup, domain: SocketDomain) -> Signer { Signer {} }
+                }"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        let io_h = read(include_dir.join("crtrs/io.h"));
+        assert!(io_h.contains("typedef struct aws_event_loop_group aws_event_loop_group;\n"));
+        assert!(io_h.contains("void aws_event_loop_group_destroy(aws_event_loop_group* self);\n"));
+        assert!(io_h.contains("typedef uint8_t aws_SocketDomain;\n"));
+        let auth_h = read(include_dir.join("crtrs/auth.h"));
+        assert!(auth_h.contains("#include \"io.h\"\n"));
+        assert!(auth_h.contains("aws_signer* aws_signer_new(const aws_event_loop_group* elg, aws_SocketDomain domain);\n"));
+        assert!(auth_h.contains("aws_signer* aws_signer_clone(const aws_signer* self);\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn enum_discriminants() {
+        let root = write_crate("enum_discriminants", &[
+            ("src/lib.rs", "#[crt_export] #[repr(u32)] pub enum Flags { A = 1 << 2, B, C = (0x10 | 3) - 1, D = 9 * 2 - 1 }"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        assert!(read(include_dir.join("crtrs/lib.h")).contains("enum {\n    Flags_A = 4,\n    Flags_B = 5,\n    Flags_C = 18,\n    Flags_D = 17,\n};\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn debug_and_display() {
+        let root = write_crate("debug_and_display", &[
+            ("src/lib.rs", "#[crt_export] #[derive(Debug)] pub struct Label {}
+                #[crt_export] impl std::fmt::Display for Label {}"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        let lib_h = read(include_dir.join("crtrs/lib.h"));
+        assert!(lib_h.contains("char* Label_debug_string(const Label* self);\n"));
+        assert!(lib_h.contains("char* Label_to_string(const Label* self);\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn callback_destroy() {
+        let root = write_crate("callback_destroy", &[
+            ("src/lib.rs", "#[crt_export] pub fn on_done(callback: Box<dyn FnOnce(i32) + Send>) {}"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        let lib_h = read(include_dir.join("crtrs/lib.h"));
+        assert!(lib_h.contains("void on_done(void (*callback)(int32_t, void*), void* callback_user_data, void (*callback_destroy)(void*));\n"));
+        assert!(lib_h.contains(" * @param callback_destroy Called with callback_user_data once callback will not be called again"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn docs_and_deprecations() {
+        let root = write_crate("docs_and_deprecations", &[
+            ("src/lib.rs", "
+                /// A point, in */ pixels
+                #[crt_export]
+                #[deprecated(note = \"Use \\\"Pos\\\"\")]
+                #[derive(Clone)]
+                pub struct Point {
+                    /// Across
+                    x: i32,
+                    #[deprecated]
+                    y: i32,
+                }
+
+                #[crt_export]
+                #[repr(u8)]
+                pub enum Shape {
+                    /// Round
+                    Circle = 1,
+                    #[deprecated = \"Use Circle\"]
+                    Oval = 2,
+                }
+
+                /// Adds
+                ///
+                /// # Arguments
+                ///
+                /// * `this` - The first
+                #[crt_export]
+                pub fn add(this: u32, other: u32) -> u32 { this + other }
+
+                /// The limit
+                #[crt_export]
+                #[deprecated(since = \"0.2\")]
+                pub static LIMIT: u32 = 1;"),
+        ]);
+        let include_dir = root.join("include");
+        generate("crtrs", &root.join("src/lib.rs"), &include_dir).unwrap();
+        let lib_h = read(include_dir.join("crtrs/lib.h"));
+        assert!(lib_h.contains("/**\n * A point, in * / pixels\n *\n * @deprecated Use \"Pos\"\n */\n\
+            typedef struct Point {\n    /**\n     * Across\n     */\n    int32_t x;\n    \
+            /**\n     * @deprecated\n     */\n    int32_t y CRTRS_DEPRECATED(\"\");\n} Point CRTRS_DEPRECATED(\"Use \\\"Pos\\\"\");\n"));
+        assert!(lib_h.contains("Point* Point_clone(const Point* self) CRTRS_DEPRECATED(\"Use \\\"Pos\\\"\");\n"));
+        assert!(lib_h.contains("    /**\n     * Round\n     */\n    Shape_Circle = 1,\n    \
+            /**\n     * @deprecated Use Circle\n     */\n    Shape_Oval CRTRS_DEPRECATED(\"Use Circle\") = 2,\n"));
+        assert!(lib_h.contains("/**\n * Adds\n *\n * @param this_ The first\n */\nuint32_t add(uint32_t this_, uint32_t other);\n"));
+        assert!(lib_h.contains("/**\n * The limit\n *\n * @deprecated Since 0.2\n */\nextern const uint32_t LIMIT CRTRS_DEPRECATED(\"Since 0.2\");\n"));
+        assert!(read(include_dir.join("crtrs/common.h")).contains("#define CRTRS_DEPRECATED(note) __attribute__((deprecated(note)))\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
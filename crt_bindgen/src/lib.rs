@@ -0,0 +1,216 @@
+//! The model of items exported with #[crt_export], shared by the crt_export macro, which
+//! generates the extern "C" shims from it, and the bindings generators, which scan the crate's
+//! sources at build time.
+
+pub mod abi;
+pub mod args;
+pub mod cpp;
+pub mod csharp;
+pub mod docs;
+pub mod header;
+pub mod manifest;
+pub mod plugin;
+pub mod python;
+pub mod scan;
+pub mod types;
+pub mod validate;
+
+use std::path::{Path, PathBuf};
+use syn::{Ident, ImplItem, ImplItemMethod, Item, ItemFn, ItemImpl, ItemStruct};
+
+use args::ExportArgs;
+use plugin::{run_plugin, Plugin, Registry};
+use scan::Crate;
+use types::{Constant, Enum, Method, Struct};
+
+/// Which bindings to generate for a crate, and where
+pub struct Config {
+    /// The name generated files are named after, e.g. crtrs for crtrs.h
+    pub crate_name: String,
+    pub lib_rs: PathBuf,
+    pub output_dir: PathBuf,
+    /// The names of the generators to run, from the Registry, e.g. ["c"]
+    pub generators: Vec<String>,
+}
+
+impl Config {
+    /// Generates the C headers, unless other generators are given
+    pub fn new(crate_name: &str, lib_rs: &Path, output_dir: &Path) -> Config {
+        Config {
+            crate_name: String::from(crate_name),
+            lib_rs: lib_rs.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            generators: vec![String::from("c")],
+        }
+    }
+
+    /// Replaces the generators, from a comma separated list, e.g. "c,python"
+    pub fn generators(mut self, generators: &str) -> Config {
+        self.generators = generators.split(',').map(str::trim).filter(|g| !g.is_empty()).map(String::from).collect();
+        self
+    }
+}
+
+/// Scans the crate rooted at lib_rs, and writes a header for each module with exports to
+/// <output_dir>/<crate_name>/<module>.h, plus <output_dir>/<crate_name>.h which includes them all.
+/// Returns the headers which were written.
+pub fn generate(crate_name: &str, lib_rs: &Path, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    generate_with(&Config::new(crate_name, lib_rs, output_dir), &Registry::default())
+}
+
+/// Scans the crate once, then runs each of the configured generators over it in turn, all writing
+/// to the output dir. Returns the files which were written, in the order the generators are given.
+pub fn generate_with(config: &Config, registry: &Registry) -> std::io::Result<Vec<PathBuf>> {
+    // Unknown generators are reported before anything is written
+    let mut plugins: Vec<Box<dyn Plugin>> = config.generators.iter()
+        .map(|name| registry.create(name))
+        .collect::<std::io::Result<_>>()?;
+    let krate = Crate::scan(&config.crate_name, &config.lib_rs)?;
+    std::fs::create_dir_all(&config.output_dir)?;
+    let mut written: Vec<PathBuf> = vec![];
+    for plugin in plugins.iter_mut() {
+        written.extend(run_plugin(plugin.as_mut(), &krate, &config.output_dir)?);
+    }
+    Ok(written)
+}
+
+/// An item marked with #[crt_export], as parsed from its tokens
+pub enum Target {
+    Struct(Struct),
+    Enum(Enum),
+    Impl(Vec<Method>),
+    Trait(Struct, Ident),
+    Function(Box<Method>),
+    Constant(Constant),
+}
+
+// Traits which are exported as a fixed function per struct, rather than as their methods
+pub const EXPORTED_TRAITS: [&str; 5] = ["Clone", "PartialEq", "Debug", "Display", "Hash"];
+
+/// The suffix of the function exported for one of EXPORTED_TRAITS, e.g. clone for {Struct}_clone
+pub fn trait_suffix(trait_name: &str) -> Option<&'static str> {
+    match trait_name {
+        "Clone" => Some("clone"),
+        "PartialEq" => Some("equals"),
+        "Debug" => Some("debug_string"),
+        "Display" => Some("to_string"),
+        "Hash" => Some("hash"),
+        _ => None,
+    }
+}
+
+/// Parses an item marked with #[crt_export(...)], given the args. #[crt_export] attributes on
+/// methods are removed from the item.
+pub fn parse_target(macro_target: &mut Item, export_args: &ExportArgs) -> Result<Target, syn::Error> {
+    match macro_target {
+        Item::Struct(struct_item) => {
+            export_args.allow_only(&["builder", "refcounted", "prefix", "name", "style"], "structs")?;
+            Ok(Target::Struct(parse_struct(struct_item, export_args)))
+        },
+        Item::Enum(enum_item) => {
+            export_args.allow_only(&["prefix", "name", "style"], "enums")?;
+            let mut enum_target = Enum::new(enum_item)?;
+            enum_target.naming = export_args.naming();
+            Ok(Target::Enum(enum_target))
+        },
+        Item::Impl(impl_item) => {
+            export_args.allow_only(&["prefix", "name", "style"], "impls")?;
+            parse_impl(impl_item, export_args)
+        },
+        Item::Fn(fn_item) => {
+            export_args.allow_only(&["prefix", "name"], "functions")?;
+            parse_function(fn_item, export_args)
+        },
+        Item::Const(const_item) => {
+            export_args.allow_only(&["prefix", "name"], "consts")?;
+            let mut constant = Constant::from_const(const_item)?;
+            constant.prefix = export_args.prefix.clone().unwrap_or_default();
+            constant.name = export_args.name.clone();
+            Ok(Target::Constant(constant))
+        },
+        Item::Static(static_item) => {
+            export_args.allow_only(&["prefix", "name"], "statics")?;
+            let mut constant = Constant::from_static(static_item)?;
+            constant.prefix = export_args.prefix.clone().unwrap_or_default();
+            constant.name = export_args.name.clone();
+            let exported_name = constant.exported_name();
+            static_item.attrs.push(syn::parse_quote! { #[export_name = #exported_name] });
+            Ok(Target::Constant(constant))
+        },
+        _ => Err(syn::Error::new_spanned(macro_target, "crt_export is only applicable to struct, enum, impl, fn, const or static"))
+    }
+}
+
+fn parse_struct(struct_item: &ItemStruct, export_args: &ExportArgs) -> Struct {
+    let mut struct_target = Struct::from_item(struct_item);
+    struct_target.naming = export_args.naming();
+    struct_target.builder = export_args.builder;
+    struct_target.refcounted = export_args.refcounted;
+    struct_target
+}
+
+// The impl must be given the same prefix/name/style as its struct, so that its methods are
+// named consistently with {Struct}_destroy
+fn parse_impl(impl_item: &mut ItemImpl, export_args: &ExportArgs) -> Result<Target, syn::Error> {
+    let mut methods: Vec<Method> = vec![];
+    let mut method_args: Vec<ExportArgs> = vec![];
+    for item in impl_item.items.iter_mut() {
+        if let ImplItem::Method(method) = item {
+            method_args.push(ExportArgs::take(&mut method.attrs)?);
+        }
+    }
+    let mut struct_target = Struct::new(types::impl_target(impl_item)?);
+    struct_target.naming = export_args.naming();
+    // Drop is run by the generated {Struct}_destroy, so there is nothing to export for it
+    if types::impl_trait_is(impl_item, "Drop") {
+        return Ok(Target::Impl(methods));
+    }
+    if let Some(trait_name) = types::impl_trait(impl_item) {
+        if EXPORTED_TRAITS.iter().any(|t| trait_name == t) {
+            return Ok(Target::Trait(struct_target, trait_name.clone()));
+        }
+    }
+    let impl_methods = impl_item.items.iter().filter_map(|item| match item {
+        ImplItem::Method(method) => Some(method),
+        _ => None,
+    });
+    let mut errors: Vec<syn::Error> = vec![];
+    for (method, args) in impl_methods.zip(method_args.iter()) {
+        if let Err(err) = args.allow_only(&["skip", "name"], "methods") {
+            errors.push(err);
+            continue;
+        }
+        if args.skip {
+            continue;
+        }
+        let method = validate::validate_method(method).and_then(|_| Method::new(&struct_target, method));
+        match method {
+            Ok(mut method) => {
+                method.name = args.name.clone();
+                methods.push(method);
+            },
+            Err(err) => errors.push(err),
+        }
+    }
+    validate::combine(errors)?;
+    validate::validate_names(&methods, &struct_target.generated_functions())?;
+    Ok(Target::Impl(methods))
+}
+
+// Free functions are exported as {prefix}{fn}, and are otherwise marshalled like static methods
+fn parse_function(fn_item: &ItemFn, export_args: &ExportArgs) -> Result<Target, syn::Error> {
+    let method = ImplItemMethod {
+        attrs: fn_item.attrs.clone(),
+        vis: fn_item.vis.clone(),
+        defaultness: None,
+        sig: fn_item.sig.clone(),
+        block: fn_item.block.as_ref().clone(),
+    };
+    validate::validate_method(&method)?;
+    let mut function = Method::new(&Struct::new(&fn_item.sig.ident), &method)?;
+    function.is_free = true;
+    function.name = Some(export_args.name.clone().unwrap_or_else(|| {
+        format!("{}{}", export_args.prefix.clone().unwrap_or_default(), fn_item.sig.ident)
+    }));
+    Ok(Target::Function(Box::new(function)))
+}
@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use crt_bindgen::plugin::Registry;
+use crt_bindgen::Config;
+
+// crtrs-bindgen [--generators c,...] <crate name> <src/lib.rs> <output dir>, which writes the same
+// headers as the crate's build script, e.g. to check them in or to package them, or the bindings
+// from any other registered generators
+fn main() {
+    let registry = Registry::default();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut generators: Option<String> = None;
+    if args.len() > 2 && args[1] == "--generators" {
+        generators = Some(args.remove(2));
+        args.remove(1);
+    }
+    if args.len() != 4 {
+        eprintln!("usage: {} [--generators {}] <crate name> <src/lib.rs> <output dir>", args[0], registry.names().join(","));
+        std::process::exit(2);
+    }
+    let mut config = Config::new(&args[1], Path::new(&args[2]), Path::new(&args[3]));
+    if let Some(generators) = generators {
+        config = config.generators(&generators);
+    }
+    match crt_bindgen::generate_with(&config, &registry) {
+        Ok(written) => written.iter().for_each(|file| println!("{}", file.display())),
+        Err(err) => {
+            eprintln!("Failed to generate bindings: {}", err);
+            std::process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,533 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use quote::ToTokens;
+use serde_json::{json, Map, Value};
+use syn::{Ident, Type};
+
+use crate::docs::Docs;
+use crate::header::write_if_changed;
+use crate::plugin::{visit_crate, Plugin};
+use crate::scan::{Crate, Module};
+use crate::types::{marshal_type, rename_c_type, returned_type, Marshal, MethodArg};
+use crate::{Constant, Enum, Struct, Method, Target};
+
+/// Bumped whenever the manifest changes in a way which breaks the generators reading it
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// The manifest of the crate, as written by the json generator
+pub fn describe(krate: &Crate) -> Value {
+    let mut exporter = ManifestExporter::new();
+    visit_crate(&mut exporter, krate);
+    exporter.manifest(krate)
+}
+
+/// The manifest as it is written to <crate_name>.json
+pub fn to_json(manifest: &Value) -> serde_json::Result<String> {
+    Ok(serde_json::to_string_pretty(manifest)? + "\n")
+}
+
+/// The exported functions which generated bindings report failures with. Generators only use those
+/// which the crate exports, so failures may only have a code.
+#[derive(Clone)]
+pub struct ErrorFunctions {
+    /// Returns the code of the last failure on the calling thread
+    pub last_error: String,
+    /// These return a static string for an error code
+    pub error_name: String,
+    pub error_str: String,
+}
+
+impl Default for ErrorFunctions {
+    fn default() -> ErrorFunctions {
+        ErrorFunctions {
+            last_error: String::from("CRT_last_error"),
+            error_name: String::from("CRT_error_name"),
+            error_str: String::from("CRT_error_str"),
+        }
+    }
+}
+
+pub(crate) fn is_exported(manifest: &Value, symbol: &str) -> bool {
+    items(manifest, "functions").any(|function| function["symbol"] == *symbol)
+}
+
+// The entries of an item, e.g. a module's "structs"
+pub(crate) fn list<'a>(item: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    item[key].as_array().into_iter().flatten()
+}
+
+// The items of a kind from every module, e.g. "functions"
+pub(crate) fn items<'a>(manifest: &'a Value, key: &'a str) -> impl Iterator<Item = &'a Value> {
+    list(manifest, "modules").flat_map(move |module| list(module, key))
+}
+
+pub(crate) fn str_of(value: &Value) -> String {
+    value.as_str().map(String::from).unwrap_or_default()
+}
+
+// Maps the symbol of each free function to its name in the bindings, which is its Rust name unless
+// another module's function already has it, in which case it is the symbol
+pub(crate) fn free_function_names(manifest: &Value, escape: fn(&str) -> String) -> HashMap<String, String> {
+    let mut taken = BTreeSet::new();
+    let mut names = HashMap::new();
+    for function in items(manifest, "functions").filter(|function| function["struct"].is_null()) {
+        let name = escape(&str_of(&function["name"]));
+        let name = match taken.insert(name.clone()) {
+            true => name,
+            false => str_of(&function["symbol"]),
+        };
+        names.insert(str_of(&function["symbol"]), name);
+    }
+    names
+}
+
+/// The json generator, which describes everything exported from the crate in
+/// <output_dir>/<crate_name>.json, so that bindings generators don't have to parse Rust. Keys are
+/// sorted and the file is only rewritten if it changes, so it can be checked in and diffed.
+///
+/// Each function lists the C params of each arg, and the ownership of each arg and of the return
+/// value, which is one of:
+/// * value: copied, e.g. integers, enums and raw pointers
+/// * borrowed: only valid for the call when passed, or until the owner is modified or released
+///   when returned
+/// * transferred: an arg handle which is consumed by the call, and must not be used afterwards
+/// * retained: a callback and its user_data, which are kept until the callback is dropped and
+///   the user_data passed to its destroy function
+/// * owned: a returned handle, string or buffer, which the caller must pass to its release function
+#[derive(Default)]
+pub struct ManifestExporter {
+    modules: Vec<Value>,
+    // The function which releases each handle type, e.g. EventLoopGroup -> EventLoopGroup_release
+    releases: HashMap<String, String>,
+    // The exported C name of each struct and enum, which C types are renamed to
+    c_names: HashMap<String, String>,
+}
+
+impl ManifestExporter {
+    pub fn new() -> ManifestExporter {
+        ManifestExporter::default()
+    }
+
+    fn manifest(&self, krate: &Crate) -> Value {
+        let mut manifest = Map::new();
+        manifest.insert(String::from("crate"), json!(krate.name));
+        manifest.insert(String::from("manifest_version"), json!(MANIFEST_VERSION));
+        manifest.insert(String::from("modules"), Value::Array(self.modules.clone()));
+        Value::Object(manifest)
+    }
+
+    fn c_type(&self, c_type: &str) -> String {
+        rename_c_type(c_type, &self.c_names)
+    }
+
+    // The entries of the current module, e.g. "structs"
+    fn section(&mut self, name: &str) -> &mut Vec<Value> {
+        let module = self.modules.last_mut().expect("Items are visited after their module");
+        module[name].as_array_mut().expect("Modules have a list per kind of item")
+    }
+
+    // Where an owned value is returned, the function which the caller releases it with
+    fn release_for(&self, ty: &Type, c_type: &str) -> Option<String> {
+        match marshal_type(ty) {
+            Marshal::Str | Marshal::String | Marshal::OptionStr => Some(String::from("crtrs_string_free")),
+            Marshal::Bytes => Some(String::from("crtrs_byte_buf_free")),
+            _ => self.releases.get(c_type.trim_start_matches("const ").trim_end_matches('*')).cloned(),
+        }
+    }
+
+    fn function(&self, kind: &str, method: &Method) -> Value {
+        let mut args: Vec<Value> = method.args.iter().map(|arg| self.arg(arg, &method.docs)).collect();
+        let returns = match &method.out {
+            // Result<T, E> returns the error code, and passes T back through out
+            Some(out) => {
+                let mut out_arg = self.arg(out, &method.docs);
+                out_arg["ownership"] = json!("owned");
+                out_arg["release"] = json!(self.release_for(&out.ty, self.c_type(&out.c_type).trim_end_matches('*')));
+                if method.is_constructor {
+                    out_arg["release"] = json!(self.releases.get(&self.c_type(&method.target.id.to_string())));
+                }
+                args.push(out_arg);
+                json!({ "c_type": "int", "ownership": "value", "nullable": false })
+            },
+            None => match returned_type(&method.method) {
+                Some(ty) if !method.is_fallible => {
+                    let c_return_type = self.c_type(&method.c_return_type);
+                    let marshal = match method.is_constructor {
+                        true => Marshal::Owned,
+                        false => marshal_type(ty),
+                    };
+                    // Returned strings are copied, so only &CStr is borrowed
+                    let ownership = match marshal {
+                        Marshal::CStr | Marshal::Slice | Marshal::Ref | Marshal::OptionRef | Marshal::OptionMut => "borrowed",
+                        Marshal::Value | Marshal::Array => "value",
+                        Marshal::FfiValue if !c_return_type.ends_with('*') => "value",
+                        _ => "owned",
+                    };
+                    let release = match ownership {
+                        "owned" => self.release_for(ty, &c_return_type),
+                        _ => None,
+                    };
+                    json!({
+                        "rust_type": rust_type(ty),
+                        "c_type": c_return_type,
+                        "marshal": format!("{:?}", marshal),
+                        "ownership": ownership,
+                        "nullable": c_return_type.ends_with('*'),
+                        "release": release,
+                    })
+                },
+                // Fallible functions without a value only return the error code
+                _ => json!({ "c_type": self.c_type(&method.c_return_type), "ownership": "value", "nullable": false }),
+            },
+        };
+        json!({
+            "kind": kind,
+            "name": method.method.sig.ident.to_string(),
+            "symbol": method.exported_name(),
+            "struct": match method.is_free {
+                true => Value::Null,
+                false => json!(method.target.id.to_string()),
+            },
+            "fallible": method.is_fallible,
+            "args": args,
+            "returns": returns,
+            "docs": docs(&method.docs),
+            "deprecated": method.docs.deprecated,
+        })
+    }
+
+    fn arg(&self, arg: &MethodArg, method_docs: &Docs) -> Value {
+        let ownership = match arg.marshal {
+            Marshal::Value | Marshal::Array => "value",
+            Marshal::FfiValue if !arg.c_type.ends_with('*') => "value",
+            Marshal::FfiValue | Marshal::Owned | Marshal::Boxed | Marshal::OptionBoxed | Marshal::OptionHandle => "transferred",
+            Marshal::Callback | Marshal::OptionCallback => "retained",
+            _ => "borrowed",
+        };
+        let c_params: Vec<Value> = arg.c_params().iter().map(|(name, c_type)| json!({ "name": name, "c_type": self.c_type(c_type) })).collect();
+        json!({
+            "name": arg.name,
+            "rust_type": rust_type(&arg.ty),
+            "c_type": self.c_type(&arg.c_type),
+            "c_params": c_params,
+            "marshal": format!("{:?}", arg.marshal),
+            "ownership": ownership,
+            "nullable": arg.marshal.is_nullable(),
+            "docs": method_docs.arg(&arg.name),
+        })
+    }
+}
+
+// The functions generated for every struct, which only take and return handles. Destroying or
+// releasing NULL does nothing, like free.
+fn handle_function(struct_target: &Struct, kind: &str, args: Vec<(&str, String, &str)>, returns: Value) -> Value {
+    let args: Vec<Value> = args.into_iter().map(|(name, c_type, ownership)| json!({
+        "name": name,
+        "c_type": c_type,
+        "c_params": [{ "name": name, "c_type": c_type }],
+        "ownership": ownership,
+        "nullable": kind == "destroy" || kind == "release",
+    })).collect();
+    json!({
+        "kind": kind,
+        "name": kind,
+        "symbol": format!("{}_{}", struct_target.exported_name(), kind),
+        "struct": struct_target.id.to_string(),
+        "fallible": false,
+        "args": args,
+        "returns": returns,
+        "docs": Value::Null,
+        "deprecated": struct_target.docs.deprecated,
+    })
+}
+
+fn returns_value(c_type: &str) -> Value {
+    json!({ "c_type": c_type, "ownership": "value", "nullable": false })
+}
+
+fn returns_owned(c_type: &str, release: &str) -> Value {
+    json!({ "c_type": c_type, "ownership": "owned", "nullable": true, "release": release })
+}
+
+// The doc comment, without the # Arguments section, whose descriptions are on the args
+fn docs(docs: &Docs) -> Value {
+    match docs.lines.is_empty() {
+        true => Value::Null,
+        false => json!(docs.lines.join("\n")),
+    }
+}
+
+// As written in Rust, e.g. Option<&str> rather than Option < & str >
+fn rust_type(ty: &Type) -> String {
+    let mut rust_type = ty.to_token_stream().to_string();
+    for (spaced, tight) in [(" < ", "<"), ("< ", "<"), (" >", ">"), ("& ", "&"), (" ,", ","), (" :: ", "::"), (":: ", "::"), (" (", "("), ("* ", "*"), ("[ ", "["), (" ]", "]"), (" ;", ";")].iter() {
+        rust_type = rust_type.replace(spaced, tight);
+    }
+    rust_type
+}
+
+impl Plugin for ManifestExporter {
+    fn on_module(&mut self, krate: &Crate, module: &Module) {
+        if self.modules.is_empty() {
+            self.c_names = krate.c_type_names();
+            for target in krate.modules.iter().flat_map(|module| module.targets.iter()) {
+                if let Target::Struct(struct_target) = target {
+                    let release = match struct_target.refcounted {
+                        true => format!("{}_release", struct_target.exported_name()),
+                        false => format!("{}_destroy", struct_target.exported_name()),
+                    };
+                    self.releases.insert(struct_target.exported_name(), release);
+                }
+            }
+        }
+        self.modules.push(json!({
+            "name": module.name(),
+            "path": module.path,
+            "structs": [],
+            "enums": [],
+            "functions": [],
+            "constants": [],
+        }));
+    }
+
+    fn on_struct(&mut self, struct_target: &Struct) {
+        let id = struct_target.id.to_string();
+        let c_fields: HashMap<String, String> = struct_target.c_fields().unwrap_or_default().into_iter()
+            .map(|(field, c_type)| (field.id.to_string(), self.c_type(&c_type)))
+            .collect();
+        let fields: Vec<Value> = struct_target.fields.iter().map(|field| json!({
+            "name": field.id.to_string(),
+            "rust_type": rust_type(&field.ty),
+            "c_type": c_fields.get(&field.id.to_string()),
+            "docs": docs(&field.docs),
+            "deprecated": field.docs.deprecated,
+        })).collect();
+        let handle = format!("{}*", struct_target.exported_name());
+        let this = format!("const {}*", struct_target.exported_name());
+        let kind = match (struct_target.refcounted, struct_target.builder, c_fields.is_empty()) {
+            (true, _, _) => "refcounted",
+            (_, true, _) => "builder",
+            (_, _, false) => "value",
+            _ => "opaque",
+        };
+        let entry = json!({
+            "name": id,
+            "c_name": struct_target.exported_name(),
+            "symbol_prefix": struct_target.exported_name(),
+            "kind": kind,
+            "fields": fields,
+            "traits": [],
+            "docs": docs(&struct_target.docs),
+            "deprecated": struct_target.docs.deprecated,
+        });
+        self.section("structs").push(entry);
+
+        let mut functions = match struct_target.refcounted {
+            true => vec![
+                handle_function(struct_target, "acquire", vec![("self", this.clone(), "borrowed")], returns_owned(&handle, &format!("{}_release", struct_target.exported_name()))),
+                handle_function(struct_target, "release", vec![("self", this, "transferred")], returns_value("void")),
+            ],
+            false => vec![handle_function(struct_target, "destroy", vec![("self", handle.clone(), "transferred")], returns_value("void"))],
+        };
+        if struct_target.builder {
+            functions.push(handle_function(struct_target, "new", vec![], returns_owned(&handle, &format!("{}_destroy", struct_target.exported_name()))));
+            for accessor in struct_target.accessors().unwrap_or_default().iter() {
+                if let Ok(method) = Method::new(struct_target, accessor) {
+                    functions.push(self.function("method", &method));
+                }
+            }
+        }
+        self.section("functions").extend(functions);
+    }
+
+    fn on_enum(&mut self, enum_target: &Enum) {
+        let variants: Vec<Value> = enum_target.variants.iter().map(|variant| json!({
+            "name": variant.id.to_string(),
+            "symbol": format!("{}_{}", enum_target.exported_name(), variant.id),
+            "value": variant.value,
+            "docs": docs(&variant.docs),
+            "deprecated": variant.docs.deprecated,
+        })).collect();
+        let entry = json!({
+            "name": enum_target.id.to_string(),
+            "c_name": enum_target.exported_name(),
+            "symbol_prefix": enum_target.exported_name(),
+            "c_type": enum_target.c_repr_type(),
+            "variants": variants,
+            "docs": docs(&enum_target.docs),
+            "deprecated": enum_target.docs.deprecated,
+        });
+        self.section("enums").push(entry);
+    }
+
+    fn on_impl(&mut self, _struct_target: &Struct, methods: &[Method]) {
+        for method in methods.iter() {
+            let kind = match (method.is_constructor, method.is_static) {
+                (true, _) => "constructor",
+                (_, true) => "static",
+                _ => "method",
+            };
+            let function = self.function(kind, method);
+            self.section("functions").push(function);
+        }
+    }
+
+    // The trait's function is listed with the struct's other functions, and the trait on the
+    // struct, which may be in another module if the trait is implemented by hand
+    fn on_trait(&mut self, struct_target: &Struct, trait_name: &Ident) {
+        let id = struct_target.id.to_string();
+        let c_name = self.c_type(&id);
+        let this = format!("const {}*", c_name);
+        let (kind, args, returns) = match trait_name.to_string().as_str() {
+            "Clone" => ("clone", vec![("self", this, "borrowed")], returns_owned(&format!("{}*", c_name), &self.releases.get(&c_name).cloned().unwrap_or_default())),
+            "PartialEq" => ("equals", vec![("self", this.clone(), "borrowed"), ("other", this, "borrowed")], returns_value("bool")),
+            "Debug" => ("debug_string", vec![("self", this, "borrowed")], returns_owned("char*", "crtrs_string_free")),
+            "Display" => ("to_string", vec![("self", this, "borrowed")], returns_owned("char*", "crtrs_string_free")),
+            "Hash" => ("hash", vec![("self", this, "borrowed")], returns_value("uint64_t")),
+            _ => unreachable!("{} is not in EXPORTED_TRAITS", trait_name),
+        };
+        let mut function = handle_function(struct_target, kind, args, returns);
+        function["trait"] = json!(trait_name.to_string());
+        self.section("functions").push(function);
+        for module in self.modules.iter_mut() {
+            let structs = module["structs"].as_array_mut().expect("Modules have a list of structs");
+            if let Some(entry) = structs.iter_mut().find(|entry| entry["name"] == json!(id)) {
+                entry["traits"].as_array_mut().expect("Structs have a list of traits").push(json!(trait_name.to_string()));
+            }
+        }
+    }
+
+    fn on_function(&mut self, function: &Method) {
+        let function = self.function("function", function);
+        self.section("functions").push(function);
+    }
+
+    fn on_const(&mut self, constant: &Constant) {
+        let entry = json!({
+            "name": constant.id.to_string(),
+            "symbol": constant.exported_name(),
+            "kind": match (&constant.value, constant.is_mut) {
+                (Some(_), _) => "const",
+                (None, false) => "static",
+                (None, true) => "static_mut",
+            },
+            "c_type": self.c_type(&constant.c_type),
+            "value": constant.value,
+            "docs": docs(&constant.docs),
+            "deprecated": constant.docs.deprecated,
+        });
+        self.section("constants").push(entry);
+    }
+
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let path = output_dir.join(format!("{}.json", krate.name));
+        write_if_changed(&path, &to_json(&self.manifest(krate))?)?;
+        Ok(vec![path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::Registry;
+    use crate::scan::tests::write_crate;
+    use crate::{generate_with, Config};
+    use std::fs;
+
+    fn function<'a>(module: &'a Value, symbol: &str) -> &'a Value {
+        module["functions"].as_array().unwrap().iter().find(|f| f["symbol"] == json!(symbol))
+            .unwrap_or_else(|| panic!("{} is not in the manifest", symbol))
+    }
+
+    #[test]
+    fn describes_exports() {
+        let root = write_crate("describes_exports", &[
+            ("src/lib.rs", "
+                /// Event loop threads
+                #[crt_export(refcounted)]
+                pub struct EventLoopGroup {}
+
+                #[crt_export]
+                impl EventLoopGroup {
+                    /// Starts the threads
+                    ///
+                    /// # Arguments
+                    ///
+                    /// * `name` - Names the threads
+                    pub fn new(name: Option<&str>, threads: u16) -> Result<EventLoopGroup, Error> {}
+                    pub fn name(&self) -> String {}
+                    pub fn label(&self) -> &str {}
+                    pub fn on_shutdown(&self, callback: Box<dyn FnOnce(i32) + Send>) {}
+                }
+
+                #[crt_export]
+                #[repr(u8)]
+                pub enum Shape { Circle = 1, #[deprecated] Square }
+
+                #[crt_export]
+                #[derive(Clone)]
+                pub struct Point { x: i32, y: i32 }
+
+                #[crt_export(prefix = \"crtrs_\")]
+                pub const VERSION: u32 = 1;"),
+        ]);
+        let output_dir = root.join("out");
+        let config = Config::new("crtrs", &root.join("src/lib.rs"), &output_dir).generators("json");
+        let written = generate_with(&config, &Registry::default()).unwrap();
+        assert_eq!(vec![output_dir.join("crtrs.json")], written);
+        let contents = fs::read_to_string(&written[0]).unwrap();
+        let manifest: Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(json!("crtrs"), manifest["crate"]);
+        assert_eq!(json!(MANIFEST_VERSION), manifest["manifest_version"]);
+        let lib = &manifest["modules"][0];
+        assert_eq!(json!("lib"), lib["name"]);
+
+        assert_eq!(json!({
+            "name": "EventLoopGroup", "c_name": "EventLoopGroup", "symbol_prefix": "EventLoopGroup", "kind": "refcounted",
+            "fields": [], "traits": [], "docs": "Event loop threads", "deprecated": null,
+        }), lib["structs"][0]);
+        assert_eq!(json!(["x", "y"]), json!(lib["structs"][1]["fields"].as_array().unwrap().iter().map(|f| f["name"].clone()).collect::<Vec<Value>>()));
+        assert_eq!(json!("value"), lib["structs"][1]["kind"]);
+        assert_eq!(json!(["Clone"]), lib["structs"][1]["traits"]);
+        assert_eq!(json!("Point_destroy"), function(lib, "Point_clone")["returns"]["release"]);
+
+        let new = function(lib, "EventLoopGroup_new");
+        assert_eq!(json!("constructor"), new["kind"]);
+        assert_eq!(json!(true), new["fallible"]);
+        assert_eq!(json!("Starts the threads"), new["docs"]);
+        assert_eq!(json!({
+            "name": "name", "rust_type": "Option<&str>", "c_type": "const char*",
+            "c_params": [{ "name": "name", "c_type": "const char*" }], "marshal": "OptionStr",
+            "ownership": "borrowed", "nullable": true, "docs": "Names the threads",
+        }), new["args"][0]);
+        assert_eq!(json!("out"), new["args"][2]["name"]);
+        assert_eq!(json!("owned"), new["args"][2]["ownership"]);
+        assert_eq!(json!("EventLoopGroup_release"), new["args"][2]["release"]);
+        assert_eq!(json!("int"), new["returns"]["c_type"]);
+
+        let name = function(lib, "EventLoopGroup_name");
+        assert_eq!(json!("this"), name["args"][0]["name"]);
+        assert_eq!(json!("borrowed"), name["args"][0]["ownership"]);
+        assert_eq!(json!("owned"), name["returns"]["ownership"]);
+        assert_eq!(json!("crtrs_string_free"), name["returns"]["release"]);
+        // Returned &str is copied too
+        assert_eq!(json!("crtrs_string_free"), function(lib, "EventLoopGroup_label")["returns"]["release"]);
+        let on_shutdown = function(lib, "EventLoopGroup_on_shutdown");
+        assert_eq!(json!("retained"), on_shutdown["args"][1]["ownership"]);
+        assert_eq!(json!(["callback", "callback_user_data", "callback_destroy"]), json!(on_shutdown["args"][1]["c_params"].as_array().unwrap().iter().map(|p| p["name"].clone()).collect::<Vec<Value>>()));
+        assert_eq!(json!("transferred"), function(lib, "EventLoopGroup_release")["args"][0]["ownership"]);
+
+        assert_eq!(json!("uint8_t"), lib["enums"][0]["c_type"]);
+        assert_eq!(json!({ "name": "Square", "symbol": "Shape_Square", "value": "2", "docs": null, "deprecated": "" }), lib["enums"][0]["variants"][1]);
+        assert_eq!(json!({
+            "name": "VERSION", "symbol": "crtrs_VERSION", "kind": "const", "c_type": "uint32_t",
+            "value": "1", "docs": null, "deprecated": null,
+        }), lib["constants"][0]);
+
+        // Unchanged manifests aren't rewritten, and keys are sorted so they diff cleanly
+        generate_with(&config, &Registry::default()).unwrap();
+        assert_eq!(contents, fs::read_to_string(&written[0]).unwrap());
+        assert!(contents.find("\"constants\"").unwrap() < contents.find("\"enums\"").unwrap());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use syn::Ident;
+
+use crate::scan::{Crate, Module};
+use crate::{Constant, Enum, Struct, Method, Target, EXPORTED_TRAITS};
+
+/// A bindings generator. It is given each module of the crate in turn, followed by the module's
+/// exported items in source order, and then writes its output in on_finish. Everything but
+/// on_finish does nothing by default, so generators only handle the items they need.
+pub trait Plugin {
+    /// Called before the module's items, e.g. for generators which write a file per module
+    fn on_module(&mut self, _krate: &Crate, _module: &Module) {}
+    fn on_struct(&mut self, _struct_target: &Struct) {}
+    fn on_enum(&mut self, _enum_target: &Enum) {}
+    /// The methods of an impl, and the struct they are implemented for. That is the exported
+    /// struct where there is one, so that its fields and args are available.
+    fn on_impl(&mut self, _struct_target: &Struct, _methods: &[Method]) {}
+    /// One of EXPORTED_TRAITS, derived or implemented for the struct
+    fn on_trait(&mut self, _struct_target: &Struct, _trait_name: &Ident) {}
+    fn on_function(&mut self, _function: &Method) {}
+    fn on_const(&mut self, _constant: &Constant) {}
+    /// Writes the bindings to output_dir, and returns the files which make them up
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> io::Result<Vec<PathBuf>>;
+}
+
+/// Runs the plugin over every exported item in the crate, then finishes it
+pub fn run_plugin(plugin: &mut dyn Plugin, krate: &Crate, output_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    visit_crate(plugin, krate);
+    plugin.on_finish(krate, output_dir)
+}
+
+/// Gives the plugin every exported item in the crate, without finishing it
+pub fn visit_crate(plugin: &mut dyn Plugin, krate: &Crate) {
+    for module in krate.modules.iter() {
+        plugin.on_module(krate, module);
+        module.targets.iter().for_each(|target| visit_target(plugin, krate, target));
+    }
+}
+
+// Plugins are only given targets which parsed successfully, as the macro reports the errors
+fn visit_target(plugin: &mut dyn Plugin, krate: &Crate, target: &Target) {
+    // Impls only know their struct's name, so are given the struct's definition where it's exported
+    let resolve = |struct_target: &Struct| krate.find_struct(&struct_target.id).cloned().unwrap_or_else(|| struct_target.clone());
+    match target {
+        Target::Struct(struct_target) => {
+            plugin.on_struct(struct_target);
+            struct_target.derives.iter()
+                .filter(|derive| EXPORTED_TRAITS.iter().any(|t| *derive == t))
+                .for_each(|derive| plugin.on_trait(struct_target, derive));
+        },
+        Target::Enum(enum_target) => plugin.on_enum(enum_target),
+        Target::Impl(methods) => {
+            // Drop impls have no methods to export, so nothing to be implemented for
+            if let Some(method) = methods.first() {
+                plugin.on_impl(&resolve(&method.target), methods);
+            }
+        },
+        Target::Trait(struct_target, trait_name) => plugin.on_trait(&resolve(struct_target), trait_name),
+        Target::Function(function) => plugin.on_function(function),
+        Target::Constant(constant) => plugin.on_const(constant),
+    }
+}
+
+type Factory = Box<dyn Fn() -> Box<dyn Plugin>>;
+
+/// The generators which can be enabled by name, e.g. from the command line or a build script
+pub struct Registry {
+    factories: BTreeMap<String, Factory>,
+}
+
+impl Registry {
+    /// A registry without any generators, for projects which only want their own
+    pub fn empty() -> Registry {
+        Registry { factories: BTreeMap::new() }
+    }
+
+    /// Adds a generator, replacing any existing generator with the same name
+    pub fn register<F: Fn() -> Box<dyn Plugin> + 'static>(&mut self, name: &str, factory: F) {
+        self.factories.insert(String::from(name), Box::new(factory));
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    pub fn create(&self, name: &str) -> io::Result<Box<dyn Plugin>> {
+        match self.factories.get(name) {
+            Some(factory) => Ok(factory()),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Unknown generator {}, expected one of {}", name, self.names().join(", ")))),
+        }
+    }
+}
+
+/// The generators which are built in: c, which writes a C header per module, json, which
+/// describes the exports for generators which aren't built in, python, which writes a ctypes
+/// module, cpp, which writes C++ RAII wrappers over the c generator's header, and csharp, which
+/// writes P/Invoke bindings
+impl Default for Registry {
+    fn default() -> Registry {
+        let mut registry = Registry::empty();
+        registry.register("c", || Box::new(crate::header::HeaderExporter::new()));
+        registry.register("json", || Box::new(crate::manifest::ManifestExporter::new()));
+        registry.register("python", || Box::new(crate::python::PythonExporter::new()));
+        registry.register("cpp", || Box::new(crate::cpp::CppExporter::new()));
+        registry.register("csharp", || Box::new(crate::csharp::CSharpExporter::new()));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::tests::write_crate;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    // Records each callback as a line, e.g. "impl Point(x, y): Point_new"
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Plugin for Recorder {
+        fn on_module(&mut self, _krate: &Crate, module: &Module) {
+            self.events.borrow_mut().push(format!("module {}", module.name()));
+        }
+
+        fn on_impl(&mut self, struct_target: &Struct, methods: &[Method]) {
+            let fields: Vec<String> = struct_target.fields.iter().map(|f| f.id.to_string()).collect();
+            let methods: Vec<String> = methods.iter().map(|m| m.exported_name()).collect();
+            self.events.borrow_mut().push(format!("impl {}({}): {}", struct_target.id, fields.join(", "), methods.join(", ")));
+        }
+
+        fn on_trait(&mut self, struct_target: &Struct, trait_name: &Ident) {
+            self.events.borrow_mut().push(format!("trait {} for {}", trait_name, struct_target.id));
+        }
+
+        fn on_const(&mut self, constant: &Constant) {
+            self.events.borrow_mut().push(format!("const {}", constant.exported_name()));
+        }
+
+        fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> io::Result<Vec<PathBuf>> {
+            self.events.borrow_mut().push(format!("finish {}", krate.name));
+            let file = output_dir.join("events.txt");
+            fs::write(&file, self.events.borrow().join("\n"))?;
+            Ok(vec![file])
+        }
+    }
+
+    #[test]
+    fn runs_registered_plugins() {
+        let root = write_crate("runs_registered_plugins", &[
+            ("src/lib.rs", "mod geometry;
+                #[crt_export] pub const ORIGIN: i32 = 0;"),
+            ("src/geometry.rs", "#[crt_export] #[derive(Clone)] pub struct Point { x: i32, y: i32 }
+                #[crt_export] impl Point { pub fn new(x: i32, y: i32) -> Point { Point { x, y } } }
+                #[crt_export] impl PartialEq for Point { fn eq(&self, other: &Point) -> bool { true } }
+                #[crt_export] impl Drop for Point { fn drop(&mut self) {} }"),
+        ]);
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut registry = Registry::empty();
+        let recorded = events.clone();
+        registry.register("recorder", move || Box::new(Recorder { events: recorded.clone() }));
+        assert_eq!(vec!["recorder"], registry.names());
+        assert!(registry.create("c").is_err());
+
+        let krate = Crate::scan("geometry", &root.join("src/lib.rs")).unwrap();
+        let output_dir = root.join("out");
+        fs::create_dir_all(&output_dir).unwrap();
+        let written = run_plugin(registry.create("recorder").unwrap().as_mut(), &krate, &output_dir).unwrap();
+        assert_eq!(vec![output_dir.join("events.txt")], written);
+        assert_eq!(vec![
+            "module lib",
+            "const ORIGIN",
+            "module geometry",
+            "trait Clone for Point",
+            "impl Point(x, y): Point_new",
+            "trait PartialEq for Point",
+            "finish geometry",
+        ], *events.borrow());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,738 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::header::write_if_changed;
+use crate::manifest::{self, free_function_names, is_exported, items, list, str_of, ErrorFunctions};
+use crate::plugin::Plugin;
+use crate::scan::Crate;
+
+// Python keywords which are valid Rust identifiers, and so may be used as names
+const RESERVED_NAMES: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "class", "def", "del",
+    "elif", "except", "finally", "from", "global", "import", "is", "lambda", "nonlocal", "not",
+    "or", "pass", "raise", "try", "with", "yield",
+];
+
+/// The python generator, which writes <output_dir>/<crate_name>.py, a ctypes module for the
+/// crate's cdylib. Each exported struct is a class holding a handle, which is released when the
+/// object is closed, leaves a with block or is garbage collected. Strings are passed as UTF-8,
+/// byte slices as bytes, and failures are raised as <module>.Error from the error functions.
+#[derive(Default)]
+pub struct PythonExporter {
+    errors: ErrorFunctions,
+}
+
+impl PythonExporter {
+    pub fn new() -> PythonExporter {
+        PythonExporter::default()
+    }
+
+    pub fn errors(mut self, errors: ErrorFunctions) -> PythonExporter {
+        self.errors = errors;
+        self
+    }
+
+    fn module(&self, crate_name: &str, manifest: &Value) -> String {
+        let types = Types::new(manifest);
+        let functions: Vec<&Value> = items(manifest, "functions").collect();
+        let error_name = match is_exported(manifest, &self.errors.error_name) {
+            true => format!("_lib.{}(code).decode(\"utf-8\")", self.errors.error_name),
+            false => String::from("None"),
+        };
+        let error_message = match is_exported(manifest, &self.errors.error_str) {
+            true => format!("\"{{}}: {{}}\".format(self.name, _lib.{}(code).decode(\"utf-8\"))", self.errors.error_str),
+            false => String::from("\"error {}\".format(code)"),
+        };
+        let last_error = match is_exported(manifest, &self.errors.last_error) {
+            true => format!("_lib.{}()", self.errors.last_error),
+            false => String::from("-1"),
+        };
+
+        let mut sections = vec![];
+        for module in manifest["modules"].as_array().into_iter().flatten() {
+            let mut section = vec![];
+            for item in list(module, "enums") {
+                section.push(types.enum_class(item));
+            }
+            for item in list(module, "structs").filter(|item| item["kind"] == "value") {
+                section.push(types.fields_structure(item));
+            }
+            let constants: Vec<String> = list(module, "constants").map(|item| types.constant(item)).collect();
+            if !constants.is_empty() {
+                section.push(constants.concat());
+            }
+            let declarations: Vec<String> = list(module, "functions").map(|function| types.declaration(function)).collect();
+            if !declarations.is_empty() {
+                section.push(declarations.concat());
+            }
+            for item in list(module, "structs") {
+                let name = str_of(&item["name"]);
+                let methods: Vec<&Value> = functions.iter().copied().filter(|function| function["struct"] == *name).collect();
+                section.push(types.handle_class(item, &methods));
+            }
+            for function in list(module, "functions").filter(|function| function["struct"].is_null()) {
+                section.push(types.wrapper(function, &types.free_name(function), Receiver::None, ""));
+            }
+            if !section.is_empty() {
+                sections.push(format!("# {}\n\n{}", module_path(crate_name, module), section.join("\n\n")));
+            }
+        }
+        format!(
+"# Generated by crt_export from the {crate_name} crate, do not edit
+\"\"\"ctypes bindings for the {crate_name} library
+
+The library is loaded from ${env}, or from next to this module, or else from wherever
+ctypes.util.find_library finds it. Exported structs are classes which release their handle when
+closed, at the end of a with block, or when garbage collected.
+\"\"\"
+import ctypes
+import ctypes.util
+import enum
+import itertools
+import os
+import sys
+import warnings
+
+
+def _load():
+    path = os.environ.get(\"{env}\")
+    if path is None:
+        name = {{\"darwin\": \"lib{lib}.dylib\", \"win32\": \"{lib}.dll\"}}.get(sys.platform, \"lib{lib}.so\")
+        path = os.path.join(os.path.dirname(os.path.abspath(__file__)), name)
+        if not os.path.exists(path):
+            path = ctypes.util.find_library(\"{lib}\")
+    if path is None:
+        raise ImportError(\"Cannot find the {lib} library, set {env} to its path\")
+    return ctypes.CDLL(path)
+
+
+_lib = _load()
+
+
+def _declare(symbol, restype, *argtypes):
+    function = getattr(_lib, symbol)
+    function.restype = restype
+    function.argtypes = argtypes
+
+
+class CByteCursor(ctypes.Structure):
+    \"\"\"Bytes borrowed from the library, valid until the owner is modified or released\"\"\"
+    _fields_ = [(\"len\", ctypes.c_size_t), (\"ptr\", ctypes.c_void_p)]
+
+    def to_bytes(self):
+        return ctypes.string_at(self.ptr, self.len) if self.ptr else b\"\"
+
+
+class CByteBuf(ctypes.Structure):
+    \"\"\"Bytes owned by the caller, which must be released with crtrs_byte_buf_free\"\"\"
+    _fields_ = [(\"len\", ctypes.c_size_t), (\"ptr\", ctypes.c_void_p), (\"capacity\", ctypes.c_size_t)]
+
+
+_declare(\"crtrs_string_free\", None, ctypes.c_void_p)
+_declare(\"crtrs_byte_buf_free\", None, CByteBuf)
+
+
+class Error(Exception):
+    \"\"\"A failure reported by the library, with its error code and name\"\"\"
+
+    def __init__(self, code):
+        self.code = code
+        self.name = {error_name}
+        super().__init__({error_message})
+
+
+def _raise_last_error():
+    raise Error({last_error})
+
+
+def _check(result):
+    if result != 0:
+        _raise_last_error()
+
+
+def _str(value):
+    if isinstance(value, str):
+        return value.encode(\"utf-8\")
+    return value
+
+
+# Copies a string returned by the library, and frees it. NULL is a failure unless the string is
+# optional.
+def _string(pointer, nullable):
+    if not pointer:
+        if nullable:
+            return None
+        _raise_last_error()
+    try:
+        return ctypes.string_at(pointer).decode(\"utf-8\")
+    finally:
+        _lib.crtrs_string_free(pointer)
+
+
+def _bytes(buf):
+    try:
+        return ctypes.string_at(buf.ptr, buf.len) if buf.ptr else b\"\"
+    finally:
+        _lib.crtrs_byte_buf_free(buf)
+
+
+# A view of a bytearray for the library to write into
+def _buffer(value):
+    return (ctypes.c_char * len(value)).from_buffer(value)
+
+
+def _array(array_type, values):
+    if isinstance(values, array_type):
+        return values
+    if issubclass(array_type._type_, ctypes.Array):
+        return array_type(*(_array(array_type._type_, value) for value in values))
+    return array_type(*values)
+
+
+# The library may call callbacks at any time, so they are kept until the library destroys their
+# user_data, which is the key they are kept under. They are given the raw C args, without user_data.
+_callbacks = {{}}
+_callback_keys = itertools.count(1)
+
+
+def _callback(function_type, callback):
+    if callback is None:
+        return None, None
+    key = next(_callback_keys)
+    _callbacks[key] = function_type(lambda *args: callback(*args[:-1]))
+    return _callbacks[key], key
+
+
+@ctypes.CFUNCTYPE(None, ctypes.c_void_p)
+def _destroy_callback(user_data):
+    del _callbacks[user_data]
+
+
+class _Handle:
+    \"\"\"A handle to an exported struct. Handles borrowed from another object keep it alive, and
+    are not released.\"\"\"
+    _release = None
+
+    def __init__(self, handle, owner=None):
+        self._handle = handle
+        self._owner = owner
+
+    @property
+    def _as_parameter_(self):
+        if self._handle is None:
+            raise ValueError(\"{{}} is closed\".format(type(self).__name__))
+        return self._handle
+
+    def close(self):
+        handle, self._handle = getattr(self, \"_handle\", None), None
+        if handle is not None and self._owner is None and self._release is not None:
+            self._release(handle)
+        self._owner = None
+
+    def __del__(self):
+        self.close()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc_info):
+        self.close()
+
+
+# Passing the wrong struct to the library is undefined, so handle args are checked
+def _handle(value, cls, nullable):
+    if value is None and nullable:
+        return None
+    if not isinstance(value, cls):
+        raise TypeError(\"Expected {{}}, got {{}}\".format(cls.__name__, type(value).__name__))
+    return value._as_parameter_
+
+
+# Forgets a handle which was transferred to the library, which now releases it
+def _detach(value):
+    if value is not None:
+        value._handle = None
+
+
+# NULL is a failure unless the handle is optional
+def _wrap(cls, handle, nullable, owner=None):
+    if not handle:
+        if nullable:
+            return None
+        _raise_last_error()
+    return cls(handle, owner)
+
+
+{sections}", crate_name = crate_name, env = format!("{}_LIBRARY", crate_name.to_uppercase().replace('-', "_")),
+            lib = crate_name.replace('-', "_"), error_name = error_name, error_message = error_message,
+            last_error = last_error, sections = sections.join("\n\n"))
+    }
+}
+
+// How a wrapper is given the handle its function is called on
+#[derive(Clone, Copy, PartialEq)]
+enum Receiver {
+    // A free function, or a static method
+    None,
+    // A constructor, which is a classmethod
+    Class,
+    // A method, whose first arg is self
+    Instance,
+}
+
+// The exported structs and enums which C types refer to, e.g. EventLoopGroup* is a handle
+struct Types {
+    // The C name of each struct to its kind, e.g. refcounted
+    structs: HashMap<String, String>,
+    // The C name of each enum to the C type it is passed as
+    enums: HashMap<String, String>,
+    // The C name of each struct and enum to its name in the bindings
+    names: HashMap<String, String>,
+    // Free function symbol to name
+    free_names: HashMap<String, String>,
+}
+
+impl Types {
+    fn new(manifest: &Value) -> Types {
+        Types {
+            structs: items(manifest, "structs").map(|item| (str_of(&item["c_name"]), str_of(&item["kind"]))).collect(),
+            enums: items(manifest, "enums").map(|item| (str_of(&item["c_name"]), str_of(&item["c_type"]))).collect(),
+            names: items(manifest, "structs").chain(items(manifest, "enums")).map(|item| (str_of(&item["c_name"]), str_of(&item["name"]))).collect(),
+            free_names: free_function_names(manifest, py_name),
+        }
+    }
+
+    fn free_name(&self, function: &Value) -> String {
+        self.free_names[&str_of(&function["symbol"])].clone()
+    }
+
+    // The struct a handle type points to, e.g. EventLoopGroup for const EventLoopGroup*
+    fn handle_struct(&self, c_type: &str) -> Option<&str> {
+        let pointee = c_type.strip_suffix('*')?.trim_start_matches("const ").trim();
+        match self.structs.contains_key(pointee) {
+            true => self.names.get(pointee).map(String::as_str),
+            false => None,
+        }
+    }
+
+    // The ctypes type of a C type, as a Python expression
+    fn ctype(&self, c_type: &str) -> String {
+        let c_type = c_type.trim();
+        if let Some(idx) = c_type.find("(*)") {
+            let params = c_type[idx + 3..].trim().trim_start_matches('(').trim_end_matches(')');
+            let mut types = vec![self.ctype(&c_type[..idx])];
+            types.extend(params.split(',').map(str::trim).filter(|param| !param.is_empty()).map(|param| self.ctype(param)));
+            return format!("ctypes.CFUNCTYPE({})", types.join(", "));
+        }
+        // uint8_t[2][4] is two arrays of 4
+        if let Some(idx) = c_type.find('[') {
+            return c_type[idx..].split(['[', ']']).filter(|len| !len.is_empty()).rev()
+                .fold(self.ctype(&c_type[..idx]), |elem, len| format!("({} * {})", elem, len));
+        }
+        if let Some(pointee) = c_type.strip_suffix('*') {
+            let pointee = pointee.trim().trim_end_matches(" const");
+            return match (pointee, pointee.trim_start_matches("const ")) {
+                // Returned strings are freed, so must be kept as a pointer
+                ("const char", _) | ("const uint8_t", _) => String::from("ctypes.c_char_p"),
+                (_, "char") | (_, "uint8_t") | (_, "void") => String::from("ctypes.c_void_p"),
+                (_, base) if self.structs.contains_key(base) => String::from("ctypes.c_void_p"),
+                (_, base) => format!("ctypes.POINTER({})", self.ctype(base)),
+            };
+        }
+        let ctype = match c_type {
+            "void" => "None",
+            "bool" => "ctypes.c_bool",
+            "char" => "ctypes.c_char",
+            "signed char" | "int8_t" => "ctypes.c_int8",
+            "unsigned char" | "uint8_t" => "ctypes.c_uint8",
+            "short" => "ctypes.c_short",
+            "unsigned short" => "ctypes.c_ushort",
+            "int" => "ctypes.c_int",
+            "unsigned int" => "ctypes.c_uint",
+            "long" => "ctypes.c_long",
+            "unsigned long" => "ctypes.c_ulong",
+            "long long" => "ctypes.c_longlong",
+            "unsigned long long" => "ctypes.c_ulonglong",
+            "int16_t" => "ctypes.c_int16",
+            "uint16_t" => "ctypes.c_uint16",
+            "int32_t" => "ctypes.c_int32",
+            "uint32_t" => "ctypes.c_uint32",
+            "int64_t" => "ctypes.c_int64",
+            "uint64_t" => "ctypes.c_uint64",
+            "size_t" | "uintptr_t" => "ctypes.c_size_t",
+            "intptr_t" => "ctypes.c_ssize_t",
+            "float" => "ctypes.c_float",
+            "double" => "ctypes.c_double",
+            "CByteCursor" | "CByteBuf" => c_type,
+            _ => match self.enums.get(c_type) {
+                Some(repr) => return self.ctype(repr),
+                None => return format!("_{}Fields", self.names.get(c_type).map_or(c_type, String::as_str)),
+            },
+        };
+        String::from(ctype)
+    }
+
+    // Arrays are passed as a pointer to their first element
+    fn param_ctype(&self, c_type: &str) -> String {
+        match (c_type.find('['), c_type.find(']')) {
+            (Some(start), Some(end)) => format!("ctypes.POINTER({})", self.ctype(&format!("{}{}", &c_type[..start], &c_type[end + 1..]))),
+            _ => self.ctype(c_type),
+        }
+    }
+
+    fn declaration(&self, function: &Value) -> String {
+        let mut params = vec![format!("\"{}\"", str_of(&function["symbol"])), self.ctype(&str_of(&function["returns"]["c_type"]))];
+        for param in function["args"].as_array().into_iter().flatten().flat_map(|arg| list(arg, "c_params")) {
+            params.push(self.param_ctype(&str_of(&param["c_type"])));
+        }
+        format!("_declare({})\n", params.join(", "))
+    }
+
+    fn enum_class(&self, item: &Value) -> String {
+        let mut lines = vec![format!("class {}(enum.IntEnum):", item["name"].as_str().unwrap_or_default())];
+        lines.extend(docstring(&item["docs"], &[], "    "));
+        for variant in list(item, "variants") {
+            lines.push(format!("    {} = {}", py_name(&str_of(&variant["name"])), str_of(&variant["value"])));
+        }
+        if lines.len() == 1 {
+            lines.push(String::from("    pass"));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // Structs whose fields are visible to C are read through this
+    fn fields_structure(&self, item: &Value) -> String {
+        let fields: Vec<String> = list(item, "fields")
+            .map(|field| format!("(\"{}\", {})", str_of(&field["name"]), self.ctype(&str_of(&field["c_type"]))))
+            .collect();
+        format!("class _{}Fields(ctypes.Structure):\n    _fields_ = [{}]\n", str_of(&item["name"]), fields.join(", "))
+    }
+
+    fn constant(&self, item: &Value) -> String {
+        let name = py_name(&str_of(&item["name"]));
+        let symbol = str_of(&item["symbol"]);
+        let docs: Vec<String> = item["docs"].as_str().into_iter().flat_map(str::lines).map(|line| format!("# {}\n", line).replace("# \n", "#\n")).collect();
+        let value = match (item["kind"].as_str(), item["value"].as_str()) {
+            (_, Some("true")) => String::from("True"),
+            (_, Some("false")) => String::from("False"),
+            (_, Some(value)) => String::from(value),
+            (Some("static"), None) => format!("{}.in_dll(_lib, \"{}\").value", self.ctype(&str_of(&item["c_type"])), symbol),
+            // Mutable statics may change, so are read when they are needed
+            _ => return format!("{}# {} is mutable, read it with {}.in_dll(_lib, \"{}\")\n", docs.concat(), symbol, self.ctype(&str_of(&item["c_type"])), symbol),
+        };
+        format!("{}{} = {}\n", docs.concat(), name, value)
+    }
+
+    fn handle_class(&self, item: &Value, functions: &[&Value]) -> String {
+        let name = str_of(&item["name"]);
+        let mut lines = vec![format!("class {}(_Handle):", name)];
+        lines.extend(docstring(&item["docs"], &[], "    "));
+        let release = functions.iter().find(|function| function["kind"] == "release" || function["kind"] == "destroy");
+        if let Some(release) = release {
+            lines.push(format!("    _release = _lib.{}", str_of(&release["symbol"])));
+        }
+        let mut body = vec![];
+        for function in functions.iter() {
+            let method_name = py_name(&str_of(&function["name"]));
+            let method_name = match method_name.as_str() {
+                "close" => String::from("close_"),
+                _ => method_name,
+            };
+            let wrapper = match (str_of(&function["kind"]).as_str(), function["trait"].as_str()) {
+                ("release", _) | ("destroy", _) => continue,
+                ("equals", _) => self.wrapper(function, "__eq__", Receiver::Instance,
+                    &format!("if not isinstance(other, {}):\n    return NotImplemented\n", name)),
+                ("hash", _) => self.wrapper(function, "__hash__", Receiver::Instance, ""),
+                ("debug_string", _) => self.wrapper(function, "__repr__", Receiver::Instance, ""),
+                ("to_string", _) => self.wrapper(function, "__str__", Receiver::Instance, ""),
+                ("constructor", _) | ("new", _) => format!("@classmethod\n{}", self.wrapper(function, &method_name, Receiver::Class, "")),
+                ("static", _) => format!("@staticmethod\n{}", self.wrapper(function, &method_name, Receiver::None, "")),
+                _ => self.wrapper(function, &method_name, Receiver::Instance, ""),
+            };
+            body.push(wrapper);
+        }
+        for field in list(item, "fields").filter(|_| item["kind"] == "value") {
+            let field_name = str_of(&field["name"]);
+            body.push(format!("@property\ndef {}(self):\n    return ctypes.cast(self._as_parameter_, ctypes.POINTER(_{}Fields)).contents.{}\n",
+                py_name(&field_name), name, field_name));
+        }
+        for wrapper in body.iter() {
+            lines.push(String::new());
+            lines.extend(wrapper.lines().map(|line| match line.is_empty() {
+                true => String::new(),
+                false => format!("    {}", line),
+            }));
+        }
+        if lines.len() == 1 {
+            lines.push(String::from("    pass"));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // A def which converts its args to C, calls the function and converts the result back. guard
+    // is run before anything else.
+    fn wrapper(&self, function: &Value, name: &str, receiver: Receiver, guard: &str) -> String {
+        let symbol = str_of(&function["symbol"]);
+        let fallible = function["fallible"].as_bool().unwrap_or(false);
+        let args: Vec<&Value> = list(function, "args").collect();
+        // The Python params besides self or cls, and whether they may be None
+        let mut params: Vec<(String, bool)> = vec![];
+        let mut prelude: Vec<String> = vec![];
+        let mut call_args: Vec<String> = vec![];
+        let mut post: Vec<String> = vec![];
+        let mut out: Option<&Value> = None;
+        if let Some(note) = function["deprecated"].as_str() {
+            let warning = match note.is_empty() {
+                true => format!("{} is deprecated", symbol),
+                false => format!("{} is deprecated: {}", symbol, note),
+            };
+            prelude.push(format!("warnings.warn({}, DeprecationWarning, stacklevel=2)", py_string(&warning)));
+        }
+        for (idx, arg) in args.iter().enumerate() {
+            let c_type = str_of(&arg["c_type"]);
+            let nullable = arg["nullable"].as_bool().unwrap_or(false);
+            let marshal = arg["marshal"].as_str().unwrap_or_default();
+            if fallible && arg["ownership"] == "owned" {
+                prelude.push(format!("out = {}()", self.ctype(c_type.strip_suffix('*').unwrap_or(&c_type))));
+                call_args.push(String::from("ctypes.byref(out)"));
+                out = Some(arg);
+                continue;
+            }
+            let param = match (receiver, idx) {
+                (Receiver::Instance, 0) => String::from("self"),
+                _ => {
+                    let param = py_name(&str_of(&arg["name"]));
+                    params.push((param.clone(), nullable));
+                    param
+                },
+            };
+            if arg["ownership"] == "transferred" {
+                post.push(format!("_detach({})", param));
+            }
+            match (marshal, self.handle_struct(&c_type)) {
+                (_, Some(_)) if param == "self" => call_args.push(String::from("self._as_parameter_")),
+                ("Str", _) | ("String", _) | ("OptionStr", _) | ("CStr", _) => call_args.push(format!("_str({})", param)),
+                ("Slice", _) | ("Bytes", _) => {
+                    prelude.push(format!("{0} = bytes({0})", param));
+                    call_args.push(format!("{0}, len({0})", param));
+                },
+                ("SliceMut", _) => {
+                    prelude.push(format!("{0}_buffer = _buffer({0})", param));
+                    call_args.push(format!("{0}_buffer, len({0})", param));
+                },
+                // The bytearray is truncated to what was written
+                ("OutBuffer", _) => {
+                    prelude.push(format!("{0}_buffer = _buffer({0})", param));
+                    prelude.push(format!("{}_written = ctypes.c_size_t(0)", param));
+                    call_args.push(format!("{0}_buffer, len({0}), ctypes.byref({0}_written)", param));
+                    post.push(format!("del {}_buffer", param));
+                    post.push(format!("del {0}[{0}_written.value:]", param));
+                },
+                ("Array", _) => call_args.push(format!("_array({}, {})", self.ctype(&c_type), param)),
+                ("Callback", _) | ("OptionCallback", _) => {
+                    call_args.push(format!("*_callback({}, {}), _destroy_callback", self.ctype(&c_type), param));
+                },
+                ("Value", _) => call_args.push(param),
+                (_, Some(struct_name)) => call_args.push(format!("_handle({}, {}, {})", param, struct_name, py_bool(nullable))),
+                _ => call_args.push(param),
+            }
+        }
+        // Trailing optional args default to None
+        let optional = params.iter().rev().take_while(|(_, nullable)| *nullable).count();
+        let first_optional = params.len() - optional;
+        let mut signature: Vec<String> = match receiver {
+            Receiver::Instance => vec![String::from("self")],
+            Receiver::Class => vec![String::from("cls")],
+            Receiver::None => vec![],
+        };
+        signature.extend(params.iter().enumerate().map(|(idx, (param, _))| match idx >= first_optional {
+            true => format!("{}=None", param),
+            false => param.clone(),
+        }));
+
+        let void = !fallible && function["returns"]["c_type"] == "void";
+        let mut call = format!("_lib.{}({})", symbol, call_args.join(", "));
+        let mut body = vec![];
+        // Args are cleaned up after the call whether or not it fails
+        if !post.is_empty() {
+            match void {
+                true => body.push(call),
+                false => body.push(format!("result = {}", call)),
+            }
+            call = String::from("result");
+            body.extend(post);
+        }
+        let owner = match receiver {
+            Receiver::Instance => "self",
+            _ => "_lib",
+        };
+        match (fallible, out) {
+            (true, Some(out)) => {
+                body.push(format!("_check({})", call));
+                let c_type = str_of(&out["c_type"]);
+                let pointee = c_type.strip_suffix('*').unwrap_or(&c_type);
+                let value = match self.ctype(pointee).starts_with("ctypes.") {
+                    true => "out.value",
+                    false => "out",
+                };
+                let nullable = str_of(&out["rust_type"]).starts_with("Option<");
+                body.push(format!("return {}", self.convert(pointee, "owned", nullable, value, owner)));
+            },
+            (true, None) => body.push(format!("_check({})", call)),
+            (false, _) if void => if body.is_empty() {
+                body.push(call);
+            },
+            (false, _) => {
+                let returns = &function["returns"];
+                let nullable = returns["marshal"].as_str().is_some_and(|marshal| marshal.starts_with("Option"));
+                body.push(format!("return {}", self.convert(&str_of(&returns["c_type"]), &str_of(&returns["ownership"]), nullable, &call, owner)));
+            },
+        }
+
+        let mut lines = vec![format!("def {}({}):", name, signature.join(", "))];
+        let arg_docs: Vec<(String, String)> = args.iter()
+            .filter_map(|arg| Some((py_name(&str_of(&arg["name"])), String::from(arg["docs"].as_str()?))))
+            .collect();
+        lines.extend(docstring(&function["docs"], &arg_docs, "    "));
+        lines.extend(guard.lines().chain(prelude.iter().map(String::as_str)).chain(body.iter().map(String::as_str)).map(|line| format!("    {}", line)));
+        lines.join("\n") + "\n"
+    }
+
+    // Converts a value returned from C, given the function's receiver as the owner of borrowed
+    // handles
+    fn convert(&self, c_type: &str, ownership: &str, nullable: bool, value: &str, owner: &str) -> String {
+        match (c_type, ownership, self.handle_struct(c_type)) {
+            ("char*", "owned", _) => format!("_string({}, {})", value, py_bool(nullable)),
+            ("CByteCursor", _, _) => format!("{}.to_bytes()", value),
+            ("CByteBuf", "owned", _) => format!("_bytes({})", value),
+            (_, "owned", Some(struct_name)) => format!("_wrap({}, {}, {})", struct_name, value, py_bool(nullable)),
+            (_, "borrowed", Some(struct_name)) => format!("_wrap({}, {}, {}, {})", struct_name, value, py_bool(nullable), owner),
+            _ if self.enums.contains_key(c_type) => format!("{}({})", self.names[c_type], value),
+            _ => String::from(value),
+        }
+    }
+}
+
+fn module_path(crate_name: &str, module: &Value) -> String {
+    std::iter::once(crate_name).chain(list(module, "path").filter_map(Value::as_str)).collect::<Vec<&str>>().join("::")
+}
+
+fn py_name(name: &str) -> String {
+    match RESERVED_NAMES.contains(&name) {
+        true => format!("{}_", name),
+        false => String::from(name),
+    }
+}
+
+fn py_bool(value: bool) -> &'static str {
+    match value {
+        true => "True",
+        false => "False",
+    }
+}
+
+fn py_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+// The docs as a docstring, with the docs of each arg as a :param:
+fn docstring(docs: &Value, args: &[(String, String)], indent: &str) -> Vec<String> {
+    let mut lines: Vec<String> = docs.as_str().into_iter().flat_map(str::lines).map(String::from).collect();
+    if !args.is_empty() {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(args.iter().map(|(name, docs)| format!(":param {}: {}", name, docs)));
+    }
+    if lines.is_empty() {
+        return vec![];
+    }
+    let text = lines.join("\n").replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\"");
+    let text: Vec<String> = text.lines().map(|line| match line.is_empty() {
+        true => String::new(),
+        false => format!("{}{}", indent, line),
+    }).collect();
+    match text.len() {
+        1 => vec![format!("{}\"\"\"{}\"\"\"", indent, text[0].trim_start())],
+        _ => vec![format!("{}\"\"\"{}", indent, text[0].trim_start()), text[1..].join("\n"), format!("{}\"\"\"", indent)],
+    }
+}
+
+impl Plugin for PythonExporter {
+    fn on_finish(&mut self, krate: &Crate, output_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let module_name = krate.name.replace('-', "_");
+        let path = output_dir.join(format!("{}.py", module_name));
+        write_if_changed(&path, &self.module(&krate.name, &manifest::describe(krate)))?;
+        Ok(vec![path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plugin::Registry;
+    use crate::scan::tests::write_crate;
+    use crate::{generate_with, Config};
+    use std::fs;
+
+    #[test]
+    fn wraps_exports() {
+        let root = write_crate("wraps_exports", &[
+            ("src/lib.rs", "
+                #[crt_export]
+                #[repr(u8)]
+                pub enum Shape { Circle = 1, None }
+
+                #[crt_export]
+                pub struct Point { x: i32, y: i32 }
+
+                #[crt_export]
+                impl Point {
+                    pub fn digest(&self, data: &[u8], out: &mut OutBuffer) -> Result<(), Error> {}
+                    pub fn on_moved(&self, callback: Box<dyn FnOnce(i32) + Send>) {}
+                    #[deprecated(note = \"Use shape\")]
+                    pub fn kind(&self, from: Option<&str>) -> Option<String> {}
+                    pub fn close(self) {}
+                }
+
+                #[crt_export]
+                pub static LIMIT: u32 = 1;"),
+        ]);
+        let output_dir = root.join("out");
+        let config = Config::new("geometry", &root.join("src/lib.rs"), &output_dir).generators("python");
+        let written = generate_with(&config, &Registry::default()).unwrap();
+        assert_eq!(vec![output_dir.join("geometry.py")], written);
+        let module = fs::read_to_string(&written[0]).unwrap();
+        assert!(module.contains("os.environ.get(\"GEOMETRY_LIBRARY\")"));
+
+        // Python keywords are renamed, and statics are read from the library
+        assert!(module.contains("class Shape(enum.IntEnum):\n    Circle = 1\n    None_ = 2\n"));
+        assert!(module.contains("LIMIT = ctypes.c_uint32.in_dll(_lib, \"LIMIT\").value\n"));
+        assert!(module.contains("_declare(\"Point_digest\", ctypes.c_int, ctypes.c_void_p, ctypes.c_char_p, ctypes.c_size_t, \
+            ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(ctypes.c_size_t))\n"));
+        // Out buffers are bytearrays, which are truncated to what was written
+        assert!(module.contains("
+        out_buffer = _buffer(out)
+        out_written = ctypes.c_size_t(0)
+        result = _lib.Point_digest(self._as_parameter_, data, len(data), out_buffer, len(out), ctypes.byref(out_written))
+        del out_buffer
+        del out[out_written.value:]
+        _check(result)
+"));
+        assert!(module.contains("*_callback(ctypes.CFUNCTYPE(None, ctypes.c_int32, ctypes.c_void_p), callback), _destroy_callback)"));
+        assert!(module.contains("        warnings.warn(\"Point_kind is deprecated: Use shape\", DeprecationWarning, stacklevel=2)\n"));
+        assert!(module.contains("    def kind(self, from_=None):\n"));
+        // Consuming self leaves the object without a handle, and close stays the release
+        assert!(module.contains("    def close_(self):\n        _lib.Point_close(self._as_parameter_)\n        _detach(self)\n"));
+        assert!(module.contains("ctypes.cast(self._as_parameter_, ctypes.POINTER(_PointFields)).contents.x\n"));
+
+        let python = std::env::var("PYTHON").unwrap_or_else(|_| String::from("python3"));
+        let output = std::process::Command::new(&python)
+            .args(["-c", "import ast, sys; ast.parse(open(sys.argv[1]).read(), sys.argv[1])"])
+            .arg(&written[0])
+            .output()
+            .unwrap_or_else(|err| panic!("Failed to run {}: {}", python, err));
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Attribute, Ident, Item, Lit, Meta};
+
+use crate::args::ExportArgs;
+use crate::types::Struct;
+use crate::{parse_target, validate, Target, EXPORTED_TRAITS};
+
+/// Everything exported from a crate, which is the model the generators are run over
+pub struct Crate {
+    /// The name generated files are named after, e.g. crtrs for crtrs.h
+    pub name: String,
+    /// The modules with exported items, depth first in the order they are declared
+    pub modules: Vec<Module>,
+}
+
+impl Crate {
+    pub fn scan(name: &str, lib_rs: &Path) -> std::io::Result<Crate> {
+        Ok(Crate {
+            name: String::from(name),
+            modules: scan_crate(lib_rs)?,
+        })
+    }
+
+    /// The exported definition of a struct, from any module
+    pub fn find_struct(&self, id: &Ident) -> Option<&Struct> {
+        self.modules.iter().flat_map(|module| module.targets.iter()).find_map(|target| match target {
+            Target::Struct(struct_target) if struct_target.id == *id => Some(struct_target),
+            _ => None,
+        })
+    }
+
+    /// The C type name of each exported struct and enum, keyed by its Rust name
+    pub fn c_type_names(&self) -> HashMap<String, String> {
+        self.modules.iter().flat_map(|module| module.targets.iter()).filter_map(|target| match target {
+            Target::Struct(struct_target) => Some((struct_target.id.to_string(), struct_target.exported_name())),
+            Target::Enum(enum_target) => Some((enum_target.id.to_string(), enum_target.exported_name())),
+            _ => None,
+        }).collect()
+    }
+}
+
+/// The items exported from one module of the crate
+pub struct Module {
+    /// The module's path within the crate, e.g. ["io"] for src/io.rs, or empty for the crate root
+    pub path: Vec<String>,
+    /// The exported items, in the order they appear in the source
+    pub targets: Vec<Target>,
+}
+
+impl Module {
+    /// The module's path joined with _, or lib for the crate root
+    pub fn name(&self) -> String {
+        match self.path.is_empty() {
+            true => String::from("lib"),
+            false => self.path.join("_"),
+        }
+    }
+}
+
+/// Parses the crate rooted at lib_rs, following mod declarations to the files which define them,
+/// and returns each module which has exported items. Test modules are skipped, as they aren't
+/// part of the library. Items which fail to parse or to export are skipped, as the crt_export
+/// macro reports them when the crate is compiled.
+pub fn scan_crate(lib_rs: &Path) -> std::io::Result<Vec<Module>> {
+    let mut modules: Vec<Module> = vec![];
+    let dir = lib_rs.parent().unwrap_or_else(|| Path::new("."));
+    scan_file(lib_rs, vec![], dir, &mut modules)?;
+    Ok(modules)
+}
+
+fn scan_file(file: &Path, path: Vec<String>, dir: &Path, modules: &mut Vec<Module>) -> std::io::Result<()> {
+    let source = fs::read_to_string(file)?;
+    match syn::parse_file(&source) {
+        Ok(parsed) => scan_items(parsed.items, path, dir, file.parent().unwrap_or(dir), modules),
+        Err(_) => Ok(()),
+    }
+}
+
+// dir is where the module's child modules are found, i.e. src/io for src/io.rs or src/io/mod.rs,
+// and path_dir is where #[path] attributes are relative to, i.e. src for src/io.rs
+fn scan_items(items: Vec<Item>, path: Vec<String>, dir: &Path, path_dir: &Path, modules: &mut Vec<Module>) -> std::io::Result<()> {
+    let mut module = Module { path: path.clone(), targets: vec![] };
+    let mut children: Vec<(Vec<String>, PathBuf, Child)> = vec![];
+    for mut item in items.into_iter() {
+        if let Item::Mod(mod_item) = &item {
+            if is_test_only(&mod_item.attrs) {
+                continue;
+            }
+            let name = mod_item.ident.to_string();
+            let mut child_path = path.clone();
+            child_path.push(name.clone());
+            match &mod_item.content {
+                Some((_, items)) => children.push((child_path, dir.join(&name), Child::Inline(items.clone()))),
+                None => match path_attr(&mod_item.attrs) {
+                    // Files given by #[path] find their children alongside them, like mod.rs
+                    Some(file) => {
+                        let file = path_dir.join(file);
+                        let child_dir = file.parent().unwrap_or(path_dir).to_path_buf();
+                        children.push((child_path, child_dir, Child::File(file)));
+                    },
+                    None => {
+                        let candidates = [dir.join(format!("{}.rs", name)), dir.join(&name).join("mod.rs")];
+                        if let Some(file) = candidates.iter().find(|file| file.is_file()) {
+                            children.push((child_path, dir.join(&name), Child::File(file.clone())));
+                        }
+                    },
+                },
+            }
+            continue;
+        }
+        if let Some(target) = export_target(&mut item) {
+            module.targets.push(target);
+        }
+    }
+    if !module.targets.is_empty() {
+        modules.push(module);
+    }
+    for (child_path, child_dir, child) in children.into_iter() {
+        match child {
+            Child::Inline(items) => scan_items(items, child_path, &child_dir, &child_dir, modules)?,
+            Child::File(file) => scan_file(&file, child_path, &child_dir, modules)?,
+        }
+    }
+    Ok(())
+}
+
+enum Child {
+    Inline(Vec<Item>),
+    File(PathBuf),
+}
+
+// As the crt_export macro would, if the item is marked with #[crt_export]
+fn export_target(item: &mut Item) -> Option<Target> {
+    let attrs = match item {
+        Item::Struct(item) => &mut item.attrs,
+        Item::Enum(item) => &mut item.attrs,
+        Item::Impl(item) => &mut item.attrs,
+        Item::Fn(item) => &mut item.attrs,
+        Item::Const(item) => &mut item.attrs,
+        Item::Static(item) => &mut item.attrs,
+        _ => return None,
+    };
+    if is_test_only(attrs) || !attrs.iter().any(|attr| attr.path.is_ident("crt_export")) {
+        return None;
+    }
+    let export_args = ExportArgs::take(attrs).ok()?;
+    validate::validate_item(item, &EXPORTED_TRAITS).ok()?;
+    parse_target(item, &export_args).ok()
+}
+
+fn is_test_only(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.path.is_ident("cfg") && attr.tokens.to_string() == "(test)")
+}
+
+// #[path = "file.rs"] on a mod declaration
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().filter(|attr| attr.path.is_ident("path")).find_map(|attr| {
+        match attr.parse_meta() {
+            Ok(Meta::NameValue(name_value)) => match name_value.lit {
+                Lit::Str(path) => Some(path.value()),
+                _ => None,
+            },
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A crate in a fresh directory under the system temp dir, from (file, contents)
+    pub fn write_crate(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("crt_bindgen_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (file, contents) in files.iter() {
+            let file = root.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, contents).unwrap();
+        }
+        root
+    }
+
+    fn target_names(module: &Module) -> Vec<String> {
+        module.targets.iter().map(|target| match target {
+            Target::Struct(struct_target) => struct_target.exported_name(),
+            Target::Enum(enum_target) => enum_target.exported_name(),
+            Target::Impl(methods) => methods.iter().map(|m| m.exported_name()).collect::<Vec<String>>().join(","),
+            Target::Trait(struct_target, trait_name) => format!("{}:{}", struct_target.exported_name(), trait_name),
+            Target::Function(function) => function.exported_name(),
+            Target::Constant(constant) => constant.exported_name(),
+        }).collect()
+    }
+
+    #[test]
+    fn scans_modules() {
+        let root = write_crate("scans_modules", &[
+            ("src/lib.rs", "mod io; mod auth; #[path = \"other.rs\"] mod renamed;
+                #[crt_export] pub fn init() {}
+                #[cfg(test)] mod tests { #[crt_export] pub fn test_only() {} }"),
+            ("src/io.rs", "mod tls;
+                #[crt_export] pub struct EventLoopGroup {}
+                #[crt_export] impl EventLoopGroup { pub fn new() -> EventLoopGroup { EventLoopGroup {} } }
+                pub fn not_exported() {}"),
+            ("src/io/tls.rs", "#[crt_export(prefix = \"tls_\")] pub const VERSION: u32 = 13;"),
+            ("src/auth/mod.rs", "pub mod inline { #[crt_export] pub fn sign() {} }"),
+            ("src/other.rs", "#[crt_export] pub static LIMIT: u32 = 1;"),
+        ]);
+        let modules = scan_crate(&root.join("src/lib.rs")).unwrap();
+        let names: Vec<String> = modules.iter().map(|m| m.name()).collect();
+        assert_eq!(vec!["lib", "io", "io_tls", "auth_inline", "renamed"], names);
+        assert_eq!(vec!["init"], target_names(&modules[0]));
+        assert_eq!(vec!["EventLoopGroup", "EventLoopGroup_new"], target_names(&modules[1]));
+        assert_eq!(vec!["tls_VERSION"], target_names(&modules[2]));
+        assert_eq!(vec!["sign"], target_names(&modules[3]));
+        assert_eq!(vec!["LIMIT"], target_names(&modules[4]));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn skips_invalid_items() {
+        let root = write_crate("skips_invalid_items", &[
+            ("src/lib.rs", "mod missing;
+                #[crt_export] pub fn generic<T>(t: T) {}
+                #[crt_export(builder)] pub fn builder() {}
+                #[crt_export] pub fn valid() {}"),
+        ]);
+        let modules = scan_crate(&root.join("src/lib.rs")).unwrap();
+        assert_eq!(1, modules.len());
+        assert_eq!(vec!["valid"], target_names(&modules[0]));
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,1076 @@
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use syn::{parse_quote, Attribute, BinOp, Expr, ExprLit, Fields, Ident, ImplItemMethod, ItemConst, ItemEnum, ItemImpl, ItemStatic, ItemStruct, Lit, Meta, NestedMeta, ReturnType, UnOp, Type, TypePath, TypeParamBound, FnArg, Pat, PatIdent, PathArguments, GenericArgument};
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::{quote, format_ident, ToTokens};
+
+use crate::args::Naming;
+use crate::docs::Docs;
+
+#[derive(Clone)]
+pub struct Struct {
+    pub id: Ident,
+    pub fields: Vec<Field>,
+    pub derives: Vec<Ident>,
+    pub naming: Naming,
+    pub builder: bool,
+    pub refcounted: bool,
+    pub docs: Docs,
+}
+
+impl Struct {
+    pub fn new(ident: &Ident) -> Struct {
+        Struct {
+            id: ident.clone(),
+            fields: vec![],
+            derives: vec![],
+            naming: Naming::default(),
+            builder: false,
+            refcounted: false,
+            docs: Docs::default(),
+        }
+    }
+
+    pub fn from_item(struct_item: &ItemStruct) -> Struct {
+        let fields = match &struct_item.fields {
+            Fields::Named(named) => named.named.iter().filter_map(|field| {
+                field.ident.as_ref().map(|id| Field {
+                    id: id.clone(),
+                    ty: field.ty.clone(),
+                    docs: Docs::from_attrs(&field.attrs),
+                    attrs: field.attrs.iter().filter(|attr| is_doc_attr(attr)).cloned().collect(),
+                })
+            }).collect(),
+            _ => vec![],
+        };
+        Struct {
+            id: struct_item.ident.clone(),
+            fields,
+            derives: derived_traits(&struct_item.attrs),
+            naming: Naming::default(),
+            builder: false,
+            refcounted: false,
+            docs: Docs::from_attrs(&struct_item.attrs),
+        }
+    }
+
+    pub fn exported_name(&self) -> String {
+        self.naming.apply(&self.id)
+    }
+
+    // The functions generated for the struct, rather than exported from its impls. An impl can't
+    // see whether its struct is refcounted, so the handle functions are always included, while the
+    // derives and builder fields are only known to the struct's own #[crt_export].
+    pub fn generated_functions(&self) -> Vec<String> {
+        let mut suffixes: Vec<String> = ["destroy", "acquire", "release"].iter().map(|s| String::from(*s)).collect();
+        suffixes.extend(self.derives.iter().filter_map(|derive| crate::trait_suffix(&derive.to_string())).map(String::from));
+        if self.builder {
+            suffixes.push(String::from("new"));
+            for field in self.fields.iter() {
+                suffixes.push(format!("set_{}", field.id));
+                suffixes.push(format!("get_{}", field.id));
+            }
+        }
+        suffixes.iter().map(|suffix| format!("{}_{}", self.exported_name(), suffix)).collect()
+    }
+
+    // The set_/get_ methods exported for each field of a builder struct
+    pub fn accessors(&self) -> Result<Vec<ImplItemMethod>, syn::Error> {
+        let mut accessors: Vec<ImplItemMethod> = vec![];
+        for field in self.fields.iter() {
+            accessors.push(field.setter()?);
+            accessors.push(field.getter()?);
+        }
+        Ok(accessors)
+    }
+
+    // The fields as (field, c_type), if C can use the struct by value, i.e. it only has scalar
+    // fields. Otherwise C can only hold handles to it.
+    pub fn c_fields(&self) -> Option<Vec<(&Field, String)>> {
+        if self.fields.is_empty() || self.builder || self.refcounted {
+            return None;
+        }
+        self.fields.iter().map(|field| {
+            let c_type = match &field.ty {
+                Type::Path(path) if path.qself.is_none() && !last_ident_is(path, "c_void") => path_to_c_type(path)
+                    .filter(|c_type| c_type.chars().next().is_some_and(char::is_lowercase)),
+                Type::Ptr(_) => rust_to_c_type(&field.ty).ok(),
+                _ => None,
+            };
+            c_type.map(|c_type| (field, c_type))
+        }).collect()
+    }
+}
+
+/// A named field of an exported struct, which builder structs expose via set_/get_ methods
+#[derive(Clone)]
+pub struct Field {
+    pub id: Ident,
+    pub ty: Type,
+    pub docs: Docs,
+    // The doc and #[deprecated] attributes, which are copied to the set_/get_ methods
+    pub attrs: Vec<Attribute>,
+}
+
+impl Field {
+    // set_<field>(&mut self, value: T), which takes ownership of the value, dropping the old one
+    pub fn setter(&self) -> Result<ImplItemMethod, syn::Error> {
+        self.getter()?;
+        let id = &self.id;
+        let ty = &self.ty;
+        let setter = format_ident!("set_{}", id);
+        let attrs = &self.attrs;
+        Ok(parse_quote! {
+            #(#attrs)*
+            fn #setter(&mut self, #id: #ty) {
+                self.#id = #id;
+            }
+        })
+    }
+
+    // get_<field>(&self), which borrows strings, bytes and handles, and copies everything else
+    pub fn getter(&self) -> Result<ImplItemMethod, syn::Error> {
+        let id = &self.id;
+        let getter = format_ident!("get_{}", id);
+        let inner = handle_type(&self.ty);
+        let (ty, value): (Type, TokenStream) = match marshal_type(&self.ty) {
+            Marshal::String => (parse_quote! { &str }, quote! { &self.#id }),
+            Marshal::OptionStr => (parse_quote! { Option<&str> }, quote! { self.#id.as_deref() }),
+            Marshal::Bytes => (parse_quote! { &[u8] }, quote! { &self.#id }),
+            Marshal::Boxed => (parse_quote! { &#inner }, quote! { &self.#id }),
+            Marshal::OptionBoxed => (parse_quote! { Option<&#inner> }, quote! { self.#id.as_deref() }),
+            Marshal::OptionHandle => (parse_quote! { Option<&#inner> }, quote! { self.#id.as_ref() }),
+            Marshal::Value | Marshal::FfiValue => (self.ty.clone(), quote! { Clone::clone(&self.#id) }),
+            _ => return Err(syn::Error::new_spanned(&self.ty, "crt_export(builder) only supports string, bytes, value and owned handle fields")),
+        };
+        let attrs = &self.attrs;
+        Ok(parse_quote! {
+            #(#attrs)*
+            fn #getter(&self) -> #ty {
+                #value
+            }
+        })
+    }
+}
+
+/// How a value is converted between its Rust type and its FFI type by the generated shims
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Marshal {
+    /// Passed through unchanged, e.g. raw pointers
+    Value,
+    /// A primitive, enum or #[repr(C)] struct passed by value, converted to and from its
+    /// crate::ffi::FfiValue::Ffi type. Enums are validated when converted from C.
+    FfiValue,
+    /// &T or &mut T, passed as a const T* or T* which must not be NULL
+    Ref,
+    /// &str, passed as a NUL-terminated UTF-8 char*
+    Str,
+    /// String, copied from/to a NUL-terminated UTF-8 char*
+    String,
+    /// Option<&str> or Option<String>, as Str/String but NULL maps to None
+    OptionStr,
+    /// &CStr, passed as a NUL-terminated char*
+    CStr,
+    /// &[u8], passed as (const uint8_t*, size_t) and returned as a CByteCursor
+    Slice,
+    /// &mut [u8], passed as (uint8_t*, size_t)
+    SliceMut,
+    /// Vec<u8>, copied from (const uint8_t*, size_t) and returned as a CByteBuf
+    Bytes,
+    /// &mut OutBuffer, a caller provided (uint8_t*, size_t capacity) with a size_t* written length
+    OutBuffer,
+    /// Option<&T>, passed as a const T* which may be NULL
+    OptionRef,
+    /// Option<&mut T>, passed as a *mut T which may be NULL
+    OptionMut,
+    /// Box<T>, an owned handle passed as a *mut T which must not be NULL. It is the same handle as
+    /// T is passed as, so a refcounted T is an Arc reference, which must be the last one in args.
+    Boxed,
+    /// Option<Box<T>>, an owned handle passed as a *mut T which may be NULL
+    OptionBoxed,
+    /// Option<T> for a struct T, an owned handle passed as a *mut T which may be NULL
+    OptionHandle,
+    /// T for a consuming self receiver, an owned handle passed as a *mut T which must not be NULL.
+    /// The handle is invalid after the call.
+    Owned,
+    /// [T; N], passed as a pointer to the N elements which must not be NULL, and copied
+    Array,
+    /// Arc<T> for a #[crt_export(refcounted)] struct T, a handle passed as a *const T which must not
+    /// be NULL. Args borrow the caller's reference, and returned Arcs are a new reference owned by
+    /// the caller.
+    Arc,
+    /// Box<dyn FnOnce(A, ..) + Send> (or FnMut/Fn), passed as a C function pointer which takes the
+    /// args followed by a void* user_data, the user_data to pass it, and a nullable destroy function
+    /// which is called with the user_data once the callback is dropped. The function pointer must
+    /// not be NULL. Args must be FfiValues, which are passed as their Ffi type, or raw pointers.
+    Callback,
+    /// Option<Box<dyn FnOnce(A, ..) + Send>>, as Callback but a NULL function pointer maps to None
+    OptionCallback,
+}
+
+#[allow(dead_code)]
+impl Marshal {
+    pub fn is_nullable(&self) -> bool {
+        matches!(
+            self,
+            Marshal::OptionStr | Marshal::OptionRef | Marshal::OptionMut | Marshal::OptionBoxed | Marshal::OptionHandle | Marshal::OptionCallback
+        )
+    }
+}
+
+pub struct Variant {
+    pub id: Ident,
+    // The discriminant as written in C, which is an expression if it was not an integer literal
+    pub value: String,
+    pub docs: Docs,
+}
+
+pub struct Enum {
+    pub id: Ident,
+    pub repr: Ident,
+    pub variants: Vec<Variant>,
+    pub naming: Naming,
+    pub docs: Docs,
+}
+
+impl Enum {
+    pub fn new(enum_item: &ItemEnum) -> Result<Enum, syn::Error> {
+        let repr = enum_repr(enum_item).ok_or_else(|| {
+            syn::Error::new_spanned(&enum_item.ident, "crt_export enums must have an integer repr, e.g. #[repr(i32)]")
+        })?;
+        let mut variants: Vec<Variant> = vec![];
+        let mut next_value: i128 = 0;
+        for variant in enum_item.variants.iter() {
+            if !variant.fields.is_empty() {
+                return Err(syn::Error::new_spanned(&variant.fields, "crt_export enums must be fieldless"));
+            }
+            // Discriminants are evaluated, so that C gets the same values whatever the expression
+            let value = match &variant.discriminant {
+                Some((_, expr)) => discriminant_value(expr)?,
+                None => next_value,
+            };
+            next_value = value + 1;
+            variants.push(Variant {
+                id: variant.ident.clone(),
+                value: value.to_string(),
+                docs: Docs::from_attrs(&variant.attrs),
+            });
+        }
+        Ok(Enum {
+            id: enum_item.ident.clone(),
+            repr,
+            variants,
+            naming: Naming::default(),
+            docs: Docs::from_attrs(&enum_item.attrs),
+        })
+    }
+
+    pub fn exported_name(&self) -> String {
+        self.naming.apply(&self.id)
+    }
+
+    pub fn c_repr_type(&self) -> String {
+        primitive_to_c_type(&self.repr.to_string()).unwrap()
+    }
+}
+
+// Evaluates an integer constant expression of literals and arithmetic or bitwise operators
+fn discriminant_value(expr: &Expr) -> Result<i128, syn::Error> {
+    let error = || syn::Error::new_spanned(expr, "crt_export enum discriminants must be integer literals, or arithmetic on them");
+    let value = match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse::<i128>().ok(),
+        Expr::Unary(unary) => match unary.op {
+            UnOp::Neg(_) => discriminant_value(&unary.expr)?.checked_neg(),
+            _ => None,
+        },
+        Expr::Paren(paren) => Some(discriminant_value(&paren.expr)?),
+        Expr::Binary(binary) => {
+            let (left, right) = (discriminant_value(&binary.left)?, discriminant_value(&binary.right)?);
+            match binary.op {
+                BinOp::Add(_) => left.checked_add(right),
+                BinOp::Sub(_) => left.checked_sub(right),
+                BinOp::Mul(_) => left.checked_mul(right),
+                BinOp::Div(_) => left.checked_div(right),
+                BinOp::Rem(_) => left.checked_rem(right),
+                BinOp::Shl(_) => u32::try_from(right).ok().and_then(|right| left.checked_shl(right)),
+                BinOp::Shr(_) => u32::try_from(right).ok().and_then(|right| left.checked_shr(right)),
+                BinOp::BitAnd(_) => Some(left & right),
+                BinOp::BitOr(_) => Some(left | right),
+                BinOp::BitXor(_) => Some(left ^ right),
+                _ => None,
+            }
+        },
+        _ => None,
+    };
+    value.ok_or_else(error)
+}
+
+fn enum_repr(enum_item: &ItemEnum) -> Option<Ident> {
+    const INTEGERS: &[&str] = &["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"];
+    enum_item.attrs.iter()
+        .filter(|attr| attr.path.is_ident("repr"))
+        .flat_map(|attr| attr.tokens.clone().into_iter())
+        .flat_map(|token| match token {
+            TokenTree::Group(group) => group.stream().into_iter().collect(),
+            token => vec![token],
+        })
+        .find_map(|token| match token {
+            TokenTree::Ident(ident) if INTEGERS.iter().any(|int| ident == int) => Some(ident),
+            _ => None,
+        })
+}
+
+/// A const or static item. Consts are exported to C as a #define of their value, and statics as
+/// an extern symbol.
+pub struct Constant {
+    pub id: Ident,
+    pub c_type: String,
+    // The value as a C literal, for consts
+    pub value: Option<String>,
+    pub is_mut: bool,
+    // Overrides the exported symbol, from #[crt_export(name = "...")]
+    pub name: Option<String>,
+    pub prefix: String,
+    pub docs: Docs,
+}
+
+impl Constant {
+    pub fn from_const(const_item: &ItemConst) -> Result<Constant, syn::Error> {
+        Ok(Constant {
+            id: const_item.ident.clone(),
+            c_type: rust_to_c_type(&const_item.ty).unwrap_or_default(),
+            value: Some(c_literal(&const_item.expr)?),
+            is_mut: false,
+            name: None,
+            prefix: String::new(),
+            docs: Docs::from_attrs(&const_item.attrs),
+        })
+    }
+
+    pub fn from_static(static_item: &ItemStatic) -> Result<Constant, syn::Error> {
+        Ok(Constant {
+            id: static_item.ident.clone(),
+            c_type: rust_to_c_type(&static_item.ty)?,
+            value: None,
+            is_mut: static_item.mutability.is_some(),
+            name: None,
+            prefix: String::new(),
+            docs: Docs::from_attrs(&static_item.attrs),
+        })
+    }
+
+    pub fn exported_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{}{}", self.prefix, self.id),
+        }
+    }
+}
+
+// Integer, float, bool and string literals, which mean the same in C
+fn c_literal(expr: &Expr) -> Result<String, syn::Error> {
+    let error = || syn::Error::new_spanned(expr, "Exported consts must be a literal, export a static for computed values");
+    match expr {
+        Expr::Lit(ExprLit { lit, .. }) => match lit {
+            Lit::Int(lit) => Ok(String::from(lit.base10_digits())),
+            Lit::Float(lit) => Ok(String::from(lit.base10_digits())),
+            Lit::Bool(lit) => Ok(lit.value.to_string()),
+            Lit::Str(lit) if lit.value().is_ascii() => Ok(lit.to_token_stream().to_string()),
+            _ => Err(error()),
+        },
+        Expr::Unary(unary) => match (&unary.op, unary.expr.as_ref()) {
+            (UnOp::Neg(_), Expr::Lit(_)) => Ok(format!("-{}", c_literal(&unary.expr)?)),
+            _ => Err(error()),
+        },
+        Expr::Paren(paren) => c_literal(&paren.expr),
+        _ => Err(error()),
+    }
+}
+
+pub struct MethodArg {
+    pub name: String,
+    pub ty: Type,
+    pub rust_type: String,
+    pub c_type: String,
+    pub marshal: Marshal,
+    pub tokens: TokenStream,
+}
+
+impl MethodArg {
+    fn new(ident: &PatIdent, ty: &Type) -> Result<MethodArg, syn::Error> {
+        let c_type = rust_to_c_type(ty)?;
+        let rust_ffi_ty = rust_to_ffi_type(ty)?;
+        let marshal = marshal_type(ty);
+        let name = &ident.ident;
+        let len = format_ident!("{}_len", name);
+        let tokens = match marshal {
+            Marshal::Slice | Marshal::Bytes => quote!{ #name: *const u8, #len: usize },
+            Marshal::SliceMut => quote!{ #name: *mut u8, #len: usize },
+            Marshal::OutBuffer => {
+                let capacity = format_ident!("{}_capacity", name);
+                let written = format_ident!("{}_written", name);
+                quote!{ #name: *mut u8, #capacity: usize, #written: *mut usize }
+            },
+            Marshal::Callback | Marshal::OptionCallback => {
+                let user_data = format_ident!("{}_user_data", name);
+                let destroy = format_ident!("{}_destroy", name);
+                quote!{
+                    #name: #rust_ffi_ty,
+                    #user_data: *mut std::ffi::c_void,
+                    #destroy: Option<extern "C" fn(*mut std::ffi::c_void)>
+                }
+            },
+            _ => quote!{ #ident : #rust_ffi_ty },
+        };
+        Ok(MethodArg {
+            name: name.to_string(),
+            ty: ty.clone(),
+            rust_type: rust_ffi_ty.to_string(),
+            c_type,
+            marshal,
+            tokens,
+        })
+    }
+
+    // The C parameters this arg is passed as, as (name, c_type)
+    pub fn c_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![(self.name.clone(), self.c_type.clone())];
+        match self.marshal {
+            Marshal::Slice | Marshal::SliceMut | Marshal::Bytes => {
+                params.push((format!("{}_len", self.name), String::from("size_t")));
+            },
+            Marshal::OutBuffer => {
+                params.push((format!("{}_capacity", self.name), String::from("size_t")));
+                params.push((format!("{}_written", self.name), String::from("size_t*")));
+            },
+            Marshal::Callback | Marshal::OptionCallback => {
+                params.push((format!("{}_user_data", self.name), String::from("void*")));
+                params.push((format!("{}_destroy", self.name), String::from("void (*)(void*)")));
+            },
+            _ => (),
+        }
+        params
+    }
+}
+
+pub struct Method {
+    pub is_static: bool,
+    pub is_constructor: bool,
+    pub is_fallible: bool,
+    pub returns: Marshal,
+    pub c_return_type: String,
+    pub target: Struct,
+    pub method: ImplItemMethod,
+    // Overrides the exported symbol, from #[crt_export(name = "...")]
+    pub name: Option<String>,
+    // A free function rather than a method, in which case target is named after the function
+    pub is_free: bool,
+    pub this: Option<MethodArg>,
+    pub args: Vec<MethodArg>,
+    pub out: Option<MethodArg>,
+    pub docs: Docs,
+}
+
+#[allow(dead_code)]
+impl Method {
+    pub fn new(target: &Struct, method: &ImplItemMethod) -> Result<Method, syn::Error> {
+        let method = &resolve_self(target, method);
+        let is_fallible = result_ok_type(&method.sig.output).is_some();
+        let is_constructor = method_is_constructor(target, method);
+        let c_return_type = match (is_fallible, is_constructor, &method.sig.output) {
+            (true, _, _) => String::from("int"),
+            (_, true, _) => format!("{}*", target.id),
+            (_, _, ReturnType::Default) => String::from("void"),
+            (_, _, ReturnType::Type(_, ty)) => rust_to_c_return_type(ty)?,
+        };
+        // The shim returns the FFI type, so it must have one too
+        if let (false, false, ReturnType::Type(_, ty)) = (is_fallible, is_constructor, &method.sig.output) {
+            rust_to_ffi_return_type(ty)?;
+        }
+        Ok(Method {
+            is_static: method_is_static(method),
+            is_constructor,
+            is_fallible,
+            returns: returned_type(method).map_or(Marshal::Value, marshal_type),
+            c_return_type,
+            target: target.clone(),
+            method: method.clone(),
+            name: None,
+            is_free: false,
+            this: Method::parse_this(target, method)?,
+            args: Method::parse_args(target, method)?,
+            out: Method::parse_out(target, method)?,
+            docs: Docs::from_attrs(&method.attrs),
+        })
+    }
+
+    fn parse_this(target: &Struct, method: &ImplItemMethod) -> Result<Option<MethodArg>, syn::Error> {
+        // Convert the receiver into this: &self -> *const Self, &mut self -> *mut Self,
+        // self and self: Box<Self> -> *mut Self which is consumed, and self: Arc<Self> -> *const Self
+        // which borrows the caller's reference
+        let rust_type = &target.id;
+        let receiver = match method.sig.receiver() {
+            Some(receiver) => receiver,
+            None => return Ok(None),
+        };
+        let ty: Type = match receiver {
+            FnArg::Receiver(receiver) => match (&receiver.reference, &receiver.mutability) {
+                (Some(_), Some(_)) => parse_quote! { &mut #rust_type },
+                (Some(_), None) => parse_quote! { &#rust_type },
+                (None, _) => parse_quote! { #rust_type },
+            },
+            FnArg::Typed(typed) => replace_self(&typed.ty, rust_type),
+        };
+        // Self by value is owned, while any other wrapper of Self, e.g. Rc<Self>, is unsupported
+        let marshal = match (marshal_type(&ty), &ty) {
+            (Marshal::FfiValue, Type::Path(path)) if first_generic_type(path).is_none() => Marshal::Owned,
+            (Marshal::FfiValue, _) => Marshal::Value,
+            (marshal, _) => marshal,
+        };
+        let (ffi_ty, c_type) = match (marshal, &ty) {
+            (Marshal::Ref, Type::Reference(ref_type)) if ref_type.mutability.is_some() => (quote! { *mut #rust_type }, format!("{}*", rust_type)),
+            (Marshal::Ref, _) => (quote! { *const #rust_type }, format!("const {}*", rust_type)),
+            (Marshal::Owned, _) | (Marshal::Boxed, _) => (quote! { *mut #rust_type }, format!("{}*", rust_type)),
+            (Marshal::Arc, _) => (quote! { *const #rust_type }, format!("const {}*", rust_type)),
+            _ => return Err(syn::Error::new_spanned(receiver, "Unsupported receiver, expected &self, &mut self, self, self: Box<Self> or self: Arc<Self>")),
+        };
+        Ok(Some(MethodArg {
+            name: String::from("this"),
+            ty,
+            rust_type: ffi_ty.to_string(),
+            c_type,
+            marshal,
+            tokens: quote! { this: #ffi_ty },
+        }))
+    }
+
+    fn parse_args(target: &Struct, method: &ImplItemMethod) -> Result<Vec<MethodArg>, syn::Error> {
+        let mut args: Vec<MethodArg> = vec![];
+        let is_static = method_is_static(method);
+        let mut inputs : Vec<&FnArg> = method.sig.inputs.pairs().map(|p| {
+            *p.value()
+        }).collect();
+        if !is_static {
+            if inputs.len() > 1 {
+                inputs = inputs[1..].to_owned();
+            } else {
+                inputs = vec![];
+            }
+        }
+
+        if let Some(this) = Method::parse_this(target, method)? {
+            args.push(this);
+        }
+        for p in inputs.iter() {
+            if let FnArg::Typed(typed) = p {
+                if let Pat::Ident(ident) = typed.pat.as_ref() {
+                    args.push(MethodArg::new(ident, &typed.ty)?);
+                }
+            }
+        }
+
+        Ok(args)
+    }
+
+    fn parse_out(target: &Struct, method: &ImplItemMethod) -> Result<Option<MethodArg>, syn::Error> {
+        // Convert -> Result<T, E> into out: *mut T, leaving the return value for the status code
+        let ok_ty = match result_ok_type(&method.sig.output) {
+            Some(ok_ty) => ok_ty,
+            None => return Ok(None),
+        };
+        if let Type::Tuple(tuple) = ok_ty {
+            if tuple.elems.is_empty() {
+                return Ok(None);
+            }
+        }
+        Ok(if method_is_constructor(target, method) {
+            let rust_type = &target.id;
+            Some(MethodArg {
+                name: String::from("out"),
+                ty: ok_ty.clone(),
+                rust_type: format!("*mut *mut {}", rust_type),
+                c_type: format!("{}**", rust_type),
+                marshal: Marshal::Value,
+                tokens: quote! { out: *mut *mut #rust_type },
+            })
+        } else {
+            let c_type = rust_to_c_return_type(ok_ty)?;
+            let rust_ffi_ty = rust_to_ffi_return_type(ok_ty)?;
+            Some(MethodArg {
+                name: String::from("out"),
+                ty: ok_ty.clone(),
+                rust_type: format!("*mut {}", rust_ffi_ty),
+                c_type: format!("{}*", c_type),
+                marshal: Marshal::Value,
+                tokens: quote! { out: *mut #rust_ffi_ty },
+            })
+        })
+    }
+
+    pub fn exported_target_name(&self) -> String {
+        self.target.exported_name()
+    }
+
+    pub fn exported_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        format_ident!("{}_{}", &self.target.exported_name(), &self.method.sig.ident).to_string()
+    }
+
+    pub fn exported_return_type(&self) -> String {
+        self.c_return_type.clone()
+    }
+
+    pub fn exported_args(&self) -> Vec<(String, String)> {
+        self.args.iter().chain(self.out.iter()).flat_map(|a| {
+            a.c_params()
+        }).collect()
+    }
+}
+
+pub fn marshal_type(ty: &Type) -> Marshal {
+    match ty {
+        Type::Reference(ref_type) => match ref_type.elem.as_ref() {
+            Type::Path(path) if path.path.is_ident("str") => Marshal::Str,
+            Type::Path(path) if last_ident_is(path, "CStr") => Marshal::CStr,
+            Type::Path(path) if last_ident_is(path, "OutBuffer") => Marshal::OutBuffer,
+            Type::Slice(slice) if is_u8(&slice.elem) => match ref_type.mutability {
+                Some(_) => Marshal::SliceMut,
+                None => Marshal::Slice,
+            },
+            _ => Marshal::Ref,
+        },
+        Type::Path(path) if last_ident_is(path, "String") => Marshal::String,
+        Type::Path(path) if last_ident_is(path, "Vec") && first_generic_type(path).is_some_and(is_u8) => Marshal::Bytes,
+        Type::Path(path) if last_ident_is(path, "Box") && callback_signature(ty).is_some() => Marshal::Callback,
+        Type::Path(path) if last_ident_is(path, "Box") => Marshal::Boxed,
+        Type::Path(path) if last_ident_is(path, "Arc") => Marshal::Arc,
+        Type::Path(path) if last_ident_is(path, "Option") => match first_generic_type(path).map(|inner| (inner, marshal_type(inner))) {
+            Some((_, Marshal::Str)) | Some((_, Marshal::String)) => Marshal::OptionStr,
+            Some((Type::Reference(ref_type), Marshal::Ref)) => match ref_type.mutability {
+                Some(_) => Marshal::OptionMut,
+                None => Marshal::OptionRef,
+            },
+            Some((_, Marshal::Boxed)) => Marshal::OptionBoxed,
+            Some((_, Marshal::Callback)) => Marshal::OptionCallback,
+            Some((inner, Marshal::FfiValue)) if !is_primitive(inner) => Marshal::OptionHandle,
+            _ => Marshal::Value,
+        },
+        Type::Path(_) => Marshal::FfiValue,
+        Type::Array(_) => Marshal::Array,
+        _ => Marshal::Value,
+    }
+}
+
+// The args and return type of Box<dyn FnOnce(A, ..) -> R> (or FnMut/Fn), optionally within an Option
+pub fn callback_signature(ty: &Type) -> Option<(Vec<Type>, ReturnType)> {
+    let box_path = match ty {
+        Type::Path(path) if last_ident_is(path, "Option") => match first_generic_type(path)? {
+            Type::Path(path) => path,
+            _ => return None,
+        },
+        Type::Path(path) => path,
+        _ => return None,
+    };
+    if !last_ident_is(box_path, "Box") {
+        return None;
+    }
+    if let Type::TraitObject(trait_object) = first_generic_type(box_path)? {
+        for bound in trait_object.bounds.iter() {
+            if let TypeParamBound::Trait(trait_bound) = bound {
+                let segment = trait_bound.path.segments.last()?;
+                if !["FnOnce", "FnMut", "Fn"].iter().any(|f| segment.ident == f) {
+                    continue;
+                }
+                if let PathArguments::Parenthesized(fn_args) = &segment.arguments {
+                    return Some((fn_args.inputs.iter().cloned().collect(), fn_args.output.clone()));
+                }
+            }
+        }
+    }
+    None
+}
+
+// The T of handle types passed as *mut T: &T, Box<T>, Option<&T>, Option<Box<T>> and Option<T>,
+// and of Arc<T> passed as *const T
+fn handle_type(ty: &Type) -> &Type {
+    match ty {
+        Type::Reference(ref_type) => handle_type(&ref_type.elem),
+        Type::Path(path) if ["Box", "Option", "Arc"].iter().any(|ident| last_ident_is(path, ident)) => {
+            first_generic_type(path).map_or(ty, handle_type)
+        },
+        _ => ty,
+    }
+}
+
+// &T and Option<&T>, which C must not modify or release, so are passed as const T*
+fn is_shared_borrow(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(ref_type) => ref_type.mutability.is_none(),
+        Type::Path(path) if last_ident_is(path, "Option") => {
+            matches!(first_generic_type(path), Some(Type::Reference(ref_type)) if ref_type.mutability.is_none())
+        },
+        _ => false,
+    }
+}
+
+fn is_primitive(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.get_ident().is_some_and(|ident| {
+            ident == "char" || primitive_to_c_type(&ident.to_string()).is_some()
+        }),
+        _ => false,
+    }
+}
+
+fn primitive_to_c_type(primitive: &str) -> Option<String> {
+    let c_type = match primitive {
+        "bool" => "bool",
+        "f32" => "float",
+        "f64" => "double",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "isize" => "intptr_t",
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "usize" => "size_t",
+        _ => return None,
+    };
+    Some(String::from(c_type))
+}
+
+// Methods are exported as free functions, where Self means nothing, so replace it with the target
+fn resolve_self(target: &Struct, method: &ImplItemMethod) -> ImplItemMethod {
+    let mut method = method.clone();
+    method.sig.inputs.iter_mut().for_each(|input| {
+        if let FnArg::Typed(typed) = input {
+            *typed.ty = replace_self(&typed.ty, &target.id);
+        }
+    });
+    if let ReturnType::Type(_, ty) = &mut method.sig.output {
+        **ty = replace_self(ty, &target.id);
+    }
+    method
+}
+
+fn replace_self(ty: &Type, target: &Ident) -> Type {
+    syn::parse2(replace_self_tokens(ty.to_token_stream(), target)).unwrap()
+}
+
+fn replace_self_tokens(tokens: TokenStream, target: &Ident) -> TokenStream {
+    tokens.into_iter().map(|token| match token {
+        TokenTree::Ident(ident) if ident == "Self" => TokenTree::Ident(target.clone()),
+        TokenTree::Group(group) => {
+            let mut replaced = Group::new(group.delimiter(), replace_self_tokens(group.stream(), target));
+            replaced.set_span(group.span());
+            TokenTree::Group(replaced)
+        },
+        token => token,
+    }).collect()
+}
+
+fn is_u8(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path.path.is_ident("u8"),
+        _ => false,
+    }
+}
+
+fn last_ident_is(ty_path: &TypePath, ident: &str) -> bool {
+    ty_path.path.segments.last().is_some_and(|segment| segment.ident == ident)
+}
+
+fn first_generic_type(ty_path: &TypePath) -> Option<&Type> {
+    if let PathArguments::AngleBracketed(generics) = &ty_path.path.segments.last()?.arguments {
+        if let Some(GenericArgument::Type(inner)) = generics.args.first() {
+            return Some(inner);
+        }
+    }
+    None
+}
+
+// For args passed as multiple C parameters, this is the type of the first (the data pointer).
+// Types should be checked with rust_to_c_type first, which gives the more specific errors.
+pub fn rust_to_ffi_type(ty: &Type) -> Result<TokenStream, syn::Error> {
+    match marshal_type(ty) {
+        Marshal::Str | Marshal::String | Marshal::OptionStr | Marshal::CStr => {
+            return Ok(quote!{ *const std::os::raw::c_char })
+        },
+        Marshal::Slice | Marshal::Bytes => return Ok(quote!{ *const u8 }),
+        Marshal::SliceMut | Marshal::OutBuffer => return Ok(quote!{ *mut u8 }),
+        Marshal::Ref | Marshal::OptionRef if is_shared_borrow(ty) => {
+            let ty = handle_type(ty);
+            return Ok(quote!{ *const #ty })
+        },
+        Marshal::Ref | Marshal::OptionMut | Marshal::Boxed | Marshal::OptionBoxed | Marshal::OptionHandle => {
+            let ty = handle_type(ty);
+            return Ok(quote!{ *mut #ty })
+        },
+        Marshal::Arc | Marshal::Array => {
+            let ty = handle_type(ty);
+            return Ok(quote!{ *const #ty })
+        },
+        Marshal::Callback | Marshal::OptionCallback => {
+            let (inputs, output) = callback_signature(ty).ok_or_else(|| unsupported_type(ty))?;
+            let inputs = inputs.iter().map(rust_to_ffi_type).collect::<Result<Vec<TokenStream>, syn::Error>>()?;
+            return Ok(quote!{ Option<extern "C" fn(#(#inputs,)* *mut std::ffi::c_void) #output> })
+        },
+        Marshal::FfiValue => return Ok(quote!{ <#ty as crate::ffi::FfiValue>::Ffi }),
+        _ => ()
+    }
+    match ty {
+        Type::Path(ty_path) => return Ok(ty_path.path.to_token_stream()),
+        Type::Ptr(ptr_ty) => return Ok(ptr_ty.to_token_stream()),
+        Type::Verbatim(tokens) => return Ok(tokens.to_token_stream()),
+        _ => ()
+    }
+    Err(unsupported_type(ty))
+}
+
+// The C type of a Rust type in exported signatures, or an error spanned to the type if it can't
+// be passed to C. Handles are pointers to the (opaque) struct, and arrays are written as T[N].
+pub fn rust_to_c_type(ty: &Type) -> Result<String, syn::Error> {
+    match marshal_type(ty) {
+        Marshal::Str | Marshal::String | Marshal::OptionStr | Marshal::CStr => {
+            return Ok(String::from("const char*"))
+        },
+        Marshal::Slice | Marshal::Bytes => return Ok(String::from("const uint8_t*")),
+        Marshal::SliceMut | Marshal::OutBuffer => return Ok(String::from("uint8_t*")),
+        Marshal::Ref | Marshal::OptionRef | Marshal::OptionMut | Marshal::Boxed | Marshal::OptionBoxed | Marshal::OptionHandle | Marshal::Arc => {
+            let pointee = match handle_type(ty) {
+                Type::Path(path) if first_generic_type(path).is_none() => rust_to_c_type(handle_type(ty))?,
+                _ => return Err(unsupported_type(ty)),
+            };
+            return Ok(match is_shared_borrow(ty) {
+                true => format!("const {}*", pointee),
+                false => format!("{}*", pointee),
+            })
+        },
+        Marshal::Callback | Marshal::OptionCallback => {
+            let (inputs, output) = callback_signature(ty).ok_or_else(|| unsupported_type(ty))?;
+            let mut params = inputs.iter().map(|input| match (input, marshal_type(input)) {
+                (Type::Ptr(_), _) | (_, Marshal::FfiValue) => rust_to_c_type(input),
+                _ => Err(syn::Error::new_spanned(input, "Callback args must be FFI values or raw pointers")),
+            }).collect::<Result<Vec<String>, syn::Error>>()?;
+            params.push(String::from("void*"));
+            let return_type = match &output {
+                ReturnType::Default => String::from("void"),
+                ReturnType::Type(_, ty) if is_primitive(ty) || matches!(ty.as_ref(), Type::Ptr(_)) => rust_to_c_type(ty)?,
+                ReturnType::Type(_, ty) => {
+                    return Err(syn::Error::new_spanned(ty, "Callbacks may only return primitives or raw pointers"))
+                },
+            };
+            return Ok(format!("{} (*)({})", return_type, params.join(", ")))
+        },
+        _ => ()
+    }
+    match ty {
+        Type::Path(ty_path) => path_to_c_type(ty_path).ok_or_else(|| unsupported_type(ty)),
+        Type::Ptr(ptr_ty) => {
+            let pointee = rust_to_c_type(&ptr_ty.elem)?;
+            if pointee.contains('[') || pointee.contains('(') {
+                return Err(unsupported_type(ty));
+            }
+            // const applies to the pointee, which may itself be a pointer
+            Ok(match (ptr_ty.const_token.is_some(), pointee.ends_with('*')) {
+                (true, true) => format!("{} const*", pointee),
+                (true, false) => format!("const {}*", pointee),
+                (false, _) => format!("{}*", pointee),
+            })
+        },
+        Type::Array(array_ty) => {
+            // [[u8; 4]; 2] is uint8_t[2][4], so the outer length goes before any inner ones
+            let elem = rust_to_c_type(&array_ty.elem)?;
+            let len = array_ty.len.to_token_stream().to_string();
+            Ok(match elem.find('[') {
+                Some(idx) => format!("{}[{}]{}", &elem[..idx], len, &elem[idx..]),
+                None => format!("{}[{}]", elem, len),
+            })
+        },
+        Type::Tuple(tuple) if tuple.elems.is_empty() => Ok(String::from("void")),
+        Type::Paren(paren) => rust_to_c_type(&paren.elem),
+        Type::Group(group) => rust_to_c_type(&group.elem),
+        Type::Verbatim(tokens) => Ok(tokens.to_string()),
+        _ => Err(unsupported_type(ty)),
+    }
+}
+
+// Primitives, std::os::raw/libc types and #[repr(C)] structs or enums by name, from any path
+fn path_to_c_type(ty_path: &TypePath) -> Option<String> {
+    let segment = ty_path.path.segments.last()?;
+    if !segment.arguments.is_empty() || ty_path.qself.is_some() {
+        return None;
+    }
+    let ident = segment.ident.to_string();
+    if let Some(c_type) = primitive_to_c_type(&ident) {
+        return Some(c_type);
+    }
+    let c_type = match ident.as_str() {
+        "c_char" => "char",
+        "c_schar" => "signed char",
+        "c_uchar" => "unsigned char",
+        "c_short" => "short",
+        "c_ushort" => "unsigned short",
+        "c_int" => "int",
+        "c_uint" => "unsigned int",
+        "c_long" => "long",
+        "c_ulong" => "unsigned long",
+        "c_longlong" => "long long",
+        "c_ulonglong" => "unsigned long long",
+        "c_float" => "float",
+        "c_double" => "double",
+        "c_void" => "void",
+        // Rust types with no C equivalent
+        "char" | "str" | "i128" | "u128" | "Self" => return None,
+        _ => &ident,
+    };
+    Some(String::from(c_type))
+}
+
+fn unsupported_type(ty: &Type) -> syn::Error {
+    syn::Error::new_spanned(ty, format!("Unsupported FFI type: {}", ty.to_token_stream()))
+}
+
+// Renames the structs and enums in a c_type from their Rust names to their exported C names, e.g.
+// const Point* to const aws_point*
+pub fn rename_c_type(c_type: &str, names: &HashMap<String, String>) -> String {
+    let mut renamed = String::new();
+    let mut ident = String::new();
+    for c in c_type.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() || c == '_' {
+            ident.push(c);
+            continue;
+        }
+        renamed.push_str(names.get(&ident).unwrap_or(&ident));
+        ident.clear();
+        renamed.push(c);
+    }
+    renamed.pop();
+    renamed
+}
+
+// Declares name as a c_type, e.g. uint8_t digest[32] or void (*callback)(void*)
+pub fn c_declaration(c_type: &str, name: &str) -> String {
+    if let Some(idx) = c_type.find("(*)") {
+        return format!("{}(*{}){}", &c_type[..idx], name, &c_type[idx + 3..]);
+    }
+    if let Some(idx) = c_type.find('[') {
+        return format!("{} {}{}", &c_type[..idx], name, &c_type[idx..]);
+    }
+    format!("{} {}", c_type, name)
+}
+
+// Returned strings are copied into a char* owned by the caller, to be freed with crtrs_string_free.
+// Returned byte slices borrow from the callee as a CByteCursor, and Vec<u8> is handed over as a
+// CByteBuf, to be freed with crtrs_byte_buf_free.
+pub fn rust_to_ffi_return_type(ty: &Type) -> Result<TokenStream, syn::Error> {
+    match marshal_type(ty) {
+        Marshal::Str | Marshal::String | Marshal::OptionStr => Ok(quote!{ *mut std::os::raw::c_char }),
+        Marshal::Slice => Ok(quote!{ crate::CByteCursor }),
+        Marshal::Bytes => Ok(quote!{ crate::CByteBuf }),
+        _ => rust_to_ffi_type(ty),
+    }
+}
+
+// C functions can't return arrays, so they must be wrapped in a #[repr(C)] struct
+pub fn rust_to_c_return_type(ty: &Type) -> Result<String, syn::Error> {
+    match marshal_type(ty) {
+        Marshal::Str | Marshal::String | Marshal::OptionStr => Ok(String::from("char*")),
+        Marshal::Slice => Ok(String::from("CByteCursor")),
+        Marshal::Bytes => Ok(String::from("CByteBuf")),
+        Marshal::Array => Err(syn::Error::new_spanned(ty, "Arrays cannot be returned to C, return a #[repr(C)] struct containing the array instead")),
+        _ => rust_to_c_type(ty),
+    }
+}
+
+pub fn method_is_static(method: &ImplItemMethod) -> bool {
+    method.sig.receiver().is_none()
+}
+
+// A constructor is a static method returning Self (or Result<Self, E>), which is exported as a heap handle
+pub fn method_is_constructor(target: &Struct, method: &ImplItemMethod) -> bool {
+    if !method_is_static(method) {
+        return false;
+    }
+    let ty = match returned_type(method) {
+        Some(ty) => ty,
+        None => return false,
+    };
+    if let Type::Path(ty_path) = ty {
+        if let Some(ident) = ty_path.path.get_ident() {
+            return ident == "Self" || *ident == target.id;
+        }
+    }
+    false
+}
+
+// The type of the value produced by the method, which is T for Result<T, E>
+pub fn returned_type(method: &ImplItemMethod) -> Option<&Type> {
+    match (&method.sig.output, result_ok_type(&method.sig.output)) {
+        (_, Some(ok_ty)) => Some(ok_ty),
+        (ReturnType::Type(_, ty), None) => Some(ty.as_ref()),
+        (ReturnType::Default, None) => None,
+    }
+}
+
+// Returns T for methods returning Result<T, E>
+pub fn result_ok_type(output: &ReturnType) -> Option<&Type> {
+    if let ReturnType::Type(_, ty) = output {
+        if let Type::Path(ty_path) = ty.as_ref() {
+            if last_ident_is(ty_path, "Result") {
+                return first_generic_type(ty_path);
+            }
+        }
+    }
+    None
+}
+
+pub fn impl_trait_is(impl_item: &ItemImpl, trait_name: &str) -> bool {
+    impl_trait(impl_item).is_some_and(|ident| ident == trait_name)
+}
+
+// The name of the trait being implemented, e.g. Display for impl std::fmt::Display for T
+pub fn impl_trait(impl_item: &ItemImpl) -> Option<&Ident> {
+    match &impl_item.trait_ {
+        Some((_, path, _)) => path.segments.last().map(|segment| &segment.ident),
+        None => None,
+    }
+}
+
+// Doc and #[deprecated] attributes, which are kept on the set_/get_ methods of fields
+fn is_doc_attr(attr: &Attribute) -> bool {
+    attr.path.is_ident("doc") || attr.path.is_ident("deprecated")
+}
+
+// The traits named in #[derive(..)] attributes on the item
+fn derived_traits(attrs: &[Attribute]) -> Vec<Ident> {
+    let mut traits = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("derive")) {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested.iter() {
+                if let NestedMeta::Meta(Meta::Path(path)) = nested {
+                    if let Some(segment) = path.segments.last() {
+                        traits.push(segment.ident.clone());
+                    }
+                }
+            }
+        }
+    }
+    traits
+}
+
+pub fn impl_target(impl_item: &ItemImpl) -> Result<&Ident, syn::Error> {
+    let struct_type = &impl_item.self_ty;
+    match struct_type.as_ref() {
+        Type::Path(struct_path) => struct_path.path.get_ident().ok_or_else(|| {
+            syn::Error::new_spanned(struct_type, "crt_export impls must be for a struct in the same module, without generics")
+        }),
+        _ => Err(syn::Error::new_spanned(struct_type, "No struct found in target item")),
+    }
+}
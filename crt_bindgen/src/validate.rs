@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use syn::{Generics, GenericParam, ImplItemMethod, Item, ItemImpl};
+
+use crate::types::{self, Method};
+
+/// Checks that the item can be exported, reporting anything which can't at the offending tokens
+pub fn validate_item(item: &Item, exported_traits: &[&str]) -> Result<(), syn::Error> {
+    match item {
+        Item::Struct(struct_item) => validate_generics(&struct_item.generics, "structs"),
+        Item::Enum(enum_item) => validate_generics(&enum_item.generics, "enums"),
+        Item::Impl(impl_item) => validate_impl(impl_item, exported_traits),
+        Item::Fn(_) | Item::Const(_) | Item::Static(_) => Ok(()),
+        _ => Err(syn::Error::new_spanned(item, "crt_export is only applicable to struct, enum, impl, fn, const or static")),
+    }
+}
+
+fn validate_impl(impl_item: &ItemImpl, exported_traits: &[&str]) -> Result<(), syn::Error> {
+    validate_generics(&impl_item.generics, "impls")?;
+    types::impl_target(impl_item)?;
+    if let Some((_, path, _)) = &impl_item.trait_ {
+        let trait_name = types::impl_trait(impl_item).unwrap();
+        let is_exportable = trait_name == "Drop" || trait_name == "Default"
+            || exported_traits.iter().any(|t| trait_name == t);
+        if !is_exportable {
+            let msg = format!(
+                "impls of {} cannot be exported, only Drop, Default, {} are supported. Export the methods from an inherent impl instead.",
+                trait_name, exported_traits.join(", "));
+            return Err(syn::Error::new_spanned(path, msg));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a method in an exported impl can be called from a C shim
+pub fn validate_method(method: &ImplItemMethod) -> Result<(), syn::Error> {
+    let sig = &method.sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new_spanned(asyncness, "async fn cannot be exported, take a completion callback instead"));
+    }
+    if let Some(unsafety) = &sig.unsafety {
+        return Err(syn::Error::new_spanned(unsafety, "unsafe fn cannot be exported, as the shim would call it without checking its contract"));
+    }
+    if let Some(abi) = &sig.abi {
+        return Err(syn::Error::new_spanned(abi, "Exported methods must be Rust fns, the extern \"C\" shim is generated"));
+    }
+    if let Some(variadic) = &sig.variadic {
+        return Err(syn::Error::new_spanned(variadic, "Variadic fns cannot be exported"));
+    }
+    validate_generics(&sig.generics, "methods")
+}
+
+// C has no generics, and borrows can't outlive the call, so neither can be exported
+fn validate_generics(generics: &Generics, items: &str) -> Result<(), syn::Error> {
+    if let Some(param) = generics.params.first() {
+        let msg = match param {
+            GenericParam::Lifetime(_) => format!("Exported {} cannot have lifetime parameters, as C cannot be held to them", items),
+            _ => format!("Exported {} cannot be generic, export a wrapper for each concrete type instead", items),
+        };
+        return Err(syn::Error::new_spanned(param, msg));
+    }
+    if let Some(where_clause) = &generics.where_clause {
+        return Err(syn::Error::new_spanned(where_clause, format!("Exported {} cannot be generic", items)));
+    }
+    Ok(())
+}
+
+/// Checks that no two methods are exported under the same symbol, and that none collide with the
+/// functions generated for the struct, as given by Struct::generated_functions
+pub fn validate_names(methods: &[Method], generated: &[String]) -> Result<(), syn::Error> {
+    let mut errors: Vec<syn::Error> = vec![];
+    let mut exported: HashMap<String, &Method> = HashMap::new();
+    for method in methods.iter() {
+        let name = method.exported_name();
+        let ident = &method.method.sig.ident;
+        if let Some(previous) = exported.get(&name) {
+            let msg = format!("{} is exported as {}, which is already exported by {}", ident, name, previous.method.sig.ident);
+            errors.push(syn::Error::new_spanned(ident, msg));
+            continue;
+        }
+        if generated.contains(&name) {
+            let msg = format!("{} is exported as {}, which collides with the function generated for {}", ident, name, method.target.id);
+            errors.push(syn::Error::new_spanned(ident, msg));
+            continue;
+        }
+        exported.insert(name, method);
+    }
+    combine(errors)
+}
+
+// Reports all of the errors, rather than only the first
+pub fn combine(errors: Vec<syn::Error>) -> Result<(), syn::Error> {
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut error) => {
+            errors.for_each(|e| error.combine(e));
+            Err(error)
+        },
+        None => Ok(()),
+    }
+}
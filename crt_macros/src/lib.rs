@@ -1,71 +1,345 @@
-#![feature(with_options)]
-
-mod header;
-mod plugin;
-mod types;
-
 extern crate proc_macro;
 
 use proc_macro::TokenStream as RawTokenStream;
-use proc_macro2::TokenStream;
-use syn::{parse_macro_input, ImplItem, ImplItemMethod, Item, ItemImpl, ItemStruct, ReturnType};
-use quote::{quote, format_ident, ToTokens};
+use proc_macro2::{Span, TokenStream};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, AttributeArgs, Ident, Item, ReturnType, Type};
+use quote::{quote, quote_spanned, format_ident, ToTokens};
 
-use types::{Method, Struct};
+use crt_bindgen::args::ExportArgs;
+use crt_bindgen::types::{self, Enum, Marshal, Method, MethodArg, Struct};
+use crt_bindgen::{parse_target, validate, Target, EXPORTED_TRAITS};
 
 #[proc_macro_attribute]
-pub fn crt_export(_attr: RawTokenStream, tokens: RawTokenStream) -> RawTokenStream {
-    let original = tokens.clone();
-    let macro_target = parse_macro_input!(tokens as Item);
-    let target = parse_target(macro_target).unwrap();
-
-    let mut output : RawTokenStream = match target {
-        Target::Struct(struct_target) => export_struct(&struct_target).into(),
-        Target::Impl(impl_target) => export_impl(&impl_target).into(),
-    };
-    output.extend(original);
-    // uncomment this if you get ICEs or panics during compilation and cargo expand won't
+pub fn crt_export(attr: RawTokenStream, tokens: RawTokenStream) -> RawTokenStream {
+    let attr_args = parse_macro_input!(attr as AttributeArgs);
+    let mut macro_target = parse_macro_input!(tokens as Item);
+    let output = ExportArgs::new(attr_args).and_then(|export_args| {
+        validate::validate_item(&macro_target, &EXPORTED_TRAITS)?;
+        let target = parse_target(&mut macro_target, &export_args)?;
+        export_target(&target)
+    });
+
+    // Errors are reported at the offending tokens, and the original item is still emitted so
+    // that they don't cascade into errors wherever it is used
+    let mut output = output.unwrap_or_else(|err| err.to_compile_error());
+    // The original item is emitted without any #[crt_export] attributes on its methods
+    output.extend(macro_target.into_token_stream());
+    // uncomment this to see the generated code if it fails to compile and cargo expand won't
     // show you the source
     //println!("{}", output);
     output.into()
 }
 
-enum Target {
-    Struct(Struct),
-    Impl(Vec<Method>),
+fn export_target(target: &Target) -> Result<TokenStream, syn::Error> {
+    match target {
+        Target::Struct(struct_target) => export_struct(struct_target),
+        Target::Enum(enum_target) => Ok(export_enum(enum_target)),
+        Target::Impl(impl_target) => Ok(match impl_target.first() {
+            Some(method) => {
+                let (check, methods) = (export_naming_check(&method.target), export_impl(impl_target));
+                quote! { #check #methods }
+            },
+            None => TokenStream::new(),
+        }),
+        Target::Trait(struct_target, trait_name) => {
+            let (check, function) = (export_naming_check(struct_target), export_trait(struct_target, trait_name));
+            Ok(quote! { #check #function })
+        },
+        Target::Function(function) => Ok(export_static_method(function)),
+        // Consts only appear in the header, and statics are exported by their #[export_name]
+        Target::Constant(_) => Ok(TokenStream::new()),
+    }
 }
 
-fn parse_target(macro_target: Item) -> Result<Target, &'static str> {
-    return match macro_target {
-        Item::Struct(struct_item) => Ok(Target::Struct(parse_struct(&struct_item))),
-        Item::Impl(impl_item) => Ok(Target::Impl(parse_impl(impl_item))),
-        _ => Err("crt_export attached to unknown token, only applicable to struct or impl")
+fn export_struct(struct_target: &Struct) -> Result<TokenStream, syn::Error> {
+    let target = &struct_target.id;
+    let handle = match struct_target.refcounted {
+        true => export_refcounted_handle(struct_target),
+        false => export_boxed_handle(struct_target),
+    };
+    let builder = match struct_target.builder {
+        true => export_builder(struct_target)?,
+        false => TokenStream::new(),
+    };
+    let derives: Vec<TokenStream> = struct_target.derives.iter()
+        .filter(|derive| EXPORTED_TRAITS.iter().any(|t| *derive == t))
+        .map(|derive| export_trait(struct_target, derive))
+        .collect();
+    // Only structs whose fields C can see are passed by value, the rest are opaque to C, so
+    // can only be passed by reference or as a handle. #[repr(C)] must come last, as it applies
+    // to the original struct which follows.
+    let by_value = match struct_target.c_fields() {
+        Some(_) => quote! {
+            #[allow(deprecated)]
+            impl crate::ffi::FfiValue for #target {
+                type Ffi = #target;
+
+                fn from_ffi(value: #target) -> Result<#target, crate::error::Error> {
+                    Ok(value)
+                }
+
+                fn into_ffi(self) -> #target {
+                    self
+                }
+            }
+        },
+        None => TokenStream::new(),
+    };
+    let repr = match struct_target.c_fields() {
+        Some(_) => quote! { #[repr(C)] },
+        None => TokenStream::new(),
+    };
+    let exported_name = struct_target.exported_name();
+    let gen = quote!{
+        #[allow(deprecated)]
+        impl #target {
+            #[doc(hidden)]
+            pub(crate) const __CRT_EXPORTED_NAME: &'static str = #exported_name;
+        }
+
+        #by_value
+        #handle
+        #builder
+        #(#derives)*
+
+        #repr
+    };
+    Ok(gen)
+}
+
+// Impls only see their own #[crt_export] args, so must repeat the struct's prefix, name and style
+// to export their functions under the struct's name, which is checked when the crate is compiled
+fn export_naming_check(struct_target: &Struct) -> TokenStream {
+    let target = &struct_target.id;
+    let exported_name = struct_target.exported_name();
+    let message = format!("{} is exported under a different name by its struct, repeat the struct's #[crt_export] prefix, name and style here", target);
+    quote_spanned! {target.span()=>
+        #[allow(deprecated)]
+        const _: () = assert!(crate::ffi::same_exported_name(#target::__CRT_EXPORTED_NAME, #exported_name), #message);
     }
 }
 
-fn parse_struct(struct_item: &ItemStruct) -> Struct {
-    Struct::new(&struct_item.ident)
+// Handles are a Box, owned by the caller until {Struct}_destroy
+fn export_boxed_handle(struct_target: &Struct) -> TokenStream {
+    let target = &struct_target.id;
+    let destroy_fn = format_ident!("{}_destroy", struct_target.exported_name());
+    quote! {
+        #[allow(deprecated)]
+        impl crate::ffi::Handle for #target {
+            fn into_handle(self) -> *mut #target {
+                Box::into_raw(Box::new(self))
+            }
+
+            unsafe fn from_handle(handle: *mut #target) -> Result<#target, crate::error::Error> {
+                Ok(*Box::from_raw(handle))
+            }
+        }
+
+        #[allow(non_snake_case)]
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[no_mangle]
+        pub extern "C" fn #destroy_fn(this: *mut #target) {
+            if !this.is_null() {
+                crate::error::catch_panic(move || {
+                    std::mem::drop(unsafe { Box::from_raw(this) });
+                });
+            }
+        }
+    }
+}
+
+// Handles are a reference to an Arc: {Struct}_acquire adds a reference, and {Struct}_release
+// drops one, so the struct is dropped with the last reference. Methods taking self: Arc<Self>
+// can hand out further references by returning Arc<Self>.
+fn export_refcounted_handle(struct_target: &Struct) -> TokenStream {
+    let target = &struct_target.id;
+    let acquire_fn = format_ident!("{}_acquire", struct_target.exported_name());
+    let release_fn = format_ident!("{}_release", struct_target.exported_name());
+    quote! {
+        #[allow(deprecated)]
+        impl crate::ffi::Handle for #target {
+            fn into_handle(self) -> *mut #target {
+                std::sync::Arc::into_raw(std::sync::Arc::new(self)) as *mut #target
+            }
+
+            // Consuming a refcounted handle requires it to be the last reference
+            unsafe fn from_handle(handle: *mut #target) -> Result<#target, crate::error::Error> {
+                std::sync::Arc::try_unwrap(std::sync::Arc::from_raw(handle)).map_err(|shared| {
+                    std::mem::forget(shared);
+                    crate::error::Error::new(crate::error::AWS_ERROR_INVALID_ARGUMENT)
+                })
+            }
+        }
+
+        #[allow(deprecated)]
+        unsafe impl crate::ffi::RefCounted for #target {}
+
+        #[allow(non_snake_case)]
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[no_mangle]
+        pub extern "C" fn #acquire_fn(this: *const #target) -> *mut #target {
+            if this.is_null() {
+                crate::error::null_argument();
+                return std::ptr::null_mut();
+            }
+            crate::error::catch_panic(move || {
+                unsafe { std::sync::Arc::increment_strong_count(this) };
+                this as *mut #target
+            }).unwrap_or_else(std::ptr::null_mut)
+        }
+
+        #[allow(non_snake_case)]
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[no_mangle]
+        pub extern "C" fn #release_fn(this: *const #target) {
+            if !this.is_null() {
+                crate::error::catch_panic(move || {
+                    unsafe { std::sync::Arc::decrement_strong_count(this) };
+                });
+            }
+        }
+    }
 }
 
-fn parse_impl(impl_item: ItemImpl) -> Vec<Method> {
-    let mut methods: Vec<Method> = vec![];
-    let struct_target = Struct::new(types::impl_target(&impl_item).unwrap());
-    for item in impl_item.items.iter() {
-        if let ImplItem::Method(method) = item {
-            methods.push(Method::new(&struct_target, method));
+// Builder structs are opaque to C: {Struct}_new() creates one from Default, and each field gets
+// {Struct}_set_<field> and {Struct}_get_<field>, so fields can be added without breaking the ABI
+fn export_builder(struct_target: &Struct) -> Result<TokenStream, syn::Error> {
+    let target = &struct_target.id;
+    let new_fn = format_ident!("{}_new", struct_target.exported_name());
+    let accessors = struct_target.accessors()?;
+    let marker = export_symbol_marker(target, &new_fn.to_string(), target.span());
+    let mut gen = quote! {
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        impl #target {
+            #(#accessors)*
         }
+
+        #marker
+
+        #[allow(non_snake_case)]
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        #[no_mangle]
+        pub extern "C" fn #new_fn() -> *mut #target {
+            crate::error::catch_panic(move || {
+                crate::ffi::Handle::into_handle(<#target as Default>::default())
+            }).unwrap_or_else(std::ptr::null_mut)
+        }
+    };
+    for accessor in accessors.iter() {
+        gen.extend(export_method(&Method::new(struct_target, accessor)?));
     }
-    methods
+    Ok(gen)
 }
 
-fn export_struct(_struct_target: &Struct) -> TokenStream {
+// Enums are passed as their repr, and validated when converted from C
+fn export_enum(enum_target: &Enum) -> TokenStream {
+    let target = &enum_target.id;
+    let repr = &enum_target.repr;
+    let variants: Vec<&Ident> = enum_target.variants.iter().map(|v| &v.id).collect();
     let gen = quote!{
-        #[repr(C)]
+        #[allow(deprecated)]
+        impl crate::ffi::FfiValue for #target {
+            type Ffi = #repr;
+
+            fn from_ffi(value: #repr) -> Result<#target, crate::error::Error> {
+                #(
+                    if value == #target::#variants as #repr {
+                        return Ok(#target::#variants);
+                    }
+                )*
+                Err(crate::error::Error::new(crate::error::AWS_ERROR_INVALID_ARGUMENT))
+            }
+
+            fn into_ffi(self) -> #repr {
+                self as #repr
+            }
+        }
     };
-    gen.into()
+    gen
 }
 
-fn export_impl(methods: &Vec<Method>) -> TokenStream {
+// Clone -> {Struct}_clone, PartialEq -> {Struct}_equals, Debug -> {Struct}_debug_string, Display ->
+// {Struct}_to_string and Hash -> {Struct}_hash, whether derived or implemented by hand
+fn export_trait(struct_target: &Struct, trait_name: &Ident) -> TokenStream {
+    let target = &struct_target.id;
+    let null_argument = |arg: &str, failure: TokenStream| {
+        let arg_id = format_ident!("{}", arg);
+        quote! {
+            let #arg_id = match unsafe { #arg_id.as_ref() } {
+                Some(#arg_id) => #arg_id,
+                None => {
+                    crate::error::null_argument();
+                    return #failure;
+                },
+            };
+        }
+    };
+    let this = null_argument("this", quote! { crate::error::FailureValue::failure_value() });
+    let (suffix, args, return_ty, body) = match trait_name.to_string().as_str() {
+        "Clone" => ("clone", quote! { this: *const #target }, quote! { *mut #target }, quote! {
+            #this
+            crate::ffi::Handle::into_handle(Clone::clone(this))
+        }),
+        "PartialEq" => {
+            let other = null_argument("other", quote! { false });
+            ("equals", quote! { this: *const #target, other: *const #target }, quote! { bool }, quote! {
+                #this
+                #other
+                PartialEq::eq(this, other)
+            })
+        },
+        "Debug" | "Display" => {
+            let (suffix, format) = match trait_name.to_string().as_str() {
+                "Debug" => ("debug_string", quote! { format!("{:?}", this) }),
+                _ => ("to_string", quote! { format!("{}", this) }),
+            };
+            (suffix, quote! { this: *const #target }, quote! { *mut std::os::raw::c_char }, quote! {
+                #this
+                match crate::ffi::IntoCString::into_c_string(#format) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        err.raise();
+                        std::ptr::null_mut()
+                    },
+                }
+            })
+        },
+        "Hash" => ("hash", quote! { this: *const #target }, quote! { u64 }, quote! {
+            #this
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            std::hash::Hash::hash(this, &mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        }),
+        _ => unreachable!("{} is not in EXPORTED_TRAITS", trait_name),
+    };
+    let exported_fn = format_ident!("{}_{}", struct_target.exported_name(), suffix);
+    let marker = export_symbol_marker(target, &exported_fn.to_string(), trait_name.span());
+    quote! {
+        #marker
+
+        #[allow(non_snake_case)]
+        #[allow(dead_code)]
+        #[allow(deprecated)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[no_mangle]
+        pub extern "C" fn #exported_fn(#args) -> #return_ty {
+            crate::error::catch_panic(move || -> #return_ty {
+                #body
+            }).unwrap_or_else(crate::error::FailureValue::failure_value)
+        }
+    }
+}
+
+fn export_impl(methods: &[Method]) -> TokenStream {
     let mut gen_tokens = TokenStream::new();
     methods.iter().for_each(|method| {
         gen_tokens.extend(export_method(method))
@@ -74,119 +348,403 @@ fn export_impl(methods: &Vec<Method>) -> TokenStream {
 }
 
 fn export_method(method: &Method) -> TokenStream {
-    return if method.is_static {
+    if method.is_static {
         export_static_method(method)
     } else {
         export_self_method(method)
     }
 }
 
-fn export_return_type(method: &ImplItemMethod) -> TokenStream {
-    match &method.sig.output {
+fn export_return_type(method: &Method) -> TokenStream {
+    if method.is_fallible {
+        return quote! { -> i32 };
+    }
+    if method.is_constructor {
+        let target = &method.target.id;
+        return quote! { -> *mut #target };
+    }
+    match &method.method.sig.output {
         ReturnType::Default => TokenStream::new(),
-        ReturnType::Type(_, ty) => quote! { -> #ty }
+        ReturnType::Type(_, ty) => {
+            // Return types are checked when parsing the method, so this is only a fallback
+            let ffi_ty = types::rust_to_ffi_return_type(ty).unwrap_or_else(|err| err.to_compile_error());
+            quote! { -> #ffi_ty }
+        }
     }
 }
 
 fn export_rust_args(method: &Method) -> TokenStream {
     let mut args = TokenStream::new();
-    method.args.iter().for_each(|a| {
+    method.args.iter().chain(method.out.iter()).for_each(|a| {
         if !args.is_empty() {
             (quote! {, }).to_tokens(&mut args);
         }
         a.tokens.to_tokens(&mut args);
     });
-    args.into()
+    args
 }
 
 fn export_rust_arg_refs(method: &Method) -> TokenStream {
     let mut args = TokenStream::new();
-    let mut all_args = method.exported_args();
-    if !method.is_static {
-        all_args = if all_args.len() > 1 {
-            all_args[1..].to_owned()
-        } else {
-            vec![]
-        }
-    }
-    all_args.iter().for_each(|a| {
+    let skip = if method.is_static { 0 } else { 1 };
+    method.args.iter().skip(skip).for_each(|a| {
         if !args.is_empty() {
             (quote! {, }).to_tokens(&mut args);
         }
-        let arg_ty = &a.1;
-        let arg = format_ident!("{}", a.0);
-        let arg_name = arg.to_string();
-        if arg_ty.ends_with("*") {
-            (quote! { unsafe { #arg.as_ref().expect(&format!("NULL provided for {}", #arg_name)) } }).to_tokens(&mut args);
-        } else {
-            arg.to_tokens(&mut args);
-        }
+        format_ident!("{}", a.name).to_tokens(&mut args);
     });
-    args.into()
+    args
 }
 
-fn export_static_method(method: &Method) -> TokenStream {
-    let fn_name = &method.method.sig.ident;
-    let exported_fn = format_ident!("{}_{}", method.target.id, method.method.sig.ident);
-    let args = export_rust_args(method);
-    let arg_names = export_rust_arg_refs(method);
-    let return_ty = export_return_type(&method.method);
-    let return_kw = match return_ty.is_empty() {
-        true => return_ty.clone(),
-        false => quote! { return },
-    };
-    let target = &method.target.id;
-    let gen = quote! {
-        #[allow(non_snake_case)]
-        #[allow(dead_code)]
-        #[no_mangle]
-        pub extern "C" fn #exported_fn(#args) #return_ty {
-            #return_kw #target::#fn_name(#arg_names);
+// The value returned to C when the call cannot be made, or panics
+fn export_failure_value(method: &Method) -> TokenStream {
+    match method.is_fallible {
+        true => quote! { crate::error::AWS_OP_ERR },
+        false => quote! { crate::error::FailureValue::failure_value() },
+    }
+}
+
+// Converts FFI args (including this) into their Rust types, failing the call if any are invalid
+fn export_arg_checks(method: &Method) -> TokenStream {
+    let failure = export_failure_value(method);
+    let mut checks = TokenStream::new();
+    method.args.iter().for_each(|a| {
+        let arg = format_ident!("{}", a.name);
+        let null_argument = quote! {
+            {
+                crate::error::null_argument();
+                return #failure;
+            }
+        };
+        let check = match a.marshal {
+            Marshal::Value => return,
+            Marshal::FfiValue => {
+                let ty = &a.ty;
+                quote! {
+                    let #arg = match <#ty as crate::ffi::FfiValue>::from_ffi(#arg) {
+                        Ok(#arg) => #arg,
+                        Err(err) => {
+                            err.raise();
+                            return #failure;
+                        },
+                    };
+                }
+            },
+            Marshal::Ref => {
+                let as_ref = match &a.ty {
+                    Type::Reference(ref_type) if ref_type.mutability.is_some() => quote! { as_mut },
+                    _ => quote! { as_ref },
+                };
+                quote! {
+                    let #arg = match unsafe { #arg.#as_ref() } {
+                        Some(#arg) => #arg,
+                        None => #null_argument,
+                    };
+                }
+            },
+            Marshal::OptionRef => quote! {
+                let #arg = unsafe { #arg.as_ref() };
+            },
+            Marshal::OptionMut => quote! {
+                let #arg = unsafe { #arg.as_mut() };
+            },
+            // Box<T> is a handle like T, so for refcounted structs it must be the last reference
+            Marshal::Boxed => quote! {
+                if #arg.is_null() #null_argument
+                let #arg = match unsafe { crate::ffi::Handle::from_handle(#arg) } {
+                    Ok(#arg) => Box::new(#arg),
+                    Err(err) => {
+                        err.raise();
+                        return #failure;
+                    },
+                };
+            },
+            Marshal::Array => quote! {
+                if #arg.is_null() #null_argument
+                let #arg = unsafe { *#arg };
+            },
+            Marshal::Owned => quote! {
+                if #arg.is_null() #null_argument
+                let #arg = match unsafe { crate::ffi::Handle::from_handle(#arg) } {
+                    Ok(#arg) => #arg,
+                    Err(err) => {
+                        err.raise();
+                        return #failure;
+                    },
+                };
+            },
+            // Only refcounted structs implement RefCounted, so the error points at the Arc
+            Marshal::Arc => {
+                let handle = format_ident!("{}", a.name, span = a.ty.span());
+                quote! {
+                    if #arg.is_null() #null_argument
+                    let #arg = unsafe { crate::ffi::arc_from_handle(#handle) };
+                }
+            },
+            Marshal::OptionBoxed => quote! {
+                let #arg = match #arg.is_null() {
+                    true => None,
+                    false => match unsafe { crate::ffi::Handle::from_handle(#arg) } {
+                        Ok(#arg) => Some(Box::new(#arg)),
+                        Err(err) => {
+                            err.raise();
+                            return #failure;
+                        },
+                    },
+                };
+            },
+            // Option<T> is only passed as a handle for structs, so any other T fails to implement
+            // Handle, and the error points at the Option
+            Marshal::OptionHandle => {
+                let handle = format_ident!("{}", a.name, span = a.ty.span());
+                quote! {
+                    let #arg = match #arg.is_null() {
+                        true => None,
+                        false => match unsafe { crate::ffi::Handle::from_handle(#handle) } {
+                            Ok(#arg) => Some(#arg),
+                            Err(err) => {
+                                err.raise();
+                                return #failure;
+                            },
+                        },
+                    };
+                }
+            },
+            Marshal::CStr => quote! {
+                if #arg.is_null() #null_argument
+                let #arg = unsafe { std::ffi::CStr::from_ptr(#arg) };
+            },
+            Marshal::Str | Marshal::String => {
+                let value = match a.marshal {
+                    Marshal::String => quote! { #arg.to_owned() },
+                    _ => quote! { #arg },
+                };
+                quote! {
+                    let #arg = match crate::ffi::str_from_c(#arg) {
+                        Ok(Some(#arg)) => #value,
+                        Ok(None) => #null_argument,
+                        Err(err) => {
+                            err.raise();
+                            return #failure;
+                        },
+                    };
+                }
+            },
+            Marshal::Slice | Marshal::SliceMut | Marshal::Bytes => {
+                let len = format_ident!("{}_len", a.name);
+                let (from_c, value) = match a.marshal {
+                    Marshal::SliceMut => (quote! { slice_from_c_mut }, quote! { #arg }),
+                    Marshal::Bytes => (quote! { slice_from_c }, quote! { #arg.to_vec() }),
+                    _ => (quote! { slice_from_c }, quote! { #arg }),
+                };
+                quote! {
+                    let #arg = match crate::ffi::#from_c(#arg, #len) {
+                        Some(#arg) => #value,
+                        None => #null_argument,
+                    };
+                }
+            },
+            Marshal::OutBuffer => {
+                let capacity = format_ident!("{}_capacity", a.name);
+                let written = format_ident!("{}_written", a.name);
+                quote! {
+                    let mut #arg = match crate::ffi::OutBuffer::from_c(#arg, #capacity, #written) {
+                        Some(#arg) => #arg,
+                        None => #null_argument,
+                    };
+                    let #arg = &mut #arg;
+                }
+            },
+            Marshal::Callback | Marshal::OptionCallback => {
+                export_callback_arg(a, &null_argument).unwrap_or_else(|err| err.to_compile_error())
+            },
+            Marshal::OptionStr => quote! {
+                let #arg = match crate::ffi::str_from_c(#arg) {
+                    Ok(#arg) => #arg.map(Into::into),
+                    Err(err) => {
+                        err.raise();
+                        return #failure;
+                    },
+                };
+            },
+        };
+        check.to_tokens(&mut checks);
+    });
+    checks
+}
+
+// Trampolines the closure into the C function pointer, passing along user_data, which is
+// destroyed along with the closure
+fn export_callback_arg(a: &MethodArg, null_argument: &TokenStream) -> Result<TokenStream, syn::Error> {
+    let arg = format_ident!("{}", a.name);
+    let user_data = format_ident!("{}_user_data", a.name);
+    let destroy = format_ident!("{}_destroy", a.name);
+    let ty = &a.ty;
+    let (inputs, output) = types::callback_signature(ty)
+        .ok_or_else(|| syn::Error::new_spanned(ty, "Callbacks must be Box<dyn FnOnce(..)>, Box<dyn FnMut(..)> or Box<dyn Fn(..)>"))?;
+    let params: Vec<Ident> = (0..inputs.len()).map(|idx| format_ident!("arg{}", idx)).collect();
+    // Args are passed to C as their Ffi values, while raw pointers are passed as is
+    let ffi_params = inputs.iter().zip(params.iter()).map(|(input, param)| match input {
+        Type::Ptr(_) => quote! { #param },
+        _ => quote! { crate::ffi::FfiValue::into_ffi(#param) },
+    });
+    let closure = quote! {
+        {
+            let user_data = crate::ffi::UserData::new(#user_data, #destroy);
+            Box::new(move |#(#params: #inputs),*| #output {
+                #arg(#(#ffi_params,)* user_data.get())
+            })
         }
     };
-    //println!("code: \n{}", gen);
-    gen.into()
+    Ok(match a.marshal {
+        Marshal::Callback => quote! {
+            let #arg: #ty = match #arg {
+                Some(#arg) => #closure,
+                None => #null_argument,
+            };
+        },
+        _ => quote! {
+            let #arg: #ty = match #arg {
+                Some(#arg) => Some(#closure),
+                None => None,
+            };
+        },
+    })
 }
 
-fn export_self_method(method: &Method) -> TokenStream {
-    let fn_name = &method.method.sig.ident;
-    if fn_name == "drop" {
-        return export_drop_method(method);
-    }
-    let exported_fn = format_ident!("{}_{}", method.target.id, method.method.sig.ident);
-    let args = export_rust_args(method);
-    let arg_names = export_rust_arg_refs(method);
-    let return_ty = export_return_type(&method.method);
-    let return_kw = match return_ty.is_empty() {
-        true => return_ty.clone(),
-        false => quote! { return },
+// Converts the result of calling the Rust method into the value returned to C
+fn export_return(method: &Method, call: TokenStream) -> TokenStream {
+    let failure = export_failure_value(method);
+    let into_handle = |value: TokenStream| match (method.is_constructor, method.returns) {
+        (true, _) => quote! { crate::ffi::Handle::into_handle(#value) },
+        (_, Marshal::Str) | (_, Marshal::String) | (_, Marshal::OptionStr) => quote! {
+            match crate::ffi::IntoCString::into_c_string(#value) {
+                Ok(value) => value,
+                Err(err) => {
+                    err.raise();
+                    return #failure;
+                },
+            }
+        },
+        (_, Marshal::CStr) => quote! { #value.as_ptr() },
+        (_, Marshal::FfiValue) => quote! { crate::ffi::FfiValue::into_ffi(#value) },
+        // &T is returned as a const T*, and &mut T as a T*
+        (_, Marshal::Ref) => quote! { #value as *const _ as _ },
+        (_, Marshal::OptionRef) => quote! { #value.map_or(std::ptr::null(), |value| value as *const _) },
+        (_, Marshal::OptionMut) => quote! { #value.map_or(std::ptr::null_mut(), |value| value as *mut _) },
+        (_, Marshal::Boxed) => quote! { crate::ffi::Handle::into_handle(*#value) },
+        (_, Marshal::Arc) => {
+            let span = types::returned_type(&method.method).map_or_else(Span::call_site, |ty| ty.span());
+            quote_spanned! {span=> crate::ffi::arc_into_handle(#value) }
+        },
+        (_, Marshal::OptionBoxed) => quote! { #value.map_or(std::ptr::null_mut(), |value| crate::ffi::Handle::into_handle(*value)) },
+        (_, Marshal::OptionHandle) => {
+            let span = types::returned_type(&method.method).map_or_else(Span::call_site, |ty| ty.span());
+            let into_handle = quote_spanned! {span=> crate::ffi::Handle::into_handle };
+            quote! { #value.map_or(std::ptr::null_mut(), #into_handle) }
+        },
+        (_, Marshal::Slice) => quote! { crate::CByteCursor::from(#value) },
+        (_, Marshal::Bytes) => quote! { crate::CByteBuf::from(#value) },
+        _ => value,
     };
-    let gen = quote! {
-        #[allow(non_snake_case)]
+    if method.is_fallible {
+        let (ok_pattern, write_out) = match method.out {
+            Some(_) => {
+                let value = into_handle(quote! { value });
+                (quote! { Ok(value) }, quote! {
+                    if !out.is_null() {
+                        unsafe { out.write(#value) };
+                    }
+                })
+            },
+            None => (quote! { Ok(()) }, TokenStream::new()),
+        };
+        return quote! {
+            match #call {
+                #ok_pattern => {
+                    #write_out
+                    crate::error::AWS_OP_SUCCESS
+                },
+                Err(err) => crate::error::Error::from(err).raise(),
+            }
+        };
+    }
+    let value = into_handle(call);
+    match export_return_type(method).is_empty() {
+        true => quote! { #value; },
+        false => quote! { return #value; },
+    }
+}
+
+// Symbols exported for a struct by different items, e.g. a method named clone and #[derive(Clone)],
+// can't be checked by any one #[crt_export]. Declaring each as an associated const of the struct,
+// spanned to what it's exported for, makes rustc report both definitions of a duplicate.
+fn export_symbol_marker(target: &Ident, symbol: &str, span: Span) -> TokenStream {
+    let marker = Ident::new(symbol, span);
+    quote_spanned! {span=>
+        #[allow(non_upper_case_globals)]
         #[allow(dead_code)]
-        #[no_mangle]
-        pub extern "C" fn #exported_fn(#args) #return_ty {
-            let this = unsafe { this.as_ref().expect("NULL self provided") };
-            #return_kw this.#fn_name(#arg_names);
+        #[allow(deprecated)]
+        impl #target {
+            #[doc(hidden)]
+            const #marker: () = ();
         }
-    };
-    //println!("code: \n{}", gen);
-    gen.into()
+    }
 }
 
-fn export_drop_method(method: &Method) -> TokenStream {
-    let exported_fn = format_ident!("{}_{}", method.target.id, method.method.sig.ident);
+// Wraps the body of an exported function so that panics never unwind into C
+fn export_shim(method: &Method, body: TokenStream) -> TokenStream {
+    let exported_name = method.exported_name();
+    // A free function exported under its own name would collide with the shim, so the shim is
+    // given another name and exported by #[export_name] instead
+    let (exported_fn, export_attr) = match method.is_free && method.method.sig.ident == exported_name {
+        true => (format_ident!("__crt_export_{}", exported_name), quote! { #[export_name = #exported_name] }),
+        false => (format_ident!("{}", exported_name), quote! { #[no_mangle] }),
+    };
     let args = export_rust_args(method);
-    let gen = quote! {
+    let return_ty = export_return_type(method);
+    let checks = export_arg_checks(method);
+    let failure = export_failure_value(method);
+    let marker = match method.is_free {
+        true => TokenStream::new(),
+        false => export_symbol_marker(&method.target.id, &exported_name, method.method.sig.ident.span()),
+    };
+    quote! {
+        #marker
+
         #[allow(non_snake_case)]
         #[allow(dead_code)]
-        #[no_mangle]
-        pub extern "C" fn #exported_fn(#args) {
-            let this = unsafe { this.as_ref().expect("NULL self provided") };
-            std::mem::drop(this);
+        #[allow(deprecated)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #export_attr
+        pub extern "C" fn #exported_fn(#args) #return_ty {
+            crate::error::catch_panic(move || #return_ty {
+                #checks
+                #body
+            }).unwrap_or_else(|| #failure)
         }
+    }
+}
+
+fn export_static_method(method: &Method) -> TokenStream {
+    let fn_name = &method.method.sig.ident;
+    let arg_names = export_rust_arg_refs(method);
+    let target = &method.target.id;
+    let call = match method.is_free {
+        true => quote! { #fn_name(#arg_names) },
+        false => quote! { #target::#fn_name(#arg_names) },
     };
+    let body = export_return(method, call);
+    let gen = export_shim(method, body);
     //println!("code: \n{}", gen);
-    gen.into()
+    gen
 }
 
+fn export_self_method(method: &Method) -> TokenStream {
+    let fn_name = &method.method.sig.ident;
+    let arg_names = export_rust_arg_refs(method);
+    let body = export_return(method, quote! { this.#fn_name(#arg_names) });
+    let gen = export_shim(method, body);
+    //println!("code: \n{}", gen);
+    gen
+}
@@ -0,0 +1,7 @@
+// Each of these fails to export, and the expected errors are in the .stderr next to it, which
+// can be regenerated with TRYBUILD=overwrite
+#[test]
+fn diagnostics() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,31 @@
+// The crate's ffi and error modules, which the code generated by #[crt_export] refers to as
+// crate::ffi and crate::error, along with the crate root items they use. Included by the ui tests
+// which get as far as generating code.
+
+#[path = "../../../src/error.rs"]
+mod error;
+#[path = "../../../src/ffi.rs"]
+mod ffi;
+
+extern "C" {
+    fn aws_crt_last_error() -> i32;
+}
+
+#[repr(C)]
+pub struct CByteCursor {
+    pub len: usize,
+    pub ptr: *const u8,
+}
+
+#[repr(C)]
+pub struct CByteBuf {
+    pub len: usize,
+    pub ptr: *mut u8,
+    pub capacity: usize,
+}
+
+impl CByteBuf {
+    pub fn into_vec(self) -> Vec<u8> {
+        unsafe { Vec::from_raw_parts(self.ptr, self.len, self.capacity) }
+    }
+}
@@ -0,0 +1,10 @@
+use crt_macros::crt_export;
+
+pub struct Connection;
+
+#[crt_export]
+impl Connection {
+    pub async fn connect(&self) {}
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+include!("../runtime/mod.rs");
+
+use crt_macros::crt_export;
+
+#[crt_export(builder)]
+#[derive(Default)]
+pub struct Options {
+    threads: u16,
+}
+
+#[crt_export]
+impl Options {
+    pub fn new() -> Options {
+        Options::default()
+    }
+}
+
+fn main() {}
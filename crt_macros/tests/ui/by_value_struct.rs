@@ -0,0 +1,15 @@
+include!("../runtime/mod.rs");
+
+use crt_macros::crt_export;
+
+#[crt_export]
+pub struct Label {
+    text: String,
+}
+
+#[crt_export]
+pub fn show(label: Label) -> usize {
+    label.text.len()
+}
+
+fn main() {}
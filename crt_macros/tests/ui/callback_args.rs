@@ -0,0 +1,8 @@
+use crt_macros::crt_export;
+
+#[crt_export]
+pub fn greet(on_greeted: Box<dyn FnOnce(&str) + Send>) {
+    on_greeted("hello")
+}
+
+fn main() {}
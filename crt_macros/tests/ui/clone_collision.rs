@@ -0,0 +1,18 @@
+include!("../runtime/mod.rs");
+
+use crt_macros::crt_export;
+
+#[crt_export]
+#[derive(Clone)]
+pub struct Point {
+    x: i32,
+}
+
+#[crt_export]
+impl Point {
+    pub fn clone(&self) -> i32 {
+        self.x
+    }
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use crt_macros::crt_export;
+
+const BASE: u8 = 4;
+
+#[crt_export]
+#[repr(u8)]
+pub enum Shape {
+    Circle = BASE,
+    Square,
+}
+
+fn main() {}
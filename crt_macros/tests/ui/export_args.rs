@@ -0,0 +1,8 @@
+use crt_macros::crt_export;
+
+#[crt_export(refcounted)]
+pub fn version() -> u32 {
+    1
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use crt_macros::crt_export;
+
+#[crt_export]
+pub struct Buffer<T> {
+    items: Vec<T>,
+}
+
+fn main() {}
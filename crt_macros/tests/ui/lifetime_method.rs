@@ -0,0 +1,14 @@
+use crt_macros::crt_export;
+
+pub struct Buffer {
+    bytes: Vec<u8>,
+}
+
+#[crt_export]
+impl Buffer {
+    pub fn first<'a>(&'a self, other: &'a Buffer) -> u8 {
+        self.bytes.first().or(other.bytes.first()).copied().unwrap_or_default()
+    }
+}
+
+fn main() {}
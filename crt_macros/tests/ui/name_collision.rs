@@ -0,0 +1,15 @@
+use crt_macros::crt_export;
+
+pub struct Connection;
+
+#[crt_export]
+impl Connection {
+    pub fn open(&self) {}
+
+    #[crt_export(name = "Connection_open")]
+    pub fn reopen(&self) {}
+
+    pub fn release(&self) {}
+}
+
+fn main() {}
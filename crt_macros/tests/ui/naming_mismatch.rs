@@ -0,0 +1,17 @@
+include!("../runtime/mod.rs");
+
+use crt_macros::crt_export;
+
+#[crt_export(prefix = "geo_")]
+pub struct Point {
+    x: i32,
+}
+
+#[crt_export]
+impl Point {
+    fn x(&self) -> i32 {
+        self.x
+    }
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use crt_macros::crt_export;
+use std::rc::Rc;
+
+pub struct Connection;
+
+#[crt_export]
+impl Connection {
+    pub fn close(self: Rc<Self>) {}
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use crt_macros::crt_export;
+
+pub struct Connection;
+
+#[crt_export]
+impl Iterator for Connection {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use crt_macros::crt_export;
+
+pub struct Error;
+
+#[crt_export]
+pub fn bounds() -> Result<(u8, u8), Error> {
+    Ok((0, 1))
+}
+
+fn main() {}
@@ -1,33 +1,69 @@
 use std::ffi::c_void;
 use std::os::raw::c_char;
 use crate::CByteCursor;
+use crate::error::Error;
+use crate::ffi::to_c_string;
 
-#[crt_export]
+/// Options for creating AwsCredentials
+#[crt_export(builder)]
+#[derive(Default)]
 pub struct AwsCredentialsOptions {
-    access_key_id : *const c_char,
-    secret_access_key: *const c_char,
-    session_token: *const c_char,
+    access_key_id : String,
+    secret_access_key: String,
+    /// Only set for temporary credentials
+    session_token: Option<String>,
+    /// When the credentials expire, in seconds since the Unix epoch
     expiration_timepoint_seconds: u64,
 }
 
-#[crt_export]
+/// AWS credentials, which are immutable and shared by everything which signs with them
+#[crt_export(refcounted)]
 pub struct AwsCredentials {
     aws_credentials: *const c_void,
 }
 
 #[crt_export]
 impl AwsCredentials {
-    fn new(options: &AwsCredentialsOptions) -> AwsCredentials {
-        AwsCredentials {
-            aws_credentials: unsafe {
-                aws_crt_credentials_new(
-                    options.access_key_id,
-                    options.secret_access_key,
-                    options.session_token,
-                    options.expiration_timepoint_seconds
-                )
-            }
+    /// Creates credentials from the options, which may be destroyed afterwards
+    fn new(options: &AwsCredentialsOptions) -> Result<AwsCredentials, Error> {
+        let access_key_id = to_c_string(&options.access_key_id)?;
+        let secret_access_key = to_c_string(&options.secret_access_key)?;
+        let session_token = options.session_token.as_deref().map(to_c_string).transpose()?;
+        let aws_credentials = unsafe {
+            aws_crt_credentials_new(
+                access_key_id.as_ptr(),
+                secret_access_key.as_ptr(),
+                session_token.as_ref().map_or(std::ptr::null(), |token| token.as_ptr()),
+                options.expiration_timepoint_seconds
+            )
+        };
+        if aws_credentials.is_null() {
+            return Err(Error::last_error());
         }
+        Ok(AwsCredentials {
+            aws_credentials,
+        })
+    }
+
+    /// Creates credentials without building AwsCredentialsOptions
+    ///
+    /// # Arguments
+    ///
+    /// * `session_token` - Only set for temporary credentials, otherwise NULL
+    /// * `expiration_timepoint_seconds` - When the credentials expire, in seconds since the Unix
+    ///   epoch
+    fn new_from_keys(
+        access_key_id: &str,
+        secret_access_key: &str,
+        session_token: Option<&str>,
+        expiration_timepoint_seconds: u64,
+    ) -> Result<AwsCredentials, Error> {
+        AwsCredentials::new(&AwsCredentialsOptions {
+            access_key_id: access_key_id.to_owned(),
+            secret_access_key: secret_access_key.to_owned(),
+            session_token: session_token.map(str::to_owned),
+            expiration_timepoint_seconds,
+        })
     }
 
     fn get_access_key_id(&self) -> CByteCursor {
@@ -82,21 +118,81 @@ extern "C" {
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    fn static_str(s: &str) -> &'static str {
-        Box::leak(s.to_string().into_boxed_str())
-    }
+    use crate::error::{AWS_ERROR_INVALID_ARGUMENT, AWS_ERROR_MALFORMED_INPUT_STRING, AWS_OP_ERR, AWS_OP_SUCCESS};
+    use crate::ffi::crtrs_string_free;
+    use std::ffi::CStr;
 
     #[test]
     fn aws_credentials_lifetime() {
-        let access_key_id: &'static CStr = unsafe {CStr::from_ptr(static_str("ACCESS_KEY").as_ptr() as *const i8)};
-        let secret_access_key: &'static CStr = unsafe {CStr::from_ptr(static_str("SECRET_ACCESS_KEY").as_ptr() as *const i8)};
-        let session_token: &'static CStr = unsafe {CStr::from_ptr(static_str("SESSION_TOKEN").as_ptr() as *const i8)};
         let _creds = AwsCredentials::new(&AwsCredentialsOptions {
-            access_key_id: access_key_id.as_ptr(),
-            secret_access_key: secret_access_key.as_ptr(),
-            session_token: session_token.as_ptr(),
+            access_key_id: String::from("ACCESS_KEY"),
+            secret_access_key: String::from("SECRET_ACCESS_KEY"),
+            session_token: Some(String::from("SESSION_TOKEN")),
             expiration_timepoint_seconds: 0,
-        });
+        }).unwrap();
+    }
+
+    #[test]
+    fn aws_credentials_handle_lifetime() {
+        let options = AwsCredentialsOptions_new();
+        AwsCredentialsOptions_set_access_key_id(options, "ACCESS_KEY\0".as_ptr() as *const c_char);
+        AwsCredentialsOptions_set_secret_access_key(options, "SECRET_ACCESS_KEY\0".as_ptr() as *const c_char);
+        AwsCredentialsOptions_set_session_token(options, "SESSION_TOKEN\0".as_ptr() as *const c_char);
+        let mut creds: *mut AwsCredentials = std::ptr::null_mut();
+        assert_eq!(AWS_OP_SUCCESS, AwsCredentials_new(options, &mut creds));
+        AwsCredentialsOptions_destroy(options);
+        assert!(!creds.is_null());
+        let access_key_id = AwsCredentials_get_access_key_id(creds);
+        assert_eq!(10, access_key_id.len);
+        AwsCredentials_release(creds);
+    }
+
+    #[test]
+    fn aws_credentials_options_builder() {
+        let options = AwsCredentialsOptions_new();
+        assert!(AwsCredentialsOptions_get_session_token(options).is_null());
+        AwsCredentialsOptions_set_session_token(options, "SESSION_TOKEN\0".as_ptr() as *const c_char);
+        let session_token = AwsCredentialsOptions_get_session_token(options);
+        assert_eq!("SESSION_TOKEN", unsafe { CStr::from_ptr(session_token) }.to_str().unwrap());
+        crtrs_string_free(session_token);
+        AwsCredentialsOptions_set_session_token(options, std::ptr::null());
+        assert!(AwsCredentialsOptions_get_session_token(options).is_null());
+
+        AwsCredentialsOptions_set_expiration_timepoint_seconds(options, 42);
+        assert_eq!(42, AwsCredentialsOptions_get_expiration_timepoint_seconds(options));
+
+        // Required strings reject NULL, leaving the field unchanged
+        AwsCredentialsOptions_set_access_key_id(options, std::ptr::null());
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+        AwsCredentialsOptions_destroy(options);
+    }
+
+    #[test]
+    fn aws_credentials_from_keys() {
+        let mut creds: *mut AwsCredentials = std::ptr::null_mut();
+        assert_eq!(AWS_OP_SUCCESS, AwsCredentials_new_from_keys(
+            "ACCESS_KEY\0".as_ptr() as *const c_char,
+            "SECRET_ACCESS_KEY\0".as_ptr() as *const c_char,
+            std::ptr::null(),
+            0,
+            &mut creds));
+        let secret_access_key = AwsCredentials_get_secret_access_key(creds);
+        assert_eq!(17, secret_access_key.len);
+        AwsCredentials_release(creds);
+
+        assert_eq!(AWS_OP_ERR, AwsCredentials_new_from_keys(
+            b"\xff\0".as_ptr() as *const c_char,
+            "SECRET_ACCESS_KEY\0".as_ptr() as *const c_char,
+            std::ptr::null(),
+            0,
+            &mut creds));
+        assert_eq!(Error::new(AWS_ERROR_MALFORMED_INPUT_STRING), Error::last_error());
+    }
+
+    #[test]
+    fn aws_credentials_null_handle() {
+        let access_key_id = AwsCredentials_get_access_key_id(std::ptr::null_mut());
+        assert!(access_key_id.ptr.is_null());
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
     }
 }
@@ -0,0 +1,125 @@
+use crate::aws_crt_last_error;
+
+pub const AWS_OP_SUCCESS: i32 = 0;
+pub const AWS_OP_ERR: i32 = -1;
+
+pub const AWS_ERROR_UNKNOWN: i32 = 3;
+pub const AWS_ERROR_SHORT_BUFFER: i32 = 4;
+pub const AWS_ERROR_INVALID_ARGUMENT: i32 = 34;
+pub const AWS_ERROR_MALFORMED_INPUT_STRING: i32 = 36;
+
+/// A CRT error code. Exported methods returning Result<T, E> require Error: From<E>, and
+/// report failures to C by raising the code, so that CRT_last_error() will return it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error {
+    pub code: i32,
+}
+
+impl Error {
+    pub fn new(code: i32) -> Error {
+        Error {
+            code,
+        }
+    }
+
+    /// The last error raised on this thread, for wrapping CRT calls which signal failure
+    /// by returning NULL or AWS_OP_ERR
+    pub fn last_error() -> Error {
+        Error::new(unsafe { aws_crt_last_error() })
+    }
+
+    /// Records this error as the thread's last error, and returns AWS_OP_ERR
+    pub fn raise(self) -> i32 {
+        unsafe {
+            aws_raise_error_private(self.code);
+        }
+        AWS_OP_ERR
+    }
+}
+
+impl From<i32> for Error {
+    fn from(code: i32) -> Error {
+        Error::new(code)
+    }
+}
+
+/// The value an exported function returns to C when it fails without a status code to report,
+/// either because it was given a NULL argument or because it panicked
+pub trait FailureValue {
+    fn failure_value() -> Self;
+}
+
+impl FailureValue for () {
+    fn failure_value() {}
+}
+
+impl FailureValue for bool {
+    fn failure_value() -> bool {
+        false
+    }
+}
+
+impl<T> FailureValue for *const T {
+    fn failure_value() -> *const T {
+        std::ptr::null()
+    }
+}
+
+impl<T> FailureValue for *mut T {
+    fn failure_value() -> *mut T {
+        std::ptr::null_mut()
+    }
+}
+
+macro_rules! impl_zero_failure_value {
+    ($($ty:ty),*) => {
+        $(impl FailureValue for $ty {
+            fn failure_value() -> $ty {
+                0 as $ty
+            }
+        })*
+    }
+}
+
+impl_zero_failure_value!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+/// Runs the body of an exported function, catching any panic so that it cannot unwind into C.
+/// A panic is raised as AWS_ERROR_UNKNOWN, and None is returned. The panic hook has already
+/// reported it.
+pub fn catch_panic<R, F: FnOnce() -> R>(f: F) -> Option<R> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(result) => Some(result),
+        Err(_) => {
+            Error::new(AWS_ERROR_UNKNOWN).raise();
+            None
+        }
+    }
+}
+
+/// Raises AWS_ERROR_INVALID_ARGUMENT for a NULL pointer passed to an exported function
+pub fn null_argument() -> i32 {
+    Error::new(AWS_ERROR_INVALID_ARGUMENT).raise()
+}
+
+#[allow(dead_code)]
+extern "C" {
+    fn aws_raise_error_private(error_code: i32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raise_sets_last_error() {
+        assert_eq!(AWS_OP_ERR, Error::new(AWS_ERROR_INVALID_ARGUMENT).raise());
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+
+    #[test]
+    fn panic_is_caught_and_raised() {
+        let result: Option<i32> = catch_panic(|| panic!("boom"));
+        assert_eq!(None, result);
+        assert_eq!(Error::new(AWS_ERROR_UNKNOWN), Error::last_error());
+    }
+}
@@ -0,0 +1,847 @@
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::sync::Arc;
+use crate::CByteBuf;
+use crate::error::{Error, AWS_ERROR_INVALID_ARGUMENT, AWS_ERROR_MALFORMED_INPUT_STRING, AWS_ERROR_SHORT_BUFFER};
+
+/// Values passed to and from C by value, as their Ffi type. Primitives and #[crt_export] structs
+/// with only scalar fields are passed as themselves, while #[crt_export] enums are passed as their
+/// integer repr, and validated on the way in.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be passed by value, pass it by reference or as a handle",
+    label = "unsupported FFI type",
+    note = "Only #[crt_export] structs whose fields are all primitives or raw pointers are passed by value",
+)]
+pub trait FfiValue: Sized {
+    type Ffi;
+
+    fn from_ffi(value: Self::Ffi) -> Result<Self, Error>;
+    fn into_ffi(self) -> Self::Ffi;
+}
+
+macro_rules! impl_identity_ffi_value {
+    ($($ty:ty),*) => {
+        $(impl FfiValue for $ty {
+            type Ffi = $ty;
+
+            fn from_ffi(value: $ty) -> Result<$ty, Error> {
+                Ok(value)
+            }
+
+            fn into_ffi(self) -> $ty {
+                self
+            }
+        })*
+    }
+}
+
+impl_identity_ffi_value!(bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64, crate::CByteCursor, CByteBuf);
+
+/// Handles to #[crt_export] structs, which are a Box owned by the caller, or a reference to an
+/// Arc for #[crt_export(refcounted)] structs
+#[allow(dead_code)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a #[crt_export] struct, so can't be passed as a handle",
+    label = "unsupported FFI type",
+)]
+pub trait Handle: Sized {
+    fn into_handle(self) -> *mut Self;
+    /// Takes ownership of the struct, invalidating the handle. Fails if the struct is refcounted
+    /// and other references remain, in which case the handle is still valid.
+    ///
+    /// # Safety
+    /// The handle must have come from into_handle, and not been released
+    unsafe fn from_handle(handle: *mut Self) -> Result<Self, Error>;
+}
+
+/// Handles to #[crt_export(refcounted)] structs, which are the only handles that can be passed as
+/// an Arc<T>, as the others aren't released as one
+///
+/// # Safety
+/// into_handle must return a pointer from Arc::into_raw
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a #[crt_export(refcounted)] struct, so can't be passed as an Arc",
+    label = "unsupported FFI type",
+)]
+pub unsafe trait RefCounted: Handle {}
+
+/// An Arc for an Arc<T> arg, which borrows the caller's reference
+///
+/// # Safety
+/// The handle must have come from into_handle, and not been released
+#[allow(dead_code)]
+pub unsafe fn arc_from_handle<T: RefCounted>(handle: *const T) -> Arc<T> {
+    Arc::increment_strong_count(handle);
+    Arc::from_raw(handle)
+}
+
+/// Returns an Arc<T> as a new reference, which the caller releases with {Struct}_release
+#[allow(dead_code)]
+pub fn arc_into_handle<T: RefCounted>(value: Arc<T>) -> *const T {
+    Arc::into_raw(value)
+}
+
+/// Borrows a NUL-terminated string passed from C, which must be valid UTF-8. NULL yields None.
+pub fn str_from_c<'a>(s: *const c_char) -> Result<Option<&'a str>, Error> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    match unsafe { CStr::from_ptr(s) }.to_str() {
+        Ok(s) => Ok(Some(s)),
+        Err(_) => Err(Error::new(AWS_ERROR_MALFORMED_INPUT_STRING)),
+    }
+}
+
+/// Copies a string for passing to the CRT
+pub fn to_c_string(s: &str) -> Result<CString, Error> {
+    match CString::new(s) {
+        Ok(s) => Ok(s),
+        // Strings with interior NULs cannot be represented in C
+        Err(_) => Err(Error::new(AWS_ERROR_INVALID_ARGUMENT)),
+    }
+}
+
+/// Strings returned to C are copied into a NUL-terminated char* which the caller owns, and
+/// must release with crtrs_string_free
+#[allow(dead_code)]
+pub trait IntoCString {
+    fn into_c_string(self) -> Result<*mut c_char, Error>;
+}
+
+impl IntoCString for String {
+    fn into_c_string(self) -> Result<*mut c_char, Error> {
+        to_c_string(&self).map(CString::into_raw)
+    }
+}
+
+impl IntoCString for &str {
+    fn into_c_string(self) -> Result<*mut c_char, Error> {
+        self.to_owned().into_c_string()
+    }
+}
+
+impl<T: IntoCString> IntoCString for Option<T> {
+    fn into_c_string(self) -> Result<*mut c_char, Error> {
+        match self {
+            Some(s) => s.into_c_string(),
+            None => Ok(std::ptr::null_mut()),
+        }
+    }
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn crtrs_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        std::mem::drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Borrows (data, len) passed from C. data may only be NULL if len is 0.
+pub fn slice_from_c<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (true, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+pub fn slice_from_c_mut<'a>(data: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    match (data.is_null(), len) {
+        (true, 0) => Some(&mut []),
+        (true, _) => None,
+        (false, _) => Some(unsafe { std::slice::from_raw_parts_mut(data, len) }),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn crtrs_byte_buf_free(buf: CByteBuf) {
+    std::mem::drop(buf.into_vec());
+}
+
+/// Compares an impl's exported struct name with the struct's own at compile time, where == is not
+/// available for strings
+pub const fn same_exported_name(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut idx = 0;
+    while idx < a.len() {
+        if a[idx] != b[idx] {
+            return false;
+        }
+        idx += 1;
+    }
+    true
+}
+
+/// A buffer provided by the caller for an exported method to write into. As bytes are written,
+/// the caller's written length is updated.
+pub struct OutBuffer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    written: Option<&'a mut usize>,
+}
+
+#[allow(dead_code)]
+impl<'a> OutBuffer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> OutBuffer<'a> {
+        OutBuffer {
+            buf,
+            len: 0,
+            written: None,
+        }
+    }
+
+    /// The written length may be NULL if the caller does not need it
+    pub fn from_c(data: *mut u8, capacity: usize, written: *mut usize) -> Option<OutBuffer<'a>> {
+        let mut out = OutBuffer::new(slice_from_c_mut(data, capacity)?);
+        out.written = unsafe { written.as_mut() };
+        out.update_written();
+        Some(out)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Appends bytes to the buffer, failing with AWS_ERROR_SHORT_BUFFER if they do not all fit
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.capacity() - self.len {
+            return Err(Error::new(AWS_ERROR_SHORT_BUFFER));
+        }
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        self.update_written();
+        Ok(())
+    }
+
+    fn update_written(&mut self) {
+        if let Some(written) = self.written.as_mut() {
+            **written = self.len;
+        }
+    }
+}
+
+/// The user_data passed from C along with a callback, which is handed back to the callback
+/// untouched, and to destroy, if given, once the callback is dropped. As with any CRT callback,
+/// the caller must ensure it is safe to use from whichever thread the callback is invoked on.
+#[allow(dead_code)]
+pub struct UserData {
+    user_data: *mut c_void,
+    destroy: Option<extern "C" fn(*mut c_void)>,
+}
+
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+#[allow(dead_code)]
+impl UserData {
+    pub fn new(user_data: *mut c_void, destroy: Option<extern "C" fn(*mut c_void)>) -> UserData {
+        UserData { user_data, destroy }
+    }
+
+    pub fn get(&self) -> *mut c_void {
+        self.user_data
+    }
+}
+
+impl Drop for UserData {
+    fn drop(&mut self) {
+        if let Some(destroy) = self.destroy {
+            destroy(self.user_data);
+        }
+    }
+}
+
+/// A closure passed to the CRT as the user_data of an asynchronous operation, along with
+/// trampoline as its completion callback. The CRT invokes completion callbacks exactly once,
+/// including when the operation is cancelled or shut down, and the closure is freed when it is
+/// invoked. If the CRT fails to start the operation, the callback will never be invoked, so the
+/// closure must be freed with cancel instead.
+pub struct OnceCallback<T> {
+    callback: Box<dyn FnOnce(T) + Send>,
+}
+
+#[allow(dead_code)]
+impl<T> OnceCallback<T> {
+    pub fn into_user_data(callback: Box<dyn FnOnce(T) + Send>) -> *mut c_void {
+        Box::into_raw(Box::new(OnceCallback { callback })) as *mut c_void
+    }
+
+    pub extern "C" fn trampoline(value: T, user_data: *mut c_void) {
+        let once = unsafe { Box::from_raw(user_data as *mut OnceCallback<T>) };
+        crate::error::catch_panic(move || (once.callback)(value));
+    }
+
+    pub fn cancel(user_data: *mut c_void) {
+        std::mem::drop(unsafe { Box::from_raw(user_data as *mut OnceCallback<T>) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{AWS_OP_ERR, AWS_OP_SUCCESS};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    #[test]
+    fn string_round_trip() {
+        let s = String::from("round trip").into_c_string().unwrap();
+        assert_eq!(Some("round trip"), str_from_c(s).unwrap());
+        crtrs_string_free(s);
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let invalid_utf8 = b"\xff\xfe\0";
+        assert_eq!(Err(Error::new(AWS_ERROR_MALFORMED_INPUT_STRING)), str_from_c(invalid_utf8.as_ptr() as *const c_char));
+        assert_eq!(None, str_from_c(std::ptr::null()).unwrap());
+        assert!(String::from("interior\0nul").into_c_string().is_err());
+    }
+
+    #[crt_export]
+    pub struct ByteEcho {}
+
+    #[crt_export]
+    impl ByteEcho {
+        fn copy(bytes: &[u8]) -> Vec<u8> {
+            bytes.to_vec()
+        }
+
+        fn borrow(bytes: &[u8]) -> &[u8] {
+            bytes
+        }
+
+        fn write(out: &mut OutBuffer, bytes: &[u8]) -> Result<(), Error> {
+            out.write(bytes)
+        }
+    }
+
+    #[crt_export]
+    pub struct MaybeValue {
+        value: u32,
+    }
+
+    #[crt_export]
+    impl MaybeValue {
+        fn new(value: u32) -> Option<MaybeValue> {
+            match value {
+                0 => None,
+                value => Some(MaybeValue { value }),
+            }
+        }
+
+        fn value_or(maybe: Option<&MaybeValue>, default: u32) -> u32 {
+            maybe.map_or(default, |maybe| maybe.value)
+        }
+
+        fn take(maybe: Option<Box<MaybeValue>>) -> u32 {
+            maybe.map_or(0, |maybe| maybe.value)
+        }
+    }
+
+    #[test]
+    fn nullable_handles() {
+        assert!(MaybeValue_new(0).is_null());
+        let maybe = MaybeValue_new(42);
+        assert_eq!(42, MaybeValue_value_or(maybe, 7));
+        assert_eq!(7, MaybeValue_value_or(std::ptr::null_mut(), 7));
+        assert_eq!(42, MaybeValue_take(maybe));
+        assert_eq!(0, MaybeValue_take(std::ptr::null_mut()));
+    }
+
+    #[crt_export]
+    pub struct Completion {
+        callback: Box<dyn FnOnce(i32) + Send>,
+        on_progress: Option<Box<dyn FnMut(u32) + Send>>,
+    }
+
+    #[crt_export]
+    impl Completion {
+        fn new(callback: Box<dyn FnOnce(i32) + Send>, on_progress: Option<Box<dyn FnMut(u32) + Send>>) -> Completion {
+            Completion {
+                callback,
+                on_progress,
+            }
+        }
+
+        #[allow(clippy::boxed_local)]
+        fn complete(completion: Box<Completion>, error_code: i32) {
+            let mut completion = *completion;
+            if let Some(on_progress) = completion.on_progress.as_mut() {
+                on_progress(100);
+            }
+            (completion.callback)(error_code);
+        }
+    }
+
+    extern "C" fn record_value(value: i32, user_data: *mut c_void) {
+        let recorded = unsafe { &*(user_data as *const AtomicI32) };
+        recorded.store(value, Ordering::SeqCst);
+    }
+
+    extern "C" fn record_progress(progress: u32, user_data: *mut c_void) {
+        record_value(progress as i32, user_data);
+    }
+
+    static DESTROYED: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn count_destroyed(_user_data: *mut c_void) {
+        DESTROYED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn callback_from_c() {
+        let error_code = AtomicI32::new(-1);
+        let progress = AtomicI32::new(-1);
+        let completion = Completion_new(
+            Some(record_value), &error_code as *const AtomicI32 as *mut c_void, Some(count_destroyed),
+            Some(record_progress), &progress as *const AtomicI32 as *mut c_void, Some(count_destroyed));
+        Completion_complete(completion, 7);
+        assert_eq!(7, error_code.load(Ordering::SeqCst));
+        assert_eq!(100, progress.load(Ordering::SeqCst));
+        assert_eq!(2, DESTROYED.load(Ordering::SeqCst));
+
+        // user_data is destroyed even if the callback is never called
+        let completion = Completion_new(Some(record_value), std::ptr::null_mut(), Some(count_destroyed), None, std::ptr::null_mut(), None);
+        Completion_destroy(completion);
+        assert_eq!(3, DESTROYED.load(Ordering::SeqCst));
+
+        assert!(Completion_new(None, std::ptr::null_mut(), None, None, std::ptr::null_mut(), None).is_null());
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+
+    #[test]
+    fn callback_into_crt() {
+        let completed = Arc::new(AtomicI32::new(-1));
+        let recorded = completed.clone();
+        let user_data = OnceCallback::into_user_data(Box::new(move |error_code: i32| {
+            recorded.store(error_code, Ordering::SeqCst);
+        }));
+        OnceCallback::<i32>::trampoline(3, user_data);
+        assert_eq!(3, completed.load(Ordering::SeqCst));
+        assert_eq!(1, Arc::strong_count(&completed));
+
+        let recorded = completed.clone();
+        let user_data = OnceCallback::into_user_data(Box::new(move |error_code: i32| {
+            recorded.store(error_code, Ordering::SeqCst);
+        }));
+        OnceCallback::<i32>::cancel(user_data);
+        assert_eq!(3, completed.load(Ordering::SeqCst));
+        assert_eq!(1, Arc::strong_count(&completed));
+    }
+
+    #[crt_export]
+    #[repr(u8)]
+    #[derive(Debug, PartialEq)]
+    pub enum Shape {
+        Circle = 1,
+        Square,
+        Triangle = 8,
+    }
+
+    #[crt_export]
+    pub struct Shapes {}
+
+    #[crt_export]
+    impl Shapes {
+        fn sides(shape: Shape) -> u32 {
+            match shape {
+                Shape::Circle => 0,
+                Shape::Square => 4,
+                Shape::Triangle => 3,
+            }
+        }
+
+        fn with_sides(sides: u32) -> Result<Shape, Error> {
+            match sides {
+                0 => Ok(Shape::Circle),
+                3 => Ok(Shape::Triangle),
+                4 => Ok(Shape::Square),
+                _ => Err(Error::new(AWS_ERROR_INVALID_ARGUMENT)),
+            }
+        }
+
+        fn trace(shape: Shape, on_traced: Box<dyn FnOnce(Shape, crate::CByteCursor) + Send>) {
+            on_traced(shape, crate::CByteCursor::from(&b"traced"[..]));
+        }
+    }
+
+    #[test]
+    fn enum_conversion() {
+        assert_eq!(4, Shapes_sides(2));
+        assert_eq!(3, Shapes_sides(8));
+        let mut shape = 0u8;
+        assert_eq!(AWS_OP_SUCCESS, Shapes_with_sides(0, &mut shape));
+        assert_eq!(Shape::Circle as u8, shape);
+    }
+
+    #[test]
+    fn enum_out_of_range() {
+        assert_eq!(0, Shapes_sides(3));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+        assert_eq!(Err(Error::new(AWS_ERROR_INVALID_ARGUMENT)), Shape::from_ffi(0));
+    }
+
+    extern "C" fn record_trace(shape: u8, name: crate::CByteCursor, user_data: *mut c_void) {
+        let recorded = unsafe { &mut *(user_data as *mut (u8, Vec<u8>)) };
+        *recorded = (shape, name.as_slice().to_vec());
+    }
+
+    #[test]
+    fn callback_with_ffi_values() {
+        let mut traced = (0u8, vec![]);
+        Shapes_trace(8, Some(record_trace), &mut traced as *mut (u8, Vec<u8>) as *mut c_void, None);
+        assert_eq!((Shape::Triangle as u8, b"traced".to_vec()), traced);
+    }
+
+    #[crt_export]
+    #[derive(Clone, Debug, Hash, PartialEq)]
+    pub struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[crt_export]
+    impl Point {
+        fn new(x: i32, y: i32) -> Point {
+            Point { x, y }
+        }
+    }
+
+    /// Replaced by Label
+    #[crt_export]
+    #[deprecated(since = "0.1.0", note = "Use Label")]
+    pub struct Tag {
+        #[deprecated]
+        #[allow(dead_code)]
+        text: String,
+    }
+
+    #[crt_export]
+    #[allow(deprecated)]
+    impl Tag {
+        fn new(text: &str) -> Tag {
+            Tag { text: text.to_owned() }
+        }
+    }
+
+    #[crt_export]
+    #[derive(Debug)]
+    pub struct Label {
+        text: String,
+    }
+
+    #[crt_export]
+    impl Label {
+        fn new(text: &str) -> Label {
+            Label { text: text.to_owned() }
+        }
+    }
+
+    #[crt_export]
+    impl std::fmt::Display for Label {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "<{}>", self.text)
+        }
+    }
+
+    #[test]
+    fn derived_traits() {
+        let point = Point_new(1, 2);
+        let copy = Point_clone(point);
+        assert_ne!(point, copy);
+        assert!(Point_equals(point, copy));
+        assert_eq!(Point_hash(point), Point_hash(copy));
+        let other = Point_new(2, 1);
+        assert!(!Point_equals(point, other));
+        let s = Point_debug_string(point);
+        assert_eq!(Some("Point { x: 1, y: 2 }"), str_from_c(s).unwrap());
+        crtrs_string_free(s);
+        Point_destroy(other);
+        Point_destroy(copy);
+        Point_destroy(point);
+
+        assert!(!Point_equals(std::ptr::null_mut(), std::ptr::null_mut()));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+
+    #[test]
+    fn implemented_traits() {
+        let label = Label_new("label\0".as_ptr() as *const c_char);
+        let s = Label_to_string(label);
+        assert_eq!(Some("<label>"), str_from_c(s).unwrap());
+        crtrs_string_free(s);
+        // Debug and Display are exported side by side
+        let s = Label_debug_string(label);
+        assert_eq!(Some("Label { text: \"label\" }"), str_from_c(s).unwrap());
+        crtrs_string_free(s);
+        Label_destroy(label);
+    }
+
+    #[crt_export(prefix = "crtrs_test_", style = "snake_case")]
+    pub struct HTTPCounter {
+        count: u32,
+    }
+
+    #[crt_export(prefix = "crtrs_test_", style = "snake_case")]
+    impl HTTPCounter {
+        fn new() -> HTTPCounter {
+            HTTPCounter { count: 0 }
+        }
+
+        fn increment(&mut self) -> u32 {
+            self.count = self.next();
+            self.count
+        }
+
+        #[crt_export(name = "crtrs_test_counter_value")]
+        fn count(&self) -> u32 {
+            self.count
+        }
+
+        #[crt_export(skip)]
+        fn next(&self) -> u32 {
+            self.count + 1
+        }
+    }
+
+    #[test]
+    fn export_naming() {
+        let counter = crtrs_test_http_counter_new();
+        assert_eq!(1, crtrs_test_http_counter_increment(counter));
+        assert_eq!(2, crtrs_test_http_counter_increment(counter));
+        assert_eq!(2, crtrs_test_counter_value(counter));
+        crtrs_test_http_counter_destroy(counter);
+    }
+
+    #[crt_export]
+    pub struct RequestBuilder {
+        path: String,
+    }
+
+    #[crt_export]
+    pub struct Request {
+        path: String,
+    }
+
+    #[crt_export]
+    impl RequestBuilder {
+        fn new() -> RequestBuilder {
+            RequestBuilder { path: String::from("/") }
+        }
+
+        fn append(&mut self, segment: &str) {
+            self.path.push_str(segment);
+        }
+
+        fn build(self) -> Result<Box<Request>, Error> {
+            match self.path.len() {
+                1 => Err(Error::new(AWS_ERROR_INVALID_ARGUMENT)),
+                _ => Ok(Box::new(Request { path: self.path })),
+            }
+        }
+
+        #[allow(clippy::boxed_local)]
+        fn into_path(self: Box<Self>) -> String {
+            self.path
+        }
+    }
+
+    #[crt_export(refcounted)]
+    pub struct Route {
+        path: String,
+    }
+
+    #[crt_export]
+    impl Route {
+        fn shared(path: &str) -> Arc<Route> {
+            Arc::new(Route { path: path.to_owned() })
+        }
+
+        fn path_len(self: Arc<Self>) -> usize {
+            self.path.len()
+        }
+    }
+
+    #[test]
+    fn receivers() {
+        let builder = RequestBuilder_new();
+        RequestBuilder_append(builder, "index.html\0".as_ptr() as *const c_char);
+        // build consumes the builder, so it must not be destroyed
+        let mut request: *mut Request = std::ptr::null_mut();
+        assert_eq!(AWS_OP_SUCCESS, RequestBuilder_build(builder, &mut request));
+        assert_eq!("/index.html", unsafe { &(*request).path });
+        Request_destroy(request);
+
+        let builder = RequestBuilder_new();
+        assert_eq!(AWS_OP_ERR, RequestBuilder_build(builder, &mut request));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+
+        let builder = RequestBuilder_new();
+        let path = RequestBuilder_into_path(builder);
+        assert_eq!(Some("/"), str_from_c(path).unwrap());
+        crtrs_string_free(path);
+    }
+
+    #[test]
+    fn arc_receiver() {
+        let route = Route_shared("/index.html\0".as_ptr() as *const c_char);
+        // Calls borrow the caller's reference, leaving it valid after each call
+        assert_eq!(11, Route_path_len(route));
+        assert_eq!(11, Route_path_len(route));
+        assert_eq!(1, unsafe { Arc::strong_count(&std::mem::ManuallyDrop::new(Arc::from_raw(route))) });
+        Route_release(route);
+    }
+
+    #[crt_export(refcounted)]
+    pub struct Connection {
+        id: u32,
+    }
+
+    #[crt_export]
+    impl Connection {
+        fn new(id: u32) -> Connection {
+            Connection { id }
+        }
+
+        fn id(&self) -> u32 {
+            self.id
+        }
+
+        fn share(self: Arc<Self>) -> Arc<Connection> {
+            self
+        }
+
+        fn close(self) -> u32 {
+            self.id
+        }
+
+        fn boxed(id: u32) -> Box<Connection> {
+            Box::new(Connection { id })
+        }
+
+        #[allow(clippy::boxed_local)]
+        fn into_id(self: Box<Self>) -> u32 {
+            self.id
+        }
+    }
+
+    #[test]
+    fn refcounted_handles() {
+        let connection = Connection_new(7);
+        let acquired = Connection_acquire(connection);
+        let shared = Connection_share(connection);
+        assert_eq!(7, Connection_id(shared));
+        assert_eq!(3, unsafe { Arc::strong_count(&std::mem::ManuallyDrop::new(Arc::from_raw(connection))) });
+        Connection_release(acquired);
+        Connection_release(shared);
+
+        // Consuming a shared handle fails, leaving the caller's reference intact
+        let acquired = Connection_acquire(connection);
+        assert_eq!(0, Connection_close(connection));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+        Connection_release(acquired);
+        assert_eq!(7, Connection_close(connection));
+    }
+
+    #[test]
+    fn boxed_refcounted_handles() {
+        // Box<T> is passed as the same handle as T, so is released with Connection_release
+        let connection = Connection_boxed(3);
+        let acquired = Connection_acquire(connection);
+        assert_eq!(2, unsafe { Arc::strong_count(&std::mem::ManuallyDrop::new(Arc::from_raw(connection))) });
+        Connection_release(acquired);
+
+        let acquired = Connection_acquire(connection);
+        assert_eq!(0, Connection_into_id(connection));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+        Connection_release(acquired);
+        assert_eq!(3, Connection_into_id(connection));
+    }
+
+    #[crt_export]
+    pub struct Digest {}
+
+    #[crt_export]
+    impl Digest {
+        fn checksum(digest: [u8; 4], seed: std::os::raw::c_int) -> u32 {
+            digest.iter().fold(seed as u32, |sum, byte| sum + *byte as u32)
+        }
+    }
+
+    #[test]
+    fn arrays_and_qualified_types() {
+        let digest = [1u8, 2, 3, 4];
+        assert_eq!(20, Digest_checksum(&digest, 10));
+        assert_eq!(0, Digest_checksum(std::ptr::null(), 10));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let bytes = b"round trip";
+        let copy = ByteEcho_copy(bytes.as_ptr(), bytes.len());
+        assert_eq!(bytes.to_vec(), copy.into_vec());
+
+        let cursor = ByteEcho_borrow(bytes.as_ptr(), bytes.len());
+        assert_eq!(bytes, cursor.as_slice());
+
+        let empty = ByteEcho_copy(std::ptr::null(), 0);
+        crtrs_byte_buf_free(empty);
+    }
+
+    #[test]
+    fn bytes_into_out_buffer() {
+        let bytes = b"out buffer";
+        let mut out = [0u8; 16];
+        let mut written = 0usize;
+        assert_eq!(AWS_OP_SUCCESS, ByteEcho_write(out.as_mut_ptr(), out.len(), &mut written, bytes.as_ptr(), bytes.len()));
+        assert_eq!(bytes, &out[..written]);
+
+        assert_eq!(AWS_OP_ERR, ByteEcho_write(out.as_mut_ptr(), 4, &mut written, bytes.as_ptr(), bytes.len()));
+        assert_eq!(Error::new(AWS_ERROR_SHORT_BUFFER), Error::last_error());
+        assert_eq!(0, written);
+
+        assert_eq!(AWS_OP_ERR, ByteEcho_write(std::ptr::null_mut(), 4, &mut written, bytes.as_ptr(), bytes.len()));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+
+    #[crt_export(prefix = "crtrs_test_")]
+    pub static MAX_HEADER_SIZE: usize = 8 * 1024;
+
+    #[crt_export]
+    pub fn crtrs_test_checked_add(a: u32, b: u32) -> Result<u32, Error> {
+        a.checked_add(b).ok_or(Error::new(AWS_ERROR_INVALID_ARGUMENT))
+    }
+
+    extern "C" {
+        static crtrs_test_MAX_HEADER_SIZE: usize;
+    }
+
+    #[test]
+    fn free_functions_and_statics() {
+        assert_eq!(MAX_HEADER_SIZE, unsafe { crtrs_test_MAX_HEADER_SIZE });
+
+        let mut sum = 0u32;
+        assert_eq!(AWS_OP_SUCCESS, __crt_export_crtrs_test_checked_add(1, 2, &mut sum));
+        assert_eq!(3, sum);
+        assert_eq!(AWS_OP_ERR, __crt_export_crtrs_test_checked_add(u32::MAX, 1, &mut sum));
+        assert_eq!(Error::new(AWS_ERROR_INVALID_ARGUMENT), Error::last_error());
+    }
+}
@@ -1,7 +1,10 @@
 use std::ffi::c_void;
 
-#[crt_export]
+/// Options for creating an EventLoopGroup
+#[crt_export(builder)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct EventLoopGroupOptions {
+    /// The number of event loop threads, or 0 for one per CPU
     num_threads: u16
 }
 
@@ -14,18 +17,39 @@ impl Default for EventLoopGroupOptions {
     }
 }
 
-#[crt_export]
+/// Event loop threads, which are shared by the clients which use them
+#[crt_export(refcounted)]
 pub struct EventLoopGroup {
     c_elg : *const c_void,
+    on_shutdown: Option<Box<dyn FnOnce() + Send>>,
 }
 
 #[crt_export]
 impl EventLoopGroup {
-    fn new(options: EventLoopGroupOptions) -> EventLoopGroup {
+    /// Starts the event loop threads
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options, or NULL for the defaults
+    fn new(options: Option<&EventLoopGroupOptions>) -> EventLoopGroup {
+        let num_threads = options.map_or(0, |options| options.num_threads);
         EventLoopGroup {
-            c_elg: unsafe {aws_crt_event_loop_group_new(options.num_threads)}
+            c_elg: unsafe {aws_crt_event_loop_group_new(num_threads)},
+            on_shutdown: None,
         }
     }
+
+    /// Starts the event loop threads, and calls on_shutdown once they have been released
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options, or NULL for the defaults
+    /// * `on_shutdown` - Called once the last reference is released, on the releasing thread
+    fn new_with_shutdown_callback(options: Option<&EventLoopGroupOptions>, on_shutdown: Box<dyn FnOnce() + Send>) -> EventLoopGroup {
+        let mut elg = EventLoopGroup::new(options);
+        elg.on_shutdown = Some(on_shutdown);
+        elg
+    }
 }
 
 #[crt_export]
@@ -34,6 +58,9 @@ impl Drop for EventLoopGroup {
         unsafe {
             aws_crt_event_loop_group_release(self.c_elg);
         }
+        if let Some(on_shutdown) = self.on_shutdown.take() {
+            on_shutdown();
+        }
     }
 }
 
@@ -49,6 +76,73 @@ mod tests {
 
     #[test]
     fn event_loop_group_lifetime() {
-        let _elg = EventLoopGroup::new(EventLoopGroupOptions::default());
+        let _elg = EventLoopGroup::new(Some(&EventLoopGroupOptions::default()));
+    }
+
+    #[test]
+    fn event_loop_group_handle_lifetime() {
+        let options = EventLoopGroupOptions_default();
+        let elg = EventLoopGroup_new(options);
+        assert!(!elg.is_null());
+        EventLoopGroup_release(elg);
+        EventLoopGroupOptions_destroy(options);
+    }
+
+    #[test]
+    fn event_loop_group_options_builder() {
+        let options = EventLoopGroupOptions_new();
+        assert_eq!(0, EventLoopGroupOptions_get_num_threads(options));
+        EventLoopGroupOptions_set_num_threads(options, 2);
+        assert_eq!(2, EventLoopGroupOptions_get_num_threads(options));
+        let elg = EventLoopGroup_new(options);
+        assert!(!elg.is_null());
+        EventLoopGroup_release(elg);
+        EventLoopGroupOptions_destroy(options);
+    }
+
+    #[test]
+    fn event_loop_group_options_traits() {
+        let options = EventLoopGroupOptions_new();
+        EventLoopGroupOptions_set_num_threads(options, 2);
+        let copy = EventLoopGroupOptions_clone(options);
+        assert!(EventLoopGroupOptions_equals(options, copy));
+        EventLoopGroupOptions_set_num_threads(copy, 1);
+        assert!(!EventLoopGroupOptions_equals(options, copy));
+        EventLoopGroupOptions_destroy(copy);
+        EventLoopGroupOptions_destroy(options);
+    }
+
+    #[test]
+    fn event_loop_group_shared() {
+        let elg = EventLoopGroup_new(std::ptr::null_mut());
+        let shared = EventLoopGroup_acquire(elg);
+        assert_eq!(elg, shared);
+        EventLoopGroup_release(elg);
+        EventLoopGroup_release(shared);
+    }
+
+    #[test]
+    fn event_loop_group_shutdown_callback() {
+        extern "C" fn on_shutdown(user_data: *mut c_void) {
+            unsafe { *(user_data as *mut u32) += 1 };
+        }
+        extern "C" fn on_destroy(user_data: *mut c_void) {
+            unsafe { *(user_data as *mut u32) += 10 };
+        }
+        let mut calls: u32 = 0;
+        let user_data = &mut calls as *mut u32 as *mut c_void;
+        let elg = EventLoopGroup_new_with_shutdown_callback(std::ptr::null_mut(), Some(on_shutdown), user_data, Some(on_destroy));
+        let shared = EventLoopGroup_acquire(elg);
+        EventLoopGroup_release(elg);
+        assert_eq!(0, calls);
+        EventLoopGroup_release(shared);
+        assert_eq!(11, calls);
+    }
+
+    #[test]
+    fn event_loop_group_default_options() {
+        let elg = EventLoopGroup_new(std::ptr::null_mut());
+        assert!(!elg.is_null());
+        EventLoopGroup_release(elg);
     }
 }
\ No newline at end of file
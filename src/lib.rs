@@ -1,5 +1,7 @@
 #![feature(rustc_private)]
 
+mod error;
+mod ffi;
 mod io;
 mod auth;
 
@@ -20,46 +22,62 @@ extern "C" {
     pub fn aws_crt_last_error() -> i32;
 }
 
-#[crt_export]
-pub struct CRT {}
+/// The version of the library which the header was generated from
+#[crt_export(prefix = "CRTRS_")]
+pub const VERSION_MAJOR: u32 = 0;
+#[crt_export(prefix = "CRTRS_")]
+pub const VERSION_MINOR: u32 = 1;
+#[crt_export(prefix = "CRTRS_")]
+pub const VERSION_PATCH: u32 = 0;
+
+/// The version of the library which is loaded, which may differ from the header's
+#[crt_export(prefix = "crtrs_")]
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
 
-#[allow(dead_code)]
-#[crt_export]
-impl CRT {
-    pub fn init() {
-        unsafe {
-            aws_crt_init();
-        }
+/// Initializes the CRT, which must be done before anything else is used
+#[crt_export(prefix = "CRT_")]
+pub fn init() {
+    unsafe {
+        aws_crt_init();
     }
+}
 
-    pub fn clean_up() {
-        unsafe {
-            aws_crt_clean_up();
-        }
+/// Releases everything allocated by init, after which nothing else may be used
+#[crt_export(prefix = "CRT_")]
+pub fn clean_up() {
+    unsafe {
+        aws_crt_clean_up();
     }
+}
 
-    pub fn error_str(error_code: i32) -> *const c_char {
-        unsafe {
-            aws_crt_error_str(error_code)
-        }
+#[crt_export(prefix = "CRT_")]
+pub fn error_str(error_code: i32) -> *const c_char {
+    unsafe {
+        aws_crt_error_str(error_code)
     }
+}
 
-    pub fn error_name(error_code: i32) -> *const c_char {
-        unsafe {
-            aws_crt_error_name(error_code)
-        }
+#[crt_export(prefix = "CRT_")]
+pub fn error_name(error_code: i32) -> *const c_char {
+    unsafe {
+        aws_crt_error_name(error_code)
     }
+}
 
-    pub fn error_debug_str(error_code: i32) -> *const c_char {
-        unsafe {
-            aws_crt_error_debug_str(error_code)
-        }
+#[crt_export(prefix = "CRT_")]
+pub fn error_debug_str(error_code: i32) -> *const c_char {
+    unsafe {
+        aws_crt_error_debug_str(error_code)
     }
+}
 
-    pub fn last_error() -> i32 {
-        unsafe {
-            aws_crt_last_error()
-        }
+/// The error code of the last failure on this thread
+#[crt_export(prefix = "CRT_")]
+pub fn last_error() -> i32 {
+    unsafe {
+        aws_crt_last_error()
     }
 }
 
@@ -70,6 +88,69 @@ pub struct CByteCursor {
     pub ptr: *const u8,
 }
 
+impl CByteCursor {
+    pub fn as_slice(&self) -> &[u8] {
+        ffi::slice_from_c(self.ptr, self.len).unwrap_or(&[])
+    }
+}
+
+impl From<&[u8]> for CByteCursor {
+    fn from(bytes: &[u8]) -> CByteCursor {
+        CByteCursor {
+            len: bytes.len(),
+            ptr: bytes.as_ptr(),
+        }
+    }
+}
+
+impl error::FailureValue for CByteCursor {
+    fn failure_value() -> CByteCursor {
+        CByteCursor {
+            len: 0,
+            ptr: std::ptr::null(),
+        }
+    }
+}
+
+/// Bytes owned by C, which must be released with crtrs_byte_buf_free
+#[repr(C)]
+#[non_exhaustive]
+pub struct CByteBuf {
+    pub len: usize,
+    pub ptr: *mut u8,
+    pub capacity: usize,
+}
+
+impl CByteBuf {
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.ptr.is_null() {
+            return vec![];
+        }
+        unsafe { Vec::from_raw_parts(self.ptr, self.len, self.capacity) }
+    }
+}
+
+impl From<Vec<u8>> for CByteBuf {
+    fn from(bytes: Vec<u8>) -> CByteBuf {
+        let mut bytes = std::mem::ManuallyDrop::new(bytes);
+        CByteBuf {
+            len: bytes.len(),
+            ptr: bytes.as_mut_ptr(),
+            capacity: bytes.capacity(),
+        }
+    }
+}
+
+impl error::FailureValue for CByteBuf {
+    fn failure_value() -> CByteBuf {
+        CByteBuf {
+            len: 0,
+            ptr: std::ptr::null_mut(),
+            capacity: 0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -83,26 +164,81 @@ mod tests {
 
     #[test]
     fn init_and_clean_up() {
-        CRT::init();
-        CRT::clean_up();
+        init();
+        clean_up();
     }
 
     #[test]
     fn error_lookup() {
-        CRT::init();
-        assert_eq!("Success.", c_str( CRT::error_str(0)).to_string_lossy());
-        assert_eq!("AWS_ERROR_SUCCESS", c_str(CRT::error_name(0)).to_string_lossy());
-        assert_eq!("aws-c-common: AWS_ERROR_SUCCESS, Success.", c_str(CRT::error_debug_str(0)).to_string_lossy());
-        CRT::clean_up();
+        init();
+        assert_eq!("Success.", c_str( error_str(0)).to_string_lossy());
+        assert_eq!("AWS_ERROR_SUCCESS", c_str(error_name(0)).to_string_lossy());
+        assert_eq!("aws-c-common: AWS_ERROR_SUCCESS, Success.", c_str(error_debug_str(0)).to_string_lossy());
+        clean_up();
     }
 
     #[test]
     fn lookup_last_error() {
-        CRT::init();
-        let last_error = CRT::last_error();
-        assert_eq!("Success.", c_str( CRT::error_str(last_error)).to_string_lossy());
-        assert_eq!("AWS_ERROR_SUCCESS", c_str(CRT::error_name(last_error)).to_string_lossy());
-        assert_eq!("aws-c-common: AWS_ERROR_SUCCESS, Success.", c_str(CRT::error_debug_str(last_error)).to_string_lossy());
-        CRT::clean_up();
+        init();
+        let last_error = last_error();
+        assert_eq!("Success.", c_str( error_str(last_error)).to_string_lossy());
+        assert_eq!("AWS_ERROR_SUCCESS", c_str(error_name(last_error)).to_string_lossy());
+        assert_eq!("aws-c-common: AWS_ERROR_SUCCESS, Success.", c_str(error_debug_str(last_error)).to_string_lossy());
+        clean_up();
+    }
+
+    #[test]
+    fn exported_functions() {
+        CRT_init();
+        assert_eq!("AWS_ERROR_SUCCESS", c_str(CRT_error_name(CRT_last_error())).to_string_lossy());
+        let version = crtrs_version();
+        let expected = format!("{}.{}.{}", VERSION_MAJOR, VERSION_MINOR, VERSION_PATCH);
+        assert_eq!(expected, c_str(version).to_string_lossy());
+        ffi::crtrs_string_free(version);
+        CRT_clean_up();
+    }
+
+    // Each module's header must compile on its own, as well as the umbrella header which includes
+    // them all
+    fn compile_headers(compiler: &str, args: &[&str]) {
+        let include_dir = concat!(env!("OUT_DIR"), "/include");
+        let mut headers: Vec<String> = std::fs::read_dir(format!("{}/crtrs", include_dir)).unwrap()
+            .map(|entry| format!("crtrs/{}", entry.unwrap().file_name().to_string_lossy()))
+            .collect();
+        headers.push(String::from("crtrs.h"));
+        for header in headers.iter() {
+            // Included twice to check the include guard
+            let source = format!("{}/header_test_{}.c", env!("OUT_DIR"), args[1]);
+            std::fs::write(&source, format!("#include \"{0}\"\n#include \"{0}\"\n", header)).unwrap();
+            let output = std::process::Command::new(compiler)
+                .args(args)
+                .args(["-Wall", "-Wextra", "-Werror", "-fsyntax-only", "-I", include_dir, &source])
+                .output()
+                .unwrap_or_else(|err| panic!("Failed to run {}: {}", compiler, err));
+            assert!(output.status.success(), "{}: {}", header, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+
+    #[test]
+    fn header_compiles_as_c() {
+        let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+        compile_headers(&cc, &["-x", "c", "-std=c99", "-pedantic", "-Wstrict-prototypes"]);
+    }
+
+    #[test]
+    fn header_compiles_as_cpp() {
+        let cxx = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+        compile_headers(&cxx, &["-x", "c++"]);
+    }
+
+    // abi/crtrs.json is the ABI of the last release, which C callers may have been built against.
+    // Additive changes are fine, and the baseline is updated with crtrs-abi-check --update when
+    // releasing, or when a breaking change is intended.
+    #[test]
+    fn abi_is_compatible() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+        let changes = crt_bindgen::abi::check(&root.join("abi/crtrs.json"), "crtrs", &root.join("src/lib.rs")).unwrap();
+        let breaking: Vec<String> = changes.iter().filter(|c| c.breaking).map(|c| c.to_string()).collect();
+        assert!(breaking.is_empty(), "Breaking changes since abi/crtrs.json:\n{}", breaking.join("\n"));
     }
 }
@@ -0,0 +1,111 @@
+use std::path::Path;
+use std::process::Command;
+
+use crt_bindgen::plugin::Registry;
+use crt_bindgen::Config;
+
+// Built with the headers from the c and cpp generators, and linked against the cdylib which cargo
+// builds next to the integration tests
+const SOURCE: &str = r#"
+#include "crtrs.hpp"
+
+#include <cassert>
+#include <span>
+#include <string>
+#include <type_traits>
+#include <unordered_set>
+
+static_assert(!std::is_copy_constructible_v<crtrs::AwsCredentialsOptions>, "Only refcounted and Clone structs are copied");
+
+int main() {
+    crtrs::init();
+    assert(crtrs::version() == VERSION);
+    assert(std::string(crtrs::error_name(0)) == "AWS_ERROR_SUCCESS");
+
+    {
+        crtrs::EventLoopGroupOptions options;
+        options.set_num_threads(2);
+        assert(options.get_num_threads() == 2);
+        crtrs::EventLoopGroupOptions copy = options;
+        assert(copy == options && copy.get() != options.get());
+        assert(copy.debug_string().find("num_threads: 2") != std::string::npos);
+        crtrs::EventLoopGroupOptions moved = std::move(copy);
+        assert(!copy && moved);
+
+        crtrs::EventLoopGroup elg(&options);
+        crtrs::EventLoopGroup second = elg;
+        assert(second.get() == elg.get());
+        crtrs::EventLoopGroup defaults;
+        assert(defaults);
+    }
+
+    int shutdowns = 0;
+    {
+        auto elg = crtrs::EventLoopGroup::new_with_shutdown_callback(nullptr, [&shutdowns] { ++shutdowns; });
+        crtrs::EventLoopGroup second = elg;
+        elg = crtrs::EventLoopGroup();
+        assert(shutdowns == 0);
+    }
+    assert(shutdowns == 1);
+
+    crtrs::AwsCredentialsOptions options;
+    options.set_access_key_id(std::string_view("ACCESS_KEY"));
+    options.set_secret_access_key("SECRET_ACCESS_KEY");
+    assert(options.get_access_key_id() == "ACCESS_KEY");
+    assert(!options.get_session_token());
+    options.set_session_token("SESSION_TOKEN");
+    assert(options.get_session_token() == "SESSION_TOKEN");
+    crtrs::AwsCredentials credentials(options);
+    assert(credentials.get_session_token().len == 13);
+    credentials = crtrs::AwsCredentials::new_from_keys("ACCESS_KEY", "SECRET_ACCESS_KEY", std::nullopt, 0);
+    assert(credentials.get_secret_access_key().len == 17);
+
+    try {
+        crtrs::AwsCredentials::new_from_keys("\xff", "SECRET_ACCESS_KEY", std::nullopt, 0);
+        assert(!"invalid UTF-8 must be thrown");
+    } catch (const crtrs::Error& err) {
+        assert(err.code() == 36);
+        assert(std::string(err.what()).find(err.name()) == 0);
+    }
+
+    const uint8_t bytes[] = {1, 2, 3};
+    crtrs::ByteView view{std::span<const uint8_t>(bytes)};
+    assert(view.size() == 3 && view.data() == bytes);
+    assert(crtrs::ByteView(std::string("abc")).size() == 3);
+
+    crtrs::clean_up();
+    return 0;
+}
+"#;
+
+#[test]
+fn cpp_wrappers() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output_dir = std::env::temp_dir().join(format!("crtrs_cpp_{}", std::process::id()));
+    let config = Config::new("crtrs", &root.join("src/lib.rs"), &output_dir).generators("c,cpp");
+    crt_bindgen::generate_with(&config, &Registry::default()).unwrap();
+
+    let source = output_dir.join("main.cpp");
+    std::fs::write(&source, SOURCE.replace("VERSION", &format!("{:?}", env!("CARGO_PKG_VERSION")))).unwrap();
+    let exe = output_dir.join("main");
+    let library_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let cxx = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+    let output = Command::new(&cxx)
+        .args(["-std=c++20", "-Wall", "-Wextra", "-Werror", "-I"])
+        .arg(&output_dir)
+        .arg(&source)
+        .arg("-o")
+        .arg(&exe)
+        .arg("-L")
+        .arg(&library_dir)
+        .arg("-lcrtrs")
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .output()
+        .unwrap_or_else(|err| panic!("Failed to run {}: {}", cxx, err));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // cargo's LD_LIBRARY_PATH would otherwise take precedence over the rpath
+    let output = Command::new(&exe).env("LD_LIBRARY_PATH", &library_dir).output().unwrap();
+    std::fs::remove_dir_all(&output_dir).unwrap();
+    assert!(output.status.success(), "{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
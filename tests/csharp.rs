@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::process::Command;
+
+use crt_bindgen::plugin::Registry;
+use crt_bindgen::Config;
+
+const PROJECT: &str = r#"<Project Sdk="Microsoft.NET.Sdk">
+  <PropertyGroup>
+    <OutputType>Exe</OutputType>
+    <TargetFramework>net8.0</TargetFramework>
+    <Nullable>enable</Nullable>
+    <TreatWarningsAsErrors>true</TreatWarningsAsErrors>
+  </PropertyGroup>
+</Project>
+"#;
+
+// Runs with the bindings from the csharp generator, against the cdylib which cargo builds next to
+// the integration tests
+const PROGRAM: &str = r#"
+using System;
+using System.Text;
+using Crtrs;
+
+static void Assert(bool condition, string message)
+{
+    if (!condition)
+    {
+        throw new Exception(message);
+    }
+}
+
+Library.Init();
+Assert(Library.Version() == VERSION, Library.Version());
+Assert(Library.ErrorName(0) == "AWS_ERROR_SUCCESS", "error names are looked up");
+
+using (var options = EventLoopGroupOptions.New())
+{
+    options.SetNumThreads(2);
+    Assert(options.GetNumThreads() == 2, "num_threads is set");
+    using var copy = options.Clone();
+    Assert(copy.Equals(options) && !ReferenceEquals(copy, options), "clones are equal");
+    Assert(copy.ToString().Contains("num_threads: 2"), copy.ToString());
+    using var elg = EventLoopGroup.New(options);
+    using var second = elg.Acquire();
+    using var defaults = EventLoopGroup.New();
+    options.Dispose();
+    try
+    {
+        options.GetNumThreads();
+        Assert(false, "disposed handles must not be used");
+    }
+    catch (ObjectDisposedException)
+    {
+    }
+}
+
+var shutdowns = 0;
+using (var elg = EventLoopGroup.NewWithShutdownCallback(null, () => shutdowns++))
+{
+    elg.Acquire().Dispose();
+    Assert(shutdowns == 0, "shutdown waits for the last reference");
+}
+Assert(shutdowns == 1, "shutdown is called back once");
+
+using var credentialsOptions = AwsCredentialsOptions.New();
+credentialsOptions.SetAccessKeyId("ACCESS_KEY");
+credentialsOptions.SetSecretAccessKey("SECRET_ACCESS_KEY");
+Assert(credentialsOptions.GetAccessKeyId() == "ACCESS_KEY", "access_key_id is set");
+Assert(credentialsOptions.GetSessionToken() == null, "session_token is optional");
+credentialsOptions.SetSessionToken("SESSION_TOKEN");
+Assert(credentialsOptions.GetSessionToken() == "SESSION_TOKEN", "session_token is set");
+using var credentials = AwsCredentials.New(credentialsOptions);
+Assert(Encoding.UTF8.GetString(credentials.GetSessionToken()) == "SESSION_TOKEN", "credentials are created from the options");
+using var fromKeys = AwsCredentials.NewFromKeys("ACCESS_KEY", "SECRET_ACCESS_KEY", null, 0);
+Assert(fromKeys.GetSecretAccessKey().Length == 17, "credentials are created from keys");
+
+try
+{
+    AwsCredentials.NewFromKeys(null!, "SECRET_ACCESS_KEY", null, 0);
+    Assert(false, "NULL strings must be thrown");
+}
+catch (CrtException err)
+{
+    Assert(err.Code == 34, err.Message);
+    Assert(err.Name == "AWS_ERROR_INVALID_ARGUMENT", err.Message);
+}
+
+Library.CleanUp();
+"#;
+
+#[test]
+#[ignore = "needs the .NET SDK, run with cargo test -- --ignored"]
+fn csharp_bindings() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output_dir = std::env::temp_dir().join(format!("crtrs_csharp_{}", std::process::id()));
+    let config = Config::new("crtrs", &root.join("src/lib.rs"), &output_dir).generators("csharp");
+    crt_bindgen::generate_with(&config, &Registry::default()).unwrap();
+    std::fs::write(output_dir.join("Smoke.csproj"), PROJECT).unwrap();
+    std::fs::write(output_dir.join("Program.cs"), PROGRAM.replace("VERSION", &format!("{:?}", env!("CARGO_PKG_VERSION")))).unwrap();
+
+    // DllImport finds the cdylib through LD_LIBRARY_PATH on Linux
+    let library_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let dotnet = std::env::var("DOTNET").unwrap_or_else(|_| String::from("dotnet"));
+    let output = Command::new(&dotnet)
+        .args(["run", "--project"])
+        .arg(&output_dir)
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DOTNET_CLI_TELEMETRY_OPTOUT", "1")
+        .env("DOTNET_NOLOGO", "1")
+        .output()
+        .unwrap_or_else(|err| panic!("Failed to run {}: {}", dotnet, err));
+    std::fs::remove_dir_all(&output_dir).unwrap();
+    assert!(output.status.success(), "{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
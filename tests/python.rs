@@ -0,0 +1,87 @@
+use std::path::Path;
+use std::process::Command;
+
+use crt_bindgen::plugin::Registry;
+use crt_bindgen::Config;
+
+// Runs with the bindings from the python generator, against the cdylib which cargo builds next to
+// the integration tests
+const SCRIPT: &str = r#"
+import crtrs
+
+crtrs.init()
+assert crtrs.version() == VERSION, crtrs.version()
+assert crtrs.error_name(0) == b"AWS_ERROR_SUCCESS"
+
+with crtrs.EventLoopGroupOptions.new() as options:
+    options.set_num_threads(2)
+    assert options.get_num_threads() == 2
+    copy = options.clone()
+    assert copy == options and copy is not options
+    assert "num_threads: 2" in repr(copy)
+    copy.close()
+    with crtrs.EventLoopGroup.new(options) as elg:
+        second = elg.acquire()
+    second.close()
+    crtrs.EventLoopGroup.new().close()
+    shutdowns = []
+    with crtrs.EventLoopGroup.new_with_shutdown_callback(options, lambda: shutdowns.append(True)) as elg:
+        second = elg.acquire()
+    assert shutdowns == []
+    second.close()
+    assert shutdowns == [True]
+    assert crtrs._callbacks == {}
+try:
+    options.get_num_threads()
+    raise AssertionError("closed handles must not be used")
+except ValueError:
+    pass
+
+options = crtrs.AwsCredentialsOptions.new()
+options.set_access_key_id("ACCESS_KEY")
+options.set_secret_access_key("SECRET_ACCESS_KEY")
+assert options.get_access_key_id() == "ACCESS_KEY"
+assert options.get_session_token() is None
+options.set_session_token("SESSION_TOKEN")
+assert options.get_session_token() == "SESSION_TOKEN"
+credentials = crtrs.AwsCredentials.new(options)
+del options
+assert credentials.get_access_key_id() == b"ACCESS_KEY"
+assert credentials.get_session_token() == b"SESSION_TOKEN"
+credentials = crtrs.AwsCredentials.new_from_keys("ACCESS_KEY", "SECRET_ACCESS_KEY", None, 0)
+assert credentials.get_secret_access_key() == b"SECRET_ACCESS_KEY"
+
+try:
+    crtrs.AwsCredentials.new_from_keys(b"\xff", "SECRET_ACCESS_KEY", None, 0)
+    raise AssertionError("invalid UTF-8 must be raised")
+except crtrs.Error as err:
+    assert err.code == 36, err.code
+    assert isinstance(err.name, str)
+try:
+    crtrs.EventLoopGroup.new(credentials)
+    raise AssertionError("handles of the wrong struct must be rejected")
+except TypeError:
+    pass
+
+crtrs.clean_up()
+"#;
+
+#[test]
+fn python_bindings() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output_dir = std::env::temp_dir().join(format!("crtrs_python_{}", std::process::id()));
+    let config = Config::new("crtrs", &root.join("src/lib.rs"), &output_dir).generators("python");
+    crt_bindgen::generate_with(&config, &Registry::default()).unwrap();
+
+    let exe = std::env::current_exe().unwrap();
+    let library = exe.parent().unwrap().join(format!("{}crtrs{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX));
+    let python = std::env::var("PYTHON").unwrap_or_else(|_| String::from("python3"));
+    let output = Command::new(&python)
+        .args(["-W", "error", "-c", &SCRIPT.replace("VERSION", &format!("{:?}", env!("CARGO_PKG_VERSION")))])
+        .env("PYTHONPATH", &output_dir)
+        .env("CRTRS_LIBRARY", &library)
+        .output()
+        .unwrap_or_else(|err| panic!("Failed to run {}: {}", python, err));
+    std::fs::remove_dir_all(&output_dir).unwrap();
+    assert!(output.status.success(), "{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}